@@ -1,16 +1,281 @@
-use criterion::{black_box, criterion_group, criterion_main, Criterion};
+//! Ring workloads, run on every backend available: the emulated ring everywhere, and the kernel's
+//! on Windows 11 22H2 and later.
+//!
+//! Benchmarks are named `<workload>/<backend>[/<variant>]/<parameter>`, with the same parameters
+//! and throughput unit on every backend, so their reports can be compared side by side. Files are
+//! read from and written to the temporary directory, and stay in the page cache after the first
+//! pass: the reads measure the ring, not the disk.
 
-fn fibonacci(n: u64) -> u64 {
-    match n {
-        0 => 1,
-        1 => 1,
-        n => fibonacci(n - 1) + fibonacci(n - 2),
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use std::{fs, process};
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use ioring_rs::fs::{raw_handle, CopyOptions};
+use ioring_rs::windows::{
+    IORING_BUFFER_INFO, IORING_REGISTERED_BUFFER, NT_IORING_BUFFERREF, NT_IORING_HANDLEREF,
+    _NT_IORING_OP_FLAGS_NT_IORING_OP_FLAG_NONE,
+    _NT_IORING_OP_FLAGS_NT_IORING_OP_FLAG_REGISTERED_BUFFER,
+    _NT_IORING_OP_FLAGS_NT_IORING_OP_FLAG_REGISTERED_FILE,
+};
+use ioring_rs::{opcode, Builder, IoRing};
+
+const BATCHES: [u32; 4] = [1, 8, 32, 128];
+const DEPTHS: [u32; 2] = [1, 32];
+const BLOCK: usize = 4096;
+const READ_FILE_SIZE: usize = 16 << 20;
+const COPY_FILE_SIZE: usize = 8 << 20;
+const COPY_CHUNKS: [u32; 3] = [4 << 10, 64 << 10, 1 << 20];
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Backend {
+    Native,
+    Emulated,
+}
+
+impl Backend {
+    /// The backends that can create a ring here.
+    fn available() -> Vec<Backend> {
+        let mut backends = Vec::new();
+        if IoRing::new(1).is_ok() {
+            backends.push(Backend::Native);
+        }
+        backends.push(Backend::Emulated);
+        backends
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Backend::Native => "native",
+            Backend::Emulated => "emulated",
+        }
+    }
+
+    fn builder(self, entries: u32) -> Builder {
+        Builder::new(entries).emulated(self == Backend::Emulated)
+    }
+}
+
+/// A file in the temporary directory, removed on drop.
+struct TempFile(PathBuf);
+
+impl TempFile {
+    fn new(name: &str, size: usize) -> TempFile {
+        let path = std::env::temp_dir().join(format!("ioring-bench-{}-{}", process::id(), name));
+        let mut rng = XorShift(0x9e37_79b9_7f4a_7c15);
+        let data: Vec<u8> = (0..size).map(|_| rng.next() as u8).collect();
+        fs::write(&path, data).unwrap();
+        TempFile(path)
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+/// A fixed sequence of pseudo-random numbers, so every backend reads the same blocks.
+struct XorShift(u64);
+
+impl XorShift {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+}
+
+/// Push `count` NOPs to `ring`.
+fn push_nops(ring: &mut IoRing, count: u32) {
+    let raw = ring.info.0;
+    let mut sq = ring.submission();
+    for user_data in 0..count {
+        let nop = opcode::Nop::new(raw).build().user_data(user_data as u64);
+        unsafe { sq.push(&nop).expect("submission queue is full") };
+    }
+}
+
+/// Push a read of a random block of `file` into each of `buffers`, by handle and address, or
+/// through the file and buffer tables the ring was built with if `registered`.
+fn push_reads(
+    ring: &mut IoRing,
+    file: &fs::File,
+    buffers: &[IORING_BUFFER_INFO],
+    registered: bool,
+    rng: &mut XorShift,
+) {
+    let raw = ring.info.0;
+    let mut sq = ring.submission();
+    for (index, info) in buffers.iter().enumerate() {
+        let offset = rng.next() % (READ_FILE_SIZE / BLOCK) as u64 * BLOCK as u64;
+        let (file, buffer, flags) = if registered {
+            (
+                NT_IORING_HANDLEREF { HandleIndex: 0 },
+                NT_IORING_BUFFERREF {
+                    FixedBuffer: IORING_REGISTERED_BUFFER {
+                        BufferIndex: index as u32,
+                        Offset: 0,
+                    },
+                },
+                _NT_IORING_OP_FLAGS_NT_IORING_OP_FLAG_REGISTERED_FILE
+                    | _NT_IORING_OP_FLAGS_NT_IORING_OP_FLAG_REGISTERED_BUFFER,
+            )
+        } else {
+            (
+                NT_IORING_HANDLEREF {
+                    Handle: raw_handle(file) as _,
+                },
+                NT_IORING_BUFFERREF {
+                    Address: info.Address as _,
+                },
+                _NT_IORING_OP_FLAGS_NT_IORING_OP_FLAG_NONE,
+            )
+        };
+        let read = opcode::Read::new(raw, file, buffer, BLOCK as u32, offset, flags)
+            .build()
+            .user_data(index as u64);
+        unsafe { sq.push(&read).expect("submission queue is full") };
+    }
+}
+
+/// Reap every completion available, checking that there are `count` of them and that they
+/// succeeded.
+fn drain(ring: &mut IoRing, count: u32) {
+    let mut reaped = 0;
+    for cqe in ring.completion() {
+        black_box(cqe.into_result().unwrap());
+        reaped += 1;
+    }
+    assert_eq!(reaped, count);
+}
+
+fn nop(c: &mut Criterion) {
+    let mut group = c.benchmark_group("nop");
+    for backend in Backend::available() {
+        for batch in BATCHES {
+            let mut ring = backend.builder(batch).build().unwrap();
+            group.throughput(Throughput::Elements(batch as u64));
+            group.bench_function(BenchmarkId::new(backend.name(), batch), |b| {
+                b.iter(|| {
+                    push_nops(&mut ring, batch);
+                    ring.submit_and_wait(batch).unwrap();
+                    drain(&mut ring, batch);
+                })
+            });
+        }
     }
+    group.finish();
 }
 
-fn criterion_benchmark(c: &mut Criterion) {
-    c.bench_function("fib 20", |b| b.iter(|| fibonacci(black_box(20))));
+/// Time the two halves of a NOP batch apart: handing it to the backend and waiting for it to
+/// complete, then reaping the completions.
+fn submit_drain(c: &mut Criterion) {
+    let mut group = c.benchmark_group("submit_drain");
+    for backend in Backend::available() {
+        for batch in BATCHES {
+            let mut ring = backend.builder(batch).build().unwrap();
+            group.throughput(Throughput::Elements(batch as u64));
+            let id = BenchmarkId::new(format!("{}/submit", backend.name()), batch);
+            group.bench_function(id, |b| {
+                b.iter_custom(|iters| {
+                    let mut elapsed = Duration::ZERO;
+                    for _ in 0..iters {
+                        push_nops(&mut ring, batch);
+                        let start = Instant::now();
+                        ring.submit_and_wait(batch).unwrap();
+                        elapsed += start.elapsed();
+                        drain(&mut ring, batch);
+                    }
+                    elapsed
+                })
+            });
+            let id = BenchmarkId::new(format!("{}/drain", backend.name()), batch);
+            group.bench_function(id, |b| {
+                b.iter_custom(|iters| {
+                    let mut elapsed = Duration::ZERO;
+                    for _ in 0..iters {
+                        push_nops(&mut ring, batch);
+                        ring.submit_and_wait(batch).unwrap();
+                        let start = Instant::now();
+                        drain(&mut ring, batch);
+                        elapsed += start.elapsed();
+                    }
+                    elapsed
+                })
+            });
+        }
+    }
+    group.finish();
+}
+
+/// Read `depth` random 4 KiB blocks at once, by handle and address or through the registered
+/// file and buffer tables.
+fn random_read(c: &mut Criterion) {
+    let data = TempFile::new("read", READ_FILE_SIZE);
+    let file = fs::File::open(&data.0).unwrap();
+
+    let mut group = c.benchmark_group("read_4k");
+    for backend in Backend::available() {
+        for registered in [false, true] {
+            for depth in DEPTHS {
+                let mut buffers = vec![0u8; depth as usize * BLOCK];
+                let infos: Vec<IORING_BUFFER_INFO> = buffers
+                    .chunks_exact_mut(BLOCK)
+                    .map(|buf| IORING_BUFFER_INFO {
+                        Address: buf.as_mut_ptr() as _,
+                        Length: BLOCK as u32,
+                    })
+                    .collect();
+                let mut builder = backend.builder(depth);
+                if registered {
+                    // Safety: `buffers` outlives the ring, and each one is used by one read at
+                    // a time.
+                    builder = unsafe { builder.files(&[raw_handle(&file)]).buffers(&infos) };
+                }
+                let mut ring = builder.build().unwrap();
+                let mut rng = XorShift(depth as u64);
+
+                let variant = if registered { "registered" } else { "plain" };
+                let id = BenchmarkId::new(format!("{}/{}", backend.name(), variant), depth);
+                group.throughput(Throughput::Bytes(depth as u64 * BLOCK as u64));
+                group.bench_function(id, |b| {
+                    b.iter(|| {
+                        push_reads(&mut ring, &file, &infos, registered, &mut rng);
+                        ring.submit_and_wait(depth).unwrap();
+                        drain(&mut ring, depth);
+                    })
+                });
+                // The ring has to go before the buffers it registered.
+                drop(ring);
+                drop(buffers);
+            }
+        }
+    }
+    group.finish();
+}
+
+/// Copy a whole file with [`ioring_rs::fs::copy`], for several chunk sizes.
+fn copy(c: &mut Criterion) {
+    let src = TempFile::new("copy-src", COPY_FILE_SIZE);
+    let dst = TempFile(src.0.with_extension("dst"));
+
+    let mut group = c.benchmark_group("copy");
+    group.sample_size(20);
+    group.throughput(Throughput::Bytes(COPY_FILE_SIZE as u64));
+    for backend in Backend::available() {
+        for chunk_size in COPY_CHUNKS {
+            let options = CopyOptions::new()
+                .chunk_size(chunk_size)
+                .emulated(backend == Backend::Emulated);
+            group.bench_function(BenchmarkId::new(backend.name(), chunk_size), |b| {
+                b.iter(|| ioring_rs::fs::copy(&src.0, &dst.0, options.clone()).unwrap())
+            });
+        }
+    }
+    group.finish();
 }
 
-criterion_group!(benches, criterion_benchmark);
+criterion_group!(benches, nop, submit_drain, random_read, copy);
 criterion_main!(benches);
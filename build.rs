@@ -1,6 +1,10 @@
 use cmake::Config;
 
 fn main() {
+    // Off Windows only the emulated ring is available, and libwinring is replaced by Rust.
+    if std::env::var("CARGO_CFG_TARGET_OS").as_deref() != Ok("windows") {
+        return;
+    }
     //     use std::env;
     //     use std::path::PathBuf;
     //     const INCLUDE: &str = r#"
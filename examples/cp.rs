@@ -1 +1,42 @@
+use ioring_rs::fs::{self, CopyOptions};
+use std::{env, io, process};
 
+const USAGE: &str =
+    "usage: cp [--chunk-size BYTES] [--window N] [--no-register] [--sync] [--emulated] SRC DST";
+
+fn parse<T: std::str::FromStr>(value: Option<String>) -> T {
+    value.and_then(|v| v.parse().ok()).unwrap_or_else(|| {
+        eprintln!("{}", USAGE);
+        process::exit(2);
+    })
+}
+
+fn main() -> io::Result<()> {
+    let mut options = CopyOptions::new();
+    let mut paths = Vec::new();
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--chunk-size" => options = options.chunk_size(parse(args.next())),
+            "--window" => options = options.window(parse(args.next())),
+            "--no-register" => options = options.register(false),
+            "--sync" => options = options.flush(true),
+            "--emulated" => options = options.emulated(true),
+            _ => paths.push(arg),
+        }
+    }
+    if paths.len() != 2 {
+        eprintln!("{}", USAGE);
+        process::exit(2);
+    }
+
+    let stats = fs::copy(&paths[0], &paths[1], options)?;
+    println!(
+        "copied {} bytes in {:?} ({:.2} MiB/s)",
+        stats.bytes,
+        stats.elapsed,
+        stats.throughput() / (1024.0 * 1024.0)
+    );
+    Ok(())
+}
@@ -1,8 +1,13 @@
+#[cfg(windows)]
 use ioring_rs::{opcode, IoRing};
-use std::{fs, io, os::windows::prelude::AsRawHandle, ptr};
+#[cfg(windows)]
+use std::{io, ptr};
+#[cfg(windows)]
 use windows::Win32::Foundation::HANDLE;
+#[cfg(windows)]
 use windows::Win32::System::Threading::{CreateEventA, WaitForSingleObject};
 
+#[cfg(windows)]
 fn main() -> io::Result<()> {
     let mut ring = IoRing::new(32)?;
     let event: HANDLE = unsafe {
@@ -14,7 +19,7 @@ fn main() -> io::Result<()> {
         )
         .map_err(|e| {
             let description = format!("Failed to create event {}", e);
-            io::Error::new(io::ErrorKind::Other, description)
+            io::Error::other(description)
         })?
     };
     let entry_reg_event = opcode::RegisterEvents::new(ring.info.0, event.0 as *mut _).build();
@@ -28,18 +33,20 @@ fn main() -> io::Result<()> {
     ring.submit_and_wait(1)?;
     unsafe {
         if WaitForSingleObject(event, u32::MAX) != 0 {
-            return Err(io::Error::new(
-                io::ErrorKind::Other,
-                "failed to wait for event",
-            ));
+            return Err(io::Error::other("failed to wait for event"));
         }
         match ring.peek_cqe() {
             Some(_) => {}
             None => {
-                return Err(io::Error::new(io::ErrorKind::Other, "no cqe"));
+                return Err(io::Error::other("no cqe"));
             }
         };
     };
 
     Ok(())
 }
+
+#[cfg(not(windows))]
+fn main() {
+    eprintln!("this example only runs on Windows");
+}
@@ -1,3 +1,4 @@
+#[cfg(windows)]
 use ioring_rs::windows::{
     win_ring, win_ring_cq_clear, win_ring_cqe_iter, win_ring_get_sqe, win_ring_prep_cancel,
     win_ring_prep_read, win_ring_queue_exit, win_ring_queue_init, win_ring_sqe_set_data64,
@@ -5,6 +6,7 @@ use ioring_rs::windows::{
     _NT_IORING_OP_FLAGS_NT_IORING_OP_FLAG_NONE,
     _NT_IORING_REG_FILES_REQ_FLAGS_NT_IORING_REG_FILES_REQ_FLAG_NONE,
 };
+#[cfg(windows)]
 use std::{
     io::{self, Write},
     os::windows::prelude::AsRawHandle,
@@ -12,6 +14,7 @@ use std::{
     time::Duration,
 };
 // use os_pipe::pipe;
+#[cfg(windows)]
 unsafe fn clear_cqes(ring: *mut win_ring, string: &str) -> io::Result<()> {
     win_ring_submit_and_wait(ring, u32::MAX);
     for i in (*(*ring).info.__bindgen_anon_2.CompletionQueue).Head
@@ -30,6 +33,7 @@ unsafe fn clear_cqes(ring: *mut win_ring, string: &str) -> io::Result<()> {
 
     Ok(())
 }
+#[cfg(windows)]
 fn main() -> std::io::Result<()> {
     unsafe {
         let (read_pipe, mut write_pipe) = os_pipe::pipe()?;
@@ -38,13 +42,13 @@ fn main() -> std::io::Result<()> {
         let thread_join_handle = thread::spawn(move || {
             thread::sleep(Duration::from_secs(2));
             println!("Writing pipe!");
-            write_pipe.write("Test Pipe".as_bytes()).unwrap();
+            write_pipe.write_all("Test Pipe".as_bytes()).unwrap();
             drop(write_pipe);
         });
 
         win_ring_queue_init(32, &mut ring);
         let mut str = [0u8; 128];
-        let mut ring_sqe = win_ring_get_sqe(&mut ring);
+        let mut ring_sqe = win_ring_get_sqe(&ring);
 
         win_ring_prep_read(
             ring_sqe,
@@ -61,7 +65,7 @@ fn main() -> std::io::Result<()> {
         win_ring_sqe_set_data64(ring_sqe, 10);
         win_ring_submit(&mut ring);
 
-        ring_sqe = win_ring_get_sqe(&mut ring);
+        ring_sqe = win_ring_get_sqe(&ring);
         win_ring_prep_read(
             ring_sqe,
             _NT_IORING_HANDLEREF {
@@ -77,7 +81,7 @@ fn main() -> std::io::Result<()> {
         win_ring_sqe_set_data64(ring_sqe, 20);
         win_ring_submit(&mut ring);
 
-        ring_sqe = win_ring_get_sqe(&mut ring);
+        ring_sqe = win_ring_get_sqe(&ring);
         win_ring_prep_cancel(
             ring_sqe,
             _NT_IORING_HANDLEREF {
@@ -90,11 +94,15 @@ fn main() -> std::io::Result<()> {
         win_ring_submit(&mut ring);
 
         clear_cqes(&mut ring, "read")?;
-        thread_join_handle.join().map_err(|_| {
-            let description = format!("Failed to join write thread");
-            io::Error::new(io::ErrorKind::Other, description)
-        })?;
+        thread_join_handle
+            .join()
+            .map_err(|_| io::Error::other("Failed to join write thread"))?;
         win_ring_queue_exit(&mut ring);
         Ok(())
     }
 }
+
+#[cfg(not(windows))]
+fn main() {
+    eprintln!("this example only runs on Windows");
+}
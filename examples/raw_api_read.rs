@@ -1,3 +1,4 @@
+#[cfg(windows)]
 use ioring_rs::windows::{
     win_ring, win_ring_cq_clear, win_ring_cqe_iter, win_ring_get_sqe, win_ring_prep_read,
     win_ring_prep_register_buffers, win_ring_prep_register_files, win_ring_queue_exit,
@@ -8,8 +9,10 @@ use ioring_rs::windows::{
     _NT_IORING_OP_FLAGS_NT_IORING_OP_FLAG_REGISTERED_FILE,
     _NT_IORING_REG_FILES_REQ_FLAGS_NT_IORING_REG_FILES_REQ_FLAG_NONE,
 };
+#[cfg(windows)]
 use std::{fs, io, os::windows::prelude::AsRawHandle};
 
+#[cfg(windows)]
 unsafe fn clear_cqes(ring: *mut win_ring, string: &str) -> io::Result<()> {
     win_ring_submit_and_wait(ring, u32::MAX);
     for i in (*(*ring).info.__bindgen_anon_2.CompletionQueue).Head
@@ -28,6 +31,7 @@ unsafe fn clear_cqes(ring: *mut win_ring, string: &str) -> io::Result<()> {
 
     Ok(())
 }
+#[cfg(windows)]
 fn main() -> std::io::Result<()> {
     unsafe {
         // dbg!(std::mem::sizeof<_NT_IORING_SQ_FLAGS>());
@@ -40,7 +44,7 @@ fn main() -> std::io::Result<()> {
             Address: buf4fixed.as_mut_ptr() as *mut _,
             Length: 32,
         };
-        let ring_sqe = win_ring_get_sqe(&mut ring);
+        let ring_sqe = win_ring_get_sqe(&ring);
         win_ring_prep_register_buffers(
             ring_sqe,
             &buffer_info,
@@ -52,7 +56,7 @@ fn main() -> std::io::Result<()> {
         win_ring_submit_and_wait(&mut ring, 1);
         clear_cqes(&mut ring, "register buffr")?;
 
-        let mut ring_sqe = win_ring_get_sqe(&mut ring);
+        let mut ring_sqe = win_ring_get_sqe(&ring);
         win_ring_prep_register_files(
             ring_sqe,
             f.as_raw_handle() as _,
@@ -65,7 +69,7 @@ fn main() -> std::io::Result<()> {
         clear_cqes(&mut ring, "register file")?;
 
         for x in 0..2 {
-            ring_sqe = win_ring_get_sqe(&mut ring);
+            ring_sqe = win_ring_get_sqe(&ring);
             if (x & 1) != 0 {
                 let read_param = _NT_IORING_BUFFERREF {
                     FixedBuffer: IORING_REGISTERED_BUFFER {
@@ -108,3 +112,8 @@ fn main() -> std::io::Result<()> {
         Ok(())
     }
 }
+
+#[cfg(not(windows))]
+fn main() {
+    eprintln!("this example only runs on Windows");
+}
@@ -2,8 +2,8 @@ use ioring_rs::windows::{
     _NT_IORING_BUFFERREF, _NT_IORING_HANDLEREF, _NT_IORING_OP_FLAGS_NT_IORING_OP_FLAG_NONE,
     _NT_IORING_REG_BUFFERS_FLAGS, _NT_IORING_REG_FILES_FLAGS,
 };
-use ioring_rs::{opcode, IoRing};
-use std::{fs, io, os::windows::prelude::AsRawHandle};
+use ioring_rs::{fs::raw_handle, opcode, IoRing};
+use std::{fs, io};
 
 fn main() -> io::Result<()> {
     let f = fs::File::open("README.md")?;
@@ -13,7 +13,7 @@ fn main() -> io::Result<()> {
 
     let entry_reg_file = opcode::RegisterFiles::new(
         ring.info.0,
-        f.as_raw_handle() as _,
+        raw_handle(&f) as _,
         1,
         _NT_IORING_REG_FILES_FLAGS {
             Required: 0,
@@ -62,7 +62,7 @@ fn main() -> io::Result<()> {
     let entry_read = opcode::Read::new(
         ring.info.0,
         _NT_IORING_HANDLEREF {
-            Handle: f.as_raw_handle() as _,
+            Handle: raw_handle(&f) as _,
         },
         _NT_IORING_BUFFERREF {
             Address: buf.as_mut_ptr() as _,
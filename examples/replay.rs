@@ -0,0 +1,88 @@
+use ioring_rs::record::Replay;
+use ioring_rs::{fs, IoRing};
+use std::fs::{File, OpenOptions};
+use std::io::BufReader;
+use std::time::Duration;
+use std::{env, io, process};
+
+const USAGE: &str = "usage: replay [--native] [--writes] [--timeout MS] TRACE [HANDLE=PATH]...\n\n\
+     Replays the operations recorded in TRACE, with the file that had HANDLE (as shown in the\n\
+     trace, e.g. 0x1c) opened from PATH. Writes are skipped unless --writes is given, and then\n\
+     replayed with zeroed buffers.";
+
+fn usage() -> ! {
+    eprintln!("{}", USAGE);
+    process::exit(2);
+}
+
+fn handle(value: &str) -> u64 {
+    let parsed = match value.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => value.parse(),
+    };
+    parsed.unwrap_or_else(|_| usage())
+}
+
+fn main() -> io::Result<()> {
+    let mut native = false;
+    let mut writes = false;
+    let mut timeout = None;
+    let mut trace = None;
+    let mut files = Vec::new();
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--native" => native = true,
+            "--writes" => writes = true,
+            "--timeout" => {
+                let ms = args.next().and_then(|ms| ms.parse().ok());
+                timeout = Some(Duration::from_millis(ms.unwrap_or_else(|| usage())));
+            }
+            _ => match arg.split_once('=') {
+                Some((recorded, path)) => files.push((handle(recorded), path.to_owned())),
+                None if trace.is_none() => trace = Some(arg),
+                None => usage(),
+            },
+        }
+    }
+    let trace = trace.unwrap_or_else(|| usage());
+
+    let mut replay = Replay::read(BufReader::new(File::open(trace)?))?.writes(writes);
+    if let Some(timeout) = timeout {
+        replay = replay.timeout(timeout);
+    }
+    // The files have to stay open until the replay is done.
+    let mut opened = Vec::new();
+    for (recorded, path) in files {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&path)
+            .or_else(|_| File::open(&path))?;
+        replay = replay.file(recorded, fs::raw_handle(&file));
+        opened.push(file);
+    }
+
+    let mut ring = if native {
+        IoRing::new(256)?
+    } else {
+        IoRing::emulated(256)?
+    };
+    let report = replay.run(&mut ring)?;
+    println!(
+        "replayed {} operations, skipped {}",
+        report.replayed,
+        report.skipped.len()
+    );
+    for mismatch in &report.mismatches {
+        println!(
+            "user_data {}: recorded {:?}, replayed {:?}",
+            mismatch.user_data, mismatch.recorded, mismatch.replayed
+        );
+    }
+    if !report.is_ok() {
+        process::exit(1);
+    }
+    Ok(())
+}
@@ -1,21 +1,25 @@
+// The echo server is not written yet.
+#![allow(dead_code, unused_imports, unused_variables)]
+
+use ioring_rs::windows::HANDLE;
 use ioring_rs::{opcode, squeue, IoRing};
 use slab::Slab;
 use std::collections::VecDeque;
+use std::io;
 use std::net::TcpListener;
-use std::{io, os::windows::prelude::RawHandle};
 
 #[derive(Clone, Debug)]
 enum Token {
     Accept,
     Poll {
-        fd: RawHandle,
+        fd: HANDLE,
     },
     Read {
-        fd: RawHandle,
+        fd: HANDLE,
         buf_index: usize,
     },
     Write {
-        fd: RawHandle,
+        fd: HANDLE,
         buf_index: usize,
         offset: usize,
         len: usize,
@@ -27,7 +31,7 @@ pub struct AcceptCount {
 }
 
 impl AcceptCount {
-    fn new(fd: RawHandle, token: usize, count: usize) -> AcceptCount {
+    fn new(fd: HANDLE, token: usize, count: usize) -> AcceptCount {
         todo!()
     }
 }
@@ -0,0 +1,27 @@
+//! Decodes arbitrary 24-byte completions, and checks that the typed result agrees with the raw
+//! fields and that formatting them does not panic.
+
+#![no_main]
+
+use std::mem;
+
+use ioring_rs::cqueue::Entry;
+use ioring_rs::windows::_NT_IORING_CQE;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: [u8; mem::size_of::<_NT_IORING_CQE>()]| {
+    // Every bit pattern is a valid completion: its fields are all integers.
+    let cqe: _NT_IORING_CQE = unsafe { mem::transmute(data) };
+    let entry = Entry::from(cqe);
+    match entry.into_result() {
+        Ok(information) => {
+            assert!(entry.result() >= 0);
+            assert_eq!(information, entry.information());
+        }
+        Err(error) => {
+            assert!(entry.result() < 0);
+            let _ = (error.kind(), error.raw_os_error(), error.to_string());
+        }
+    }
+    let _ = format!("{} {:?}", entry, entry);
+});
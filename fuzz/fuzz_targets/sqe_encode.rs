@@ -0,0 +1,258 @@
+//! Builds entries with the opcode builders from arbitrary operands, and checks that decoding the
+//! encoded `_NT_IORING_SQE` gives back the same operation, with the builder's `CODE`.
+
+#![no_main]
+
+use std::ffi::c_void;
+use std::{mem, ptr};
+
+use arbitrary::Arbitrary;
+use ioring_rs::opcode;
+use ioring_rs::squeue::{Buffer, Entry, File, Operation};
+use ioring_rs::windows::{
+    HANDLE, IORING_BUFFER_INFO, IORING_REGISTERED_BUFFER, NT_IORING_BUFFERREF,
+    NT_IORING_HANDLEREF, _NT_IORING_OP_FLAGS, _NT_IORING_OP_FLAGS_NT_IORING_OP_FLAG_NONE,
+    _NT_IORING_OP_FLAGS_NT_IORING_OP_FLAG_REGISTERED_BUFFER,
+    _NT_IORING_OP_FLAGS_NT_IORING_OP_FLAG_REGISTERED_FILE,
+};
+use libfuzzer_sys::fuzz_target;
+
+#[derive(Arbitrary, Debug)]
+enum FileRef {
+    Handle(u64),
+    Registered(u32),
+}
+
+#[derive(Arbitrary, Debug)]
+enum BufferRef {
+    Address(u64),
+    Registered { index: u32, offset: u32 },
+}
+
+#[derive(Arbitrary, Debug)]
+enum Op {
+    Nop {
+        result: i32,
+        information: u64,
+    },
+    Fence,
+    Read {
+        file: FileRef,
+        buffer: BufferRef,
+        offset: u64,
+        length: u32,
+    },
+    Write {
+        file: FileRef,
+        buffer: BufferRef,
+        offset: u64,
+        length: u32,
+        flags: i32,
+    },
+    Flush {
+        file: FileRef,
+        mode: i32,
+    },
+    Cancel {
+        file: FileRef,
+        cancel_id: u64,
+    },
+    RegisterFiles {
+        handles: usize,
+        count: u32,
+    },
+    RegisterBuffers {
+        buffers: usize,
+        count: u32,
+    },
+}
+
+impl FileRef {
+    fn encode(&self) -> (NT_IORING_HANDLEREF, _NT_IORING_OP_FLAGS, File) {
+        match *self {
+            FileRef::Handle(handle) => (
+                NT_IORING_HANDLEREF { Handle: handle },
+                _NT_IORING_OP_FLAGS_NT_IORING_OP_FLAG_NONE,
+                File::Handle(handle as HANDLE),
+            ),
+            FileRef::Registered(index) => (
+                NT_IORING_HANDLEREF {
+                    HandleIndex: index as u64,
+                },
+                _NT_IORING_OP_FLAGS_NT_IORING_OP_FLAG_REGISTERED_FILE,
+                File::Registered(index),
+            ),
+        }
+    }
+}
+
+impl BufferRef {
+    fn encode(&self) -> (NT_IORING_BUFFERREF, _NT_IORING_OP_FLAGS, Buffer) {
+        match *self {
+            BufferRef::Address(address) => (
+                NT_IORING_BUFFERREF { Address: address },
+                _NT_IORING_OP_FLAGS_NT_IORING_OP_FLAG_NONE,
+                Buffer::Address(address as *mut c_void),
+            ),
+            BufferRef::Registered { index, offset } => (
+                NT_IORING_BUFFERREF {
+                    FixedBuffer: IORING_REGISTERED_BUFFER {
+                        BufferIndex: index,
+                        Offset: offset,
+                    },
+                },
+                _NT_IORING_OP_FLAGS_NT_IORING_OP_FLAG_REGISTERED_BUFFER,
+                Buffer::Registered { index, offset },
+            ),
+        }
+    }
+}
+
+/// Build the entry of `op` without a ring, with the `CODE` of its builder and the operation it
+/// should decode to.
+fn build(op: &Op) -> (Entry, i32, Operation) {
+    let ring = ptr::null_mut();
+    match *op {
+        Op::Nop {
+            result,
+            information,
+        } => (
+            opcode::Nop::new(ring)
+                .result(result)
+                .information(information)
+                .build(),
+            opcode::Nop::CODE,
+            Operation::Nop {
+                result,
+                information,
+            },
+        ),
+        Op::Fence => (
+            opcode::Fence::new(ring).build(),
+            opcode::Fence::CODE,
+            Operation::Nop {
+                result: 0,
+                information: 0,
+            },
+        ),
+        Op::Read {
+            ref file,
+            ref buffer,
+            offset,
+            length,
+        } => {
+            let (file_ref, file_flags, file) = file.encode();
+            let (buffer_ref, buffer_flags, buffer) = buffer.encode();
+            let read = opcode::Read::new(
+                ring,
+                file_ref,
+                buffer_ref,
+                length,
+                offset,
+                file_flags | buffer_flags,
+            );
+            let operation = Operation::Read {
+                file,
+                buffer,
+                offset,
+                length,
+            };
+            (read.build(), opcode::Read::CODE, operation)
+        }
+        Op::Write {
+            ref file,
+            ref buffer,
+            offset,
+            length,
+            flags,
+        } => {
+            let (file_ref, file_flags, file) = file.encode();
+            let (buffer_ref, buffer_flags, buffer) = buffer.encode();
+            let write = opcode::Write::new(
+                ring,
+                file_ref,
+                buffer_ref,
+                length,
+                offset,
+                file_flags | buffer_flags,
+            )
+            .flags(flags);
+            let operation = Operation::Write {
+                file,
+                buffer,
+                offset,
+                length,
+                flags,
+            };
+            (write.build(), opcode::Write::CODE, operation)
+        }
+        Op::Flush { ref file, mode } => {
+            let (file_ref, flags, file) = file.encode();
+            let flush = opcode::Flush::new(ring, file_ref, mode, flags);
+            (
+                flush.build(),
+                opcode::Flush::CODE,
+                Operation::Flush { file, mode },
+            )
+        }
+        Op::Cancel {
+            ref file,
+            cancel_id,
+        } => {
+            let (file_ref, flags, file) = file.encode();
+            let cancel = opcode::Cancel::new(ring, file_ref, flags).cancel_id(cancel_id);
+            (
+                cancel.build(),
+                opcode::Cancel::CODE,
+                Operation::Cancel { file, cancel_id },
+            )
+        }
+        Op::RegisterFiles { handles, count } => {
+            let handles = handles as *const HANDLE;
+            let register = opcode::RegisterFiles::new(
+                ring,
+                handles,
+                count,
+                unsafe { mem::zeroed() },
+                _NT_IORING_OP_FLAGS_NT_IORING_OP_FLAG_NONE,
+            );
+            (
+                register.build(),
+                opcode::RegisterFiles::CODE,
+                Operation::RegisterFiles { handles, count },
+            )
+        }
+        Op::RegisterBuffers { buffers, count } => {
+            let buffers = buffers as *const IORING_BUFFER_INFO;
+            let register = opcode::RegisterBuffers::new(
+                ring,
+                buffers,
+                count,
+                unsafe { mem::zeroed() },
+                _NT_IORING_OP_FLAGS_NT_IORING_OP_FLAG_NONE,
+            );
+            (
+                register.build(),
+                opcode::RegisterBuffers::CODE,
+                Operation::RegisterBuffers { buffers, count },
+            )
+        }
+    }
+}
+
+fuzz_target!(|input: (Op, u64)| {
+    let (op, user_data) = input;
+    let (entry, code, expected) = build(&op);
+    let entry = entry.user_data(user_data);
+    let decoded = entry.operation();
+    assert_eq!(decoded, expected, "{:?}", op);
+    assert_eq!(decoded.code(), code, "{:?}", op);
+    // Formatting decodes the entry once more, and must not panic on any operand.
+    let shown = entry.to_string();
+    assert!(
+        shown.contains(&format!("user_data={}", user_data)),
+        "{}",
+        shown
+    );
+    let _ = format!("{:?}", entry);
+});
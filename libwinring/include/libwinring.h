@@ -10,6 +10,9 @@ extern "C" {
 typedef struct win_ring {
   NT_IORING_INFO info;
   HANDLE handle;
+  // Tail of the entries handed out by win_ring_get_sqe. They are published to
+  // the kernel by win_ring_sq_flush, once they are filled.
+  uint32_t sqe_tail;
 } win_ring;
 
 typedef NT_IORING_SQE win_ring_sqe;
@@ -32,6 +35,7 @@ HRESULT win_ring_queue_init(_In_ uint32_t entries,
   NTSTATUS status =
       NtCreateIoRing(&ring->handle, sizeof(ioringStruct), &ioringStruct,
                      sizeof(ring->info), &ring->info);
+  ring->sqe_tail = 0;
   return HRESULT_FROM_NT(status);
 };
 
@@ -48,6 +52,7 @@ struct win_ring *win_ring_queue_init_ref(_In_ uint32_t entries) {
   NTSTATUS status =
       NtCreateIoRing(&ring->handle, sizeof(ioringStruct), &ioringStruct,
                      sizeof(ring->info), &ring->info);
+  ring->sqe_tail = 0;
   return ring;
 };
 
@@ -163,28 +168,54 @@ void win_ring_sqe_set_data64(_Inout_ win_ring_sqe *sqe,
   sqe->UserData = userData;
 }
 
+// The head and tail of each queue are shared with the kernel. The side that
+// owns an index publishes it with release semantics after filling or reading
+// the slots below it, and the other side loads it with acquire semantics before
+// touching them. See src/ring.rs in the Rust crate.
+uint32_t win_ring_load_acquire(_In_ const volatile uint32_t *index) {
+  return (uint32_t)ReadULongAcquire((const volatile DWORD *)index);
+}
+
+void win_ring_store_release(_Out_ volatile uint32_t *index,
+                            _In_ uint32_t value) {
+  WriteULongRelease((volatile DWORD *)index, (DWORD)value);
+}
+
 unsigned win_ring_sq_ready(_In_ const win_ring *ring) {
-  return ring->info.SubmissionQueue->Tail - ring->info.SubmissionQueue->Head;
+  return win_ring_load_acquire(&ring->info.SubmissionQueue->Tail) -
+         win_ring_load_acquire(&ring->info.SubmissionQueue->Head);
 }
 
 unsigned win_ring_sq_space_left(_In_ const win_ring *ring) {
-  return ring->info.SubmissionQueueSize - win_ring_sq_ready(ring);
+  return ring->info.SubmissionQueueSize -
+         (ring->sqe_tail -
+          win_ring_load_acquire(&ring->info.SubmissionQueue->Head));
 }
 
+// Reserve the next entry of the submission queue. It is not visible to the
+// kernel until it is published by win_ring_sq_flush, which the submit
+// functions call.
 win_ring_sqe *win_ring_get_sqe(_Inout_ win_ring *ring) {
-  // Do we need atomic operations?
   if (!win_ring_sq_space_left(ring))
     return NULL;
   win_ring_sqe *sqe =
-      &ring->info.SubmissionQueue->Entries[ring->info.SubmissionQueue->Tail &
-                                           ring->info.SubmissionQueueRingMask];
-  ++ring->info.SubmissionQueue->Tail;
+      &ring->info.SubmissionQueue
+           ->Entries[ring->sqe_tail & ring->info.SubmissionQueueRingMask];
+  ring->sqe_tail++;
   return sqe;
 }
 
+// Publish the entries reserved so far, which must all be filled, and return the
+// number of entries waiting for the kernel.
+unsigned win_ring_sq_flush(_Inout_ win_ring *ring) {
+  win_ring_store_release(&ring->info.SubmissionQueue->Tail, ring->sqe_tail);
+  return win_ring_sq_ready(ring);
+}
+
 HRESULT win_ring_submit_and_wait_timeout(_Inout_ win_ring *ring,
                                          _In_ uint32_t numberOfEntries,
                                          _In_ uint64_t timeout) {
+  win_ring_sq_flush(ring);
   NTSTATUS status = NtSubmitIoRing(
       ring->handle, NT_IORING_CREATE_REQUIRED_FLAG_NONE, numberOfEntries,
       numberOfEntries == 0 || timeout == INFINITE ? NULL : &timeout);
@@ -200,11 +231,10 @@ HRESULT win_ring_submit(_Inout_ win_ring *ring) {
   return win_ring_submit_and_wait_timeout(ring, 0, 0);
 }
 
-// Do we need atomic operations?
 #define win_ring_for_each_cqe(ring, head, cqe)                                 \
   for (head = (ring)->info.CompletionQueue->Head;                              \
        (cqe =                                                                  \
-            head != (ring)->info.CompletionQueue->Tail                         \
+            head != win_ring_load_acquire(&(ring)->info.CompletionQueue->Tail) \
                 ? &(ring)                                                      \
                        ->info.CompletionQueue                                  \
                        ->Entries[head & (ring)->info.CompletionQueueRingMask]  \
@@ -212,7 +242,8 @@ HRESULT win_ring_submit(_Inout_ win_ring *ring) {
        ++head)
 
 unsigned win_ring_cq_ready(_In_ const win_ring *ring) {
-  return ring->info.CompletionQueue->Tail - ring->info.CompletionQueue->Head;
+  return win_ring_load_acquire(&ring->info.CompletionQueue->Tail) -
+         win_ring_load_acquire(&ring->info.CompletionQueue->Head);
 }
 
 unsigned win_ring_cq_space_left(_In_ const win_ring *ring) {
@@ -245,11 +276,14 @@ uint64_t win_ring_cqe_get_data64(_In_ const win_ring_cqe *cqe) {
 }
 
 void win_ring_cq_clear(_Inout_ win_ring *ring) {
-  ring->info.CompletionQueue->Head = ring->info.CompletionQueue->Tail;
+  win_ring_store_release(
+      &ring->info.CompletionQueue->Head,
+      win_ring_load_acquire(&ring->info.CompletionQueue->Tail));
 }
 
 void win_ring_cq_advance(_Inout_ win_ring *ring, _In_ unsigned count) {
-  ring->info.CompletionQueue->Head += count;
+  win_ring_store_release(&ring->info.CompletionQueue->Head,
+                         ring->info.CompletionQueue->Head + count);
 }
 
 void win_ring_cqe_seen(_Inout_ win_ring *ring, _In_ win_ring_cqe *cqe) {
@@ -0,0 +1,225 @@
+//! Allocation of `user_data` keys, and routing of completions back to their operation.
+
+use std::fmt::{self, Debug, Formatter};
+
+use slab::Slab;
+
+use crate::{cqueue, squeue};
+
+/// Keys stay below this bit, clear of the [values reserved](squeue::RESERVED_USER_DATA) by the
+/// crate near `u64::MAX`.
+const GENERATION_MASK: u32 = 0x7fff_ffff;
+
+/// A registry of the operations in flight, which hands out their `user_data` and keeps a context
+/// `T` for each of them until its completion comes back.
+///
+/// Keys are allocated from a [`Slab`]: a key is the slot's index plus one, so that it is never
+/// `0`, with a generation in the upper half, so that a key that has been completed is not
+/// mistaken for the next operation to reuse the slot.
+///
+/// # Examples
+///
+/// ```
+/// use ioring_rs::{completions::Completions, opcode, IoRing};
+///
+/// # fn main() -> std::io::Result<()> {
+/// let mut ring = IoRing::emulated(8)?;
+/// let mut completions = Completions::new();
+/// for name in ["first", "second"] {
+///     let nop = completions.attach(opcode::Nop::new(ring.info.0).build(), name);
+///     unsafe { ring.submission().push(&nop).expect("submission queue is full") };
+/// }
+/// ring.submit_and_wait(2)?;
+///
+/// let names: Vec<_> = completions.drain(ring.completion()).map(|(name, _)| name).collect();
+/// assert_eq!(names, ["first", "second"]);
+/// # Ok(())
+/// # }
+/// ```
+pub struct Completions<T> {
+    slab: Slab<(u32, T)>,
+    /// The generation given to the next operation.
+    generation: u32,
+}
+
+impl<T> Default for Completions<T> {
+    fn default() -> Self {
+        Completions::new()
+    }
+}
+
+impl<T> Completions<T> {
+    /// Create an empty registry, which does not allocate until an operation is registered.
+    pub fn new() -> Completions<T> {
+        Completions::with_capacity(0)
+    }
+
+    /// Create a registry with room for `capacity` operations before it allocates.
+    pub fn with_capacity(capacity: usize) -> Completions<T> {
+        Completions {
+            slab: Slab::with_capacity(capacity),
+            generation: 0,
+        }
+    }
+
+    /// The number of operations registered.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.slab.len()
+    }
+
+    /// Whether no operation is registered.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.slab.is_empty()
+    }
+
+    /// Register an operation with its `context`, and return the `user_data` to give it.
+    pub fn insert(&mut self, context: T) -> u64 {
+        let generation = self.generation;
+        self.generation = generation.wrapping_add(1) & GENERATION_MASK;
+        let index = self.slab.insert((generation, context));
+        assert!(index < u32::MAX as usize, "too many operations");
+        key(index, generation)
+    }
+
+    /// Register an operation with its `context`, and return `entry` with its `user_data` set.
+    ///
+    /// If the entry is not pushed after all, [`remove`](Self::remove) it again.
+    pub fn attach(&mut self, entry: squeue::Entry, context: T) -> squeue::Entry {
+        let user_data = self.insert(context);
+        entry.user_data(user_data)
+    }
+
+    /// The context of the operation with `user_data`, if it is registered.
+    pub fn get(&self, user_data: u64) -> Option<&T> {
+        let index = index(user_data)?;
+        match self.slab.get(index)? {
+            (generation, context) if *generation == (user_data >> 32) as u32 => Some(context),
+            _ => None,
+        }
+    }
+
+    /// Unregister the operation with `user_data`, and return its context.
+    pub fn remove(&mut self, user_data: u64) -> Option<T> {
+        self.get(user_data)?;
+        Some(self.slab.remove(index(user_data)?).1)
+    }
+
+    /// Route `cqe` to its operation: unregister it and return its context with the completion.
+    ///
+    /// If `user_data` was not handed out by this registry, or its operation has completed
+    /// already, the entry is given back. Debug builds panic instead, since it means that
+    /// operations are being mixed up.
+    pub fn complete(&mut self, cqe: cqueue::Entry) -> Result<(T, cqueue::Entry), cqueue::Entry> {
+        match self.remove(cqe.user_data()) {
+            Some(context) => Ok((context, cqe)),
+            None => {
+                debug_assert!(
+                    false,
+                    "completion for unknown or already completed user_data {}",
+                    cqe.user_data()
+                );
+                Err(cqe)
+            }
+        }
+    }
+
+    /// Route every entry of `cqes`, usually a [`CompletionQueue`](cqueue::CompletionQueue), to
+    /// its operation. Entries that [`complete`](Self::complete) gives back are skipped.
+    pub fn drain<'a, I>(&'a mut self, cqes: I) -> impl Iterator<Item = (T, cqueue::Entry)> + 'a
+    where
+        I: IntoIterator<Item = cqueue::Entry>,
+        I::IntoIter: 'a,
+    {
+        cqes.into_iter()
+            .filter_map(move |cqe| self.complete(cqe).ok())
+    }
+}
+
+/// Unregisters every operation, yielding their `user_data` and context.
+impl<T> IntoIterator for Completions<T> {
+    type Item = (u64, T);
+    type IntoIter = std::iter::Map<slab::IntoIter<(u32, T)>, fn((usize, (u32, T))) -> (u64, T)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.slab
+            .into_iter()
+            .map(|(index, (generation, context))| (key(index, generation), context))
+    }
+}
+
+fn key(index: usize, generation: u32) -> u64 {
+    (generation as u64) << 32 | (index as u64 + 1)
+}
+
+fn index(user_data: u64) -> Option<usize> {
+    (user_data as u32)
+        .checked_sub(1)
+        .map(|index| index as usize)
+}
+
+impl<T: Debug> Debug for Completions<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let mut d = f.debug_map();
+        for (index, (generation, context)) in &self.slab {
+            d.entry(&key(index, *generation), context);
+        }
+        d.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{opcode, IoRing};
+
+    #[test]
+    fn keys_are_reused_with_a_new_generation() {
+        let mut completions = Completions::new();
+        let a = completions.insert("a");
+        let b = completions.insert("b");
+        assert_ne!(a, 0);
+        assert_ne!(a, b);
+        assert_eq!(completions.remove(a), Some("a"));
+        assert_eq!(completions.remove(a), None);
+
+        let c = completions.insert("c");
+        assert_eq!(c as u32, a as u32);
+        assert_ne!(c, a);
+        assert_eq!(completions.get(a), None);
+        assert_eq!(completions.get(c), Some(&"c"));
+        assert_eq!(completions.len(), 2);
+    }
+
+    #[test]
+    fn drain_routes_completions() {
+        let mut ring = IoRing::emulated(8).unwrap();
+        let mut completions = Completions::new();
+        for i in 0..4 {
+            let nop = completions.attach(opcode::Nop::new(ring.info.0).build(), i);
+            unsafe { ring.submission().push(&nop).unwrap() };
+        }
+        ring.submit().unwrap();
+
+        let done: Vec<_> = completions
+            .drain(ring.completion())
+            .map(|(i, cqe)| (i, cqe.result()))
+            .collect();
+        assert_eq!(done, [(0, 0), (1, 0), (2, 0), (3, 0)]);
+        assert!(completions.is_empty());
+    }
+
+    #[test]
+    #[cfg_attr(
+        debug_assertions,
+        should_panic(expected = "unknown or already completed")
+    )]
+    fn unknown_user_data_is_detected() {
+        let mut completions = Completions::<()>::new();
+        let key = completions.insert(());
+        completions.remove(key);
+        let cqe = cqueue::Entry::synthetic(key, 0, 0);
+        assert!(completions.complete(cqe).is_err());
+    }
+}
@@ -1,13 +1,22 @@
-use std::{fmt, io, mem::MaybeUninit, sync::atomic};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, Weak};
+use std::{fmt, io, mem::MaybeUninit};
 
+use crate::record;
+use crate::ring::Indices;
+use crate::squeue;
+use crate::submit;
+use crate::timer;
+use crate::trace;
 use crate::windows::{
     win_ring, win_ring_cq_clear, win_ring_cqe_get_data64, win_ring_cqe_iter,
-    win_ring_submit_and_wait, _NT_IORING_COMPLETION_QUEUE, _NT_IORING_CQE,
+    win_ring_submit_and_wait, _NT_IORING_CQE, _NT_IORING_CQE__bindgen_ty_1,
 };
+use crate::Shared;
 
 pub(crate) struct Inner {
-    cqes: *mut _NT_IORING_COMPLETION_QUEUE,
     pub(crate) info: *mut win_ring,
+    shared: *const Shared,
 }
 
 /// An io_uring instance's completion queue. This stores all the I/O operations that have completed.
@@ -21,37 +30,89 @@ unsafe impl Send for CompletionQueue<'_> {}
 unsafe impl Sync for CompletionQueue<'_> {}
 
 /// An entry in the completion queue, representing a complete I/O operation.
+///
+/// The entry is copied out of the ring, so it stays valid after the queue slot is reused.
 #[repr(transparent)]
 #[derive(Clone)]
-pub struct Entry(pub(crate) *mut _NT_IORING_CQE);
+pub struct Entry(pub(crate) _NT_IORING_CQE);
+
+/// A handle that posts completions to a ring from any thread, see
+/// [`IoRing::notifier`](crate::IoRing::notifier).
+///
+/// Its completions are handed out by the ring's [`CompletionQueue`] ahead of the kernel's, and
+/// wake up a thread waiting in
+/// [`Submitter::submit_and_wait`](crate::submit::Submitter::submit_and_wait).
+///
+/// # Examples
+///
+/// ```
+/// use ioring_rs::IoRing;
+///
+/// # fn main() -> std::io::Result<()> {
+/// let mut ring = IoRing::emulated(8)?;
+/// let notifier = ring.notifier();
+/// std::thread::spawn(move || notifier.notify(7, 42).unwrap());
+///
+/// ring.submitter().submit_and_wait(1, 1000)?;
+/// let cqe = ring.completion().next().unwrap();
+/// assert_eq!((cqe.user_data(), cqe.information()), (7, 42));
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct Notifier {
+    synthetic: Weak<Mutex<VecDeque<Entry>>>,
+}
+
+// The completions it posts are plain values, which carry no pointers of their own.
+unsafe impl Send for Notifier {}
+unsafe impl Sync for Notifier {}
+
+impl Notifier {
+    pub(crate) fn new(synthetic: &Arc<Mutex<VecDeque<Entry>>>) -> Notifier {
+        Notifier {
+            synthetic: Arc::downgrade(synthetic),
+        }
+    }
+
+    /// Post a successful completion with `user_data`, and `payload` as its
+    /// [`information`](Entry::information). This fails if the ring has been dropped, or with
+    /// [`InvalidInput`](io::ErrorKind::InvalidInput) if `user_data` is
+    /// [reserved](squeue::RESERVED_USER_DATA), as it would not be reported.
+    pub fn notify(&self, user_data: u64, payload: u64) -> io::Result<()> {
+        if user_data >= squeue::RESERVED_USER_DATA {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                squeue::PushError::ReservedUserData(user_data),
+            ));
+        }
+        let synthetic = self.synthetic.upgrade().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::BrokenPipe, "the ring has been dropped")
+        })?;
+        synthetic
+            .lock()
+            .unwrap()
+            .push_back(Entry::synthetic(user_data, 0, payload));
+        Ok(())
+    }
+}
 
 impl Inner {
-    pub(crate) unsafe fn new(p: *mut win_ring) -> Self {
-        let _head = Box::new(atomic::AtomicU32::new(
-            (*p).info
-                .__bindgen_anon_2
-                .CompletionQueue
-                .as_ref()
-                .unwrap()
-                .Head,
-        ));
-        let _tail = Box::new(atomic::AtomicU32::new(
-            (*p).info
-                .__bindgen_anon_2
-                .CompletionQueue
-                .as_ref()
-                .unwrap()
-                .Tail,
-        ));
-        let cqes = (*p).info.__bindgen_anon_2.CompletionQueue;
-        Self { cqes, info: p }
+    pub(crate) unsafe fn new(p: *mut win_ring, shared: *const Shared) -> Self {
+        Self { info: p, shared }
     }
 
     #[inline]
-    pub(crate) unsafe fn borrow_shared(&mut self) -> CompletionQueue<'_> {
+    fn shared(&self) -> &Shared {
+        unsafe { &*self.shared }
+    }
+
+    #[inline]
+    pub(crate) unsafe fn borrow_shared(&self) -> CompletionQueue<'_> {
+        let indices = Indices::completion(self.info);
         CompletionQueue {
-            head: (*(*self.info).info.__bindgen_anon_2.CompletionQueue).Head,
-            tail: (*(*self.info).info.__bindgen_anon_2.CompletionQueue).Tail,
+            head: indices.head(),
+            tail: indices.tail(),
             queue: (self as *const Self as *mut Self).as_mut().unwrap(),
         }
     }
@@ -60,6 +121,32 @@ impl Inner {
     pub(crate) fn borrow(&mut self) -> CompletionQueue<'_> {
         unsafe { self.borrow_shared() }
     }
+
+    /// Take the completion with `user_data` out of the ring buffer, if it has been posted. The
+    /// completions ahead of it are set aside, to be handed out by the next [`CompletionQueue`]
+    /// before those still in the ring buffer.
+    ///
+    /// # Safety
+    ///
+    /// No [`CompletionQueue`] may exist, and the caller must hold [`Shared::waiting`], so that
+    /// no entry is reaped or set aside twice.
+    pub(crate) unsafe fn take(&self, user_data: u64) -> Option<Entry> {
+        let shared = self.shared();
+        let indices = Indices::completion(self.info);
+        let (mut head, tail) = (indices.head(), indices.tail());
+        let mut found = None;
+        while head != tail && found.is_none() {
+            let entry = Entry(*win_ring_cqe_iter(self.info, head));
+            head = head.wrapping_add(1);
+            if entry.user_data() == user_data {
+                found = Some(reap(self.info, shared, entry).unwrap());
+            } else if !is_hidden(entry.user_data()) {
+                shared.deferred.lock().unwrap().push_back(entry);
+            }
+        }
+        indices.release(head);
+        found
+    }
 }
 
 impl CompletionQueue<'_> {
@@ -72,10 +159,9 @@ impl CompletionQueue<'_> {
     /// in the queue if the kernel has produced some entries in the meantime.
     #[inline]
     pub fn sync(&mut self) {
-        unsafe {
-            self.head = self.queue.cqes.as_ref().unwrap().Head;
-            self.tail = self.queue.cqes.as_ref().unwrap().Tail;
-        }
+        let indices = unsafe { Indices::completion(self.queue.info) };
+        indices.release(self.head);
+        self.tail = indices.tail();
     }
 
     /// Get the total number of entries in the completion queue ring buffer.
@@ -84,44 +170,54 @@ impl CompletionQueue<'_> {
         unsafe { (*self.queue.info).info.CompletionQueueSize as usize }
     }
 
-    /// Returns `true` if there are no completion queue events to be processed.
+    /// Get the number of completion queue events in the ring buffer.
+    ///
+    /// This counts the completions of the operations that the ring submits on its own behalf,
+    /// such as the cancels behind [timeouts](crate::squeue::Entry::timeout), which the iterator
+    /// skips. The completions posted by a [`Notifier`], which the iterator hands out first, are
+    /// not in the ring buffer and not counted.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.tail.wrapping_sub(self.head) as usize
+    }
+
+    /// Returns `true` if the completion queue ring buffer is empty.
     #[inline]
     pub fn is_empty(&self) -> bool {
         self.len() == 0
     }
 
-    /// Returns `true` if the completion queue is at maximum capacity. If
-    /// [`is_feature_nodrop`](crate::Parameters::is_feature_nodrop) is not set, this will cause any
-    /// new completion queue events to be dropped by the kernel.
+    /// Returns `true` if every slot of the completion queue ring buffer is taken. The ring never
+    /// submits more operations than there is room for, see
+    /// [`IoRing::overflow`](crate::IoRing::overflow).
     #[inline]
     pub fn is_full(&self) -> bool {
-        self.len() == self.capacity()
+        self.tail.wrapping_sub(self.head) as usize == self.capacity()
     }
 
     #[inline]
     pub fn fill<'a>(&mut self, entries: &'a mut [MaybeUninit<Entry>]) -> &'a mut [Entry] {
-        let len = std::cmp::min(self.len(), entries.len());
-
-        for entry in &mut entries[..len] {
-            *entry = MaybeUninit::new(self.next().unwrap());
-            self.head = self.head.wrapping_add(1);
+        let mut len = 0;
+        for (entry, cqe) in entries.iter_mut().zip(&mut *self) {
+            *entry = MaybeUninit::new(cqe);
+            len += 1;
         }
 
         unsafe { std::slice::from_raw_parts_mut(entries as *mut _ as *mut Entry, len) }
     }
 
+    /// Wait for every operation in flight and discard their completions.
+    ///
+    /// # Safety
+    ///
+    /// `ring` must point to a live ring.
     pub unsafe fn clear_cqes(ring: *mut win_ring, string: &str) -> io::Result<()> {
         win_ring_submit_and_wait(ring, u32::MAX);
-        for i in (*(*ring).info.__bindgen_anon_2.CompletionQueue).Head
-            ..(*(*ring).info.__bindgen_anon_2.CompletionQueue).Tail
-        {
-            let cqe = win_ring_cqe_iter(ring, i);
-            dbg!(
-                (*cqe).__bindgen_anon_1.ResultCode,
-                (*cqe).Information,
-                (*cqe).UserData,
-                string
-            );
+        let indices = Indices::completion(ring);
+        let head = indices.head();
+        for i in 0..indices.len() {
+            let cqe = win_ring_cqe_iter(ring, head.wrapping_add(i));
+            trace::discarded(string, &Entry(*cqe));
         }
         win_ring_cq_clear(ring);
         Ok(())
@@ -131,11 +227,7 @@ impl CompletionQueue<'_> {
 impl Drop for CompletionQueue<'_> {
     #[inline]
     fn drop(&mut self) {
-        unsafe {
-            if self.should_drop() {
-                Self::clear_cqes(self.queue.info, "drop cqe").unwrap();
-            }
-        }
+        unsafe { Indices::completion(self.queue.info) }.release(self.head);
     }
 }
 
@@ -144,50 +236,316 @@ impl Iterator for CompletionQueue<'_> {
 
     #[inline]
     fn next(&mut self) -> Option<Self::Item> {
-        dbg!(self.head);
-        Some(Entry(unsafe {
-            win_ring_cqe_iter(self.queue.info, self.head)
-        }))
+        let shared = self.queue.shared();
+        while let Some(entry) = shared.synthetic.lock().unwrap().pop_front() {
+            if is_hidden(entry.user_data()) {
+                continue;
+            }
+            trace::reaped(&entry);
+            record::reaped(shared, &entry);
+            return Some(entry);
+        }
+        let deferred = shared.deferred.lock().unwrap().pop_front();
+        if let Some(entry) = deferred {
+            return reap(self.queue.info, shared, entry);
+        }
+        while self.head != self.tail {
+            let entry = Entry(unsafe { *win_ring_cqe_iter(self.queue.info, self.head) });
+            self.head = self.head.wrapping_add(1);
+            if let Some(entry) = reap(self.queue.info, shared, entry) {
+                return Some(entry);
+            }
+        }
+        None
     }
 }
 
-impl ExactSizeIterator for CompletionQueue<'_> {
-    #[inline]
-    fn len(&self) -> usize {
-        self.tail.wrapping_sub(self.head) as usize
+/// Account for a completion read from the ring buffer, and queue the next link of its chain.
+/// The completions that are not reported give `None`.
+fn reap(ring: *mut win_ring, shared: &Shared, mut entry: Entry) -> Option<Entry> {
+    if is_hidden(entry.user_data()) {
+        return None;
     }
+    {
+        let mut timers = shared.timers.lock().unwrap();
+        timers.remove(entry.user_data());
+        if timers.expired.remove(&entry.user_data()) && entry.result() == OPERATION_ABORTED {
+            entry = entry.with_result(TIMED_OUT);
+        }
+    }
+    shared.stats.lock().unwrap().completed(&entry);
+    trace::reaped(&entry);
+    record::reaped(shared, &entry);
+    squeue::advance_chain(ring, shared, &entry);
+    Some(entry)
+}
+
+/// Whether a completion is the crate's own business, and not reported.
+pub(crate) fn is_hidden(user_data: u64) -> bool {
+    user_data == timer::CANCEL_USER_DATA
+        || user_data == submit::WAKE_USER_DATA
+        || user_data == squeue::REFUSED_USER_DATA
 }
 
 impl Entry {
+    /// Create a completion that did not come from the kernel.
+    pub(crate) fn synthetic(user_data: u64, result: i32, information: u64) -> Entry {
+        Entry(_NT_IORING_CQE {
+            UserData: user_data,
+            __bindgen_anon_1: _NT_IORING_CQE__bindgen_ty_1 { PadX86_Pointer: 0 },
+            Information: information,
+        })
+        .with_result(result)
+    }
+
+    fn with_result(mut self, result: i32) -> Entry {
+        self.0.__bindgen_anon_1.ResultCode = result as _;
+        self
+    }
+
     /// The operation-specific result code. For example, for a [`Read`](crate::opcode::Read)
     /// operation this is equivalent to the return value of the `read(2)` system call.
     #[inline]
     pub fn result(&self) -> i32 {
-        unsafe { (*self.0).__bindgen_anon_1.ResultCode as _ }
+        unsafe { self.0.__bindgen_anon_1.ResultCode as _ }
     }
     /// The user data of the request, as set by
     /// [`Entry::user_data`](crate::squeue::Entry::user_data) on the submission queue event.
     #[inline]
     pub fn user_data(&self) -> u64 {
-        unsafe { win_ring_cqe_get_data64(self.0) }
+        unsafe { win_ring_cqe_get_data64(&self.0) }
     }
 
     /// Metadata related to the operation.
     ///
-    /// This is currently used for:
-    /// - Storing the selected buffer ID, if one was selected. See
-    /// [`BUFFER_SELECT`](crate::squeue::Flags::BUFFER_SELECT) for more info.
+    /// For example, this is the number of bytes transferred by a
+    /// [`Read`](crate::opcode::Read) or [`Write`](crate::opcode::Write).
     #[inline]
     pub fn information(&self) -> usize {
-        unsafe { (*self.0).Information as _ }
+        self.0.Information as _
+    }
+
+    /// Convert the [`result`](Self::result) of the operation into an [`io::Result`], yielding
+    /// the [`information`](Self::information) on success.
+    ///
+    /// Failures are reported as `HRESULT`s; those wrapping a Win32 error code are mapped back to
+    /// the original error so that [`io::Error::kind`] is meaningful.
+    #[inline]
+    pub fn into_result(&self) -> io::Result<usize> {
+        let result = self.result();
+        if result >= 0 {
+            Ok(self.information())
+        } else {
+            Err(hresult_to_io(result))
+        }
     }
 }
 
+/// `FACILITY_WIN32`, as used by `HRESULT_FROM_WIN32`.
+const FACILITY_WIN32: i32 = 7;
+
+/// `HRESULT_FROM_NT(STATUS_END_OF_FILE)`, reported when reading at or past the end of a file.
+pub(crate) const END_OF_FILE: i32 = 0xD000_0011_u32 as i32;
+
+/// `HRESULT_FROM_WIN32(ERROR_OPERATION_ABORTED)`, the result of a cancelled operation.
+pub(crate) const OPERATION_ABORTED: i32 = 0x8007_03E3_u32 as i32;
+
+/// `HRESULT_FROM_WIN32(ERROR_TIMEOUT)`, the result of an operation whose
+/// [timeout](squeue::Entry::timeout) passed.
+pub(crate) const TIMED_OUT: i32 = 0x8007_05B4_u32 as i32;
+
+/// `HRESULT_FROM_WIN32`, which wraps a Win32 error code.
+pub(crate) fn hresult_from_win32(code: i32) -> i32 {
+    if code <= 0 {
+        code
+    } else {
+        (code & 0xffff) | FACILITY_WIN32 << 16 | 0x8000_0000_u32 as i32
+    }
+}
+
+pub(crate) fn hresult_to_io(hr: i32) -> io::Error {
+    if hr == TIMED_OUT {
+        io::Error::new(io::ErrorKind::TimedOut, "operation timed out")
+    } else if (hr >> 16) & 0x1fff == FACILITY_WIN32 {
+        io::Error::from_raw_os_error(hr & 0xffff)
+    } else {
+        io::Error::from_raw_os_error(hr)
+    }
+}
+
+/// Wraps a raw completion, such as one read from the completion queue by the `win_ring`
+/// functions.
+impl From<_NT_IORING_CQE> for Entry {
+    fn from(cqe: _NT_IORING_CQE) -> Entry {
+        Entry(cqe)
+    }
+}
+
+/// Shows the result as an `HRESULT` in hexadecimal when the operation failed.
 impl fmt::Debug for Entry {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.debug_struct("Entry")
-            .field("user_data", &self.user_data())
-            .field("Information", &self.information())
-            .finish()
+        let mut d = f.debug_struct("Entry");
+        d.field("user_data", &self.user_data());
+        match self.result() {
+            result if result < 0 => d.field("result", &format_args!("{:#010x}", result)),
+            result => d.field("result", &result),
+        };
+        d.field("information", &self.information()).finish()
+    }
+}
+
+/// Shows the entry on one line, such as `user_data=1 information=4`, or
+/// `user_data=1 error=0x80070026 (...)` with the error the result maps to.
+impl fmt::Display for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "user_data={}", self.user_data())?;
+        match self.into_result() {
+            Ok(information) => write!(f, " information={}", information),
+            Err(err) => write!(f, " error={:#010x} ({})", self.result(), err),
+        }
+    }
+}
+
+/// Shows the completions not reaped yet, those produced in user space and those set aside
+/// first, in the order the queue hands them out.
+impl fmt::Debug for CompletionQueue<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut entries = f.debug_list();
+        entries.entries(self.queue.shared().synthetic.lock().unwrap().iter());
+        entries.entries(self.queue.shared().deferred.lock().unwrap().iter());
+        let mut head = self.head;
+        while head != self.tail {
+            let cqe = Entry(unsafe { *win_ring_cqe_iter(self.queue.info, head) });
+            if !is_hidden(cqe.user_data()) {
+                entries.entry(&cqe);
+            }
+            head = head.wrapping_add(1);
+        }
+        entries.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{opcode, IoRing};
+
+    fn push_nops(ring: &mut IoRing, user_data: std::ops::Range<u64>) {
+        for user_data in user_data {
+            let nop = opcode::Nop::new(ring.info.0).build().user_data(user_data);
+            unsafe { ring.submission().push(&nop).unwrap() };
+        }
+    }
+
+    #[test]
+    fn submit_holds_back_what_the_completion_queue_cannot_take() {
+        // Room for 4 entries, and 8 completions.
+        let mut ring = IoRing::emulated(4).unwrap();
+        push_nops(&mut ring, 0..4);
+        ring.submit().unwrap();
+        push_nops(&mut ring, 4..8);
+        ring.submit().unwrap();
+        assert_eq!(ring.in_flight(), 8);
+        assert!(ring.completion().is_full());
+        assert_eq!(ring.overflow(), 0);
+
+        push_nops(&mut ring, 8..12);
+        ring.submit().unwrap();
+        assert_eq!(ring.overflow(), 4);
+        assert_eq!(ring.submission().len(), 4);
+        assert_eq!(ring.in_flight(), 8);
+
+        let reaped: Vec<_> = ring
+            .completion()
+            .take(3)
+            .map(|cqe| cqe.user_data())
+            .collect();
+        assert_eq!(reaped, [0, 1, 2]);
+        assert_eq!(ring.in_flight(), 5);
+        ring.submit().unwrap();
+        assert_eq!(ring.overflow(), 5);
+        assert_eq!(ring.submission().len(), 1);
+
+        let reaped: Vec<_> = ring.completion().map(|cqe| cqe.user_data()).collect();
+        assert_eq!(reaped, (3..11).collect::<Vec<_>>());
+        ring.submit().unwrap();
+        let reaped: Vec<_> = ring.completion().map(|cqe| cqe.user_data()).collect();
+        assert_eq!(reaped, [11]);
+        assert_eq!(ring.in_flight(), 0);
+        assert_eq!(ring.emulator().unwrap().overflowed(), 0);
+    }
+
+    #[test]
+    fn notifier_wakes_a_waiting_ring() {
+        let mut ring = IoRing::emulated(4).unwrap();
+        let notifier = ring.notifier();
+        let thread = std::thread::spawn({
+            let notifier = notifier.clone();
+            move || {
+                std::thread::sleep(std::time::Duration::from_millis(20));
+                notifier.notify(7, 42).unwrap();
+            }
+        });
+        push_nops(&mut ring, 0..1);
+        ring.submitter().submit_and_wait(2, 5000).unwrap();
+        thread.join().unwrap();
+
+        let reaped: Vec<_> = ring
+            .completion()
+            .map(|cqe| (cqe.user_data(), cqe.information()))
+            .collect();
+        assert_eq!(reaped, [(7, 42), (0, 0)]);
+
+        drop(ring);
+        let err = notifier.notify(7, 42).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::BrokenPipe);
+    }
+
+    #[test]
+    fn nop_completes_with_its_payload() {
+        let mut ring = IoRing::emulated(4).unwrap();
+        let nop = opcode::Nop::new(ring.info.0)
+            .result(super::OPERATION_ABORTED)
+            .information(42)
+            .build()
+            .user_data(1);
+        unsafe { ring.submission().push(&nop).unwrap() };
+        ring.submit().unwrap();
+
+        let cqe = ring.completion().next().unwrap();
+        assert_eq!(cqe.result(), super::OPERATION_ABORTED);
+        assert_eq!(cqe.information(), 42);
+    }
+
+    #[test]
+    fn completion_queue_shows_unread_entries() {
+        let mut ring = IoRing::emulated(4).unwrap();
+        let nop = opcode::Nop::new(ring.info.0)
+            .result(super::OPERATION_ABORTED)
+            .build()
+            .user_data(1);
+        unsafe { ring.submission().push(&nop).unwrap() };
+        let nop = opcode::Nop::new(ring.info.0)
+            .information(4)
+            .build()
+            .user_data(2);
+        unsafe { ring.submission().push(&nop).unwrap() };
+        ring.notifier().notify(3, 0).unwrap();
+        ring.submit().unwrap();
+
+        assert_eq!(
+            format!("{:?}", ring.completion()),
+            "[Entry { user_data: 3, result: 0, information: 0 }, \
+             Entry { user_data: 1, result: 0x800703e3, information: 0 }, \
+             Entry { user_data: 2, result: 0, information: 4 }]"
+        );
+        let mut cq = ring.completion();
+        assert_eq!(cq.next().unwrap().to_string(), "user_data=3 information=0");
+        let aborted = cq.next().unwrap().to_string();
+        assert!(aborted.starts_with("user_data=1 error=0x800703e3 ("));
+        drop(cq);
+        assert_eq!(
+            format!("{:?}", ring.completion()),
+            "[Entry { user_data: 2, result: 0, information: 4 }]"
+        );
     }
 }
@@ -0,0 +1,469 @@
+//! A ring whose operations are carried out in user space instead of by the kernel.
+//!
+//! An [`Emulator`] owns submission and completion queues with the same layout as the kernel's,
+//! so everything built on top of [`IoRing`](crate::IoRing) works unchanged. Each submit consumes
+//! the queued SQEs and performs them synchronously, with ordinary positional I/O on the handles
+//! they name. This makes it possible to exercise the crate where IoRing is unavailable, and to
+//! control what the kernel otherwise decides: which operations are held back with
+//! [`stall`](Emulator::stall), in which order the others complete with
+//! [`set_completion_order`](Emulator::set_completion_order), and which of them fail with
+//! [`set_faults`](Emulator::set_faults).
+//!
+//! Use [`IoRing::emulated`](crate::IoRing::emulated) to create such a ring and
+//! [`IoRing::emulator`](crate::IoRing::emulator) to reach its emulator.
+
+use std::collections::{HashSet, VecDeque};
+use std::mem::{self, ManuallyDrop};
+use std::{io, ptr, slice};
+
+use crate::cqueue::{self, hresult_from_win32, END_OF_FILE, OPERATION_ABORTED};
+use crate::fault::{self, Action};
+use crate::ring::Indices;
+use crate::squeue::target;
+use crate::windows::{
+    win_ring, HANDLE, IORING_BUFFER_INFO, NT_IORING_BUFFERREF, NT_IORING_HANDLEREF,
+    _IORING_OP_CODE_IORING_OP_CANCEL, _IORING_OP_CODE_IORING_OP_FLUSH,
+    _IORING_OP_CODE_IORING_OP_NOP, _IORING_OP_CODE_IORING_OP_READ,
+    _IORING_OP_CODE_IORING_OP_REGISTER_BUFFERS, _IORING_OP_CODE_IORING_OP_REGISTER_FILES,
+    _IORING_OP_CODE_IORING_OP_WRITE, _IORING_VERSION_IORING_VERSION_3, _NT_IORING_COMPLETION_QUEUE,
+    _NT_IORING_CQE, _NT_IORING_INFO__bindgen_ty_1, _NT_IORING_INFO__bindgen_ty_2,
+    _NT_IORING_OP_FLAGS, _NT_IORING_OP_FLAGS_NT_IORING_OP_FLAG_REGISTERED_BUFFER,
+    _NT_IORING_OP_FLAGS_NT_IORING_OP_FLAG_REGISTERED_FILE, _NT_IORING_SQE,
+    _NT_IORING_SQE_FLAGS_NT_IORING_SQE_FLAG_DRAIN_PRECEDING_OPS, _NT_IORING_SUBMISSION_QUEUE,
+};
+
+/// `E_FAIL`, for failures without an OS error code.
+const E_FAIL: i32 = 0x8000_4005_u32 as i32;
+const ERROR_INVALID_HANDLE: i32 = 6;
+const ERROR_INVALID_PARAMETER: i32 = 87;
+const ERROR_NOT_FOUND: i32 = 1168;
+#[cfg(unix)]
+const EBADF: i32 = 9;
+
+/// The order in which an [`Emulator`] completes the operations that are free to run.
+///
+/// An operation flagged with
+/// [`DRAIN_PRECEDING_OPS`](crate::windows::_NT_IORING_SQE_FLAGS_NT_IORING_SQE_FLAG_DRAIN_PRECEDING_OPS)
+/// still waits for all the operations submitted before it either way.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CompletionOrder {
+    /// Complete operations in submission order.
+    #[default]
+    Submission,
+    /// Complete the most recently submitted operation first.
+    Reversed,
+}
+
+/// The user-space backend of an emulated ring. See the [module documentation](self).
+pub struct Emulator {
+    ring: Box<win_ring>,
+    // Backing memory of the queues, as `u64`s to get the alignment of the entries.
+    _sq: Box<[u64]>,
+    _cq: Box<[u64]>,
+    /// Operations consumed from the submission queue that have not completed yet.
+    pending: VecDeque<_NT_IORING_SQE>,
+    /// Completions that did not fit in the completion queue.
+    overflow: VecDeque<_NT_IORING_CQE>,
+    overflowed: u64,
+    files: Vec<u64>,
+    buffers: Vec<IORING_BUFFER_INFO>,
+    stalled: HashSet<u64>,
+    order: CompletionOrder,
+    faults: Option<fault::Plan>,
+    /// Completions held back by [`Action::Delay`]: the number of submits to go, the user data,
+    /// the result and the information.
+    delayed: Vec<(u32, u64, i32, u64)>,
+}
+
+unsafe impl Send for Emulator {}
+
+impl Emulator {
+    /// Create the queues of a ring with room for `entries` submissions, rounded up to a power of
+    /// two, and twice as many completions.
+    pub(crate) fn new(entries: u32) -> io::Result<Emulator> {
+        if entries == 0 || entries > 1 << 15 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "the number of entries must be between 1 and 32768",
+            ));
+        }
+        let sq_size = entries.next_power_of_two();
+        let cq_size = sq_size * 2;
+        let mut sq = queue_memory::<_NT_IORING_SUBMISSION_QUEUE, _NT_IORING_SQE>(sq_size);
+        let mut cq = queue_memory::<_NT_IORING_COMPLETION_QUEUE, _NT_IORING_CQE>(cq_size);
+
+        let mut ring: Box<win_ring> = Box::new(unsafe { mem::zeroed() });
+        ring.info.IoRingVersion = _IORING_VERSION_IORING_VERSION_3;
+        ring.info.SubmissionQueueSize = sq_size;
+        ring.info.SubmissionQueueRingMask = sq_size - 1;
+        ring.info.CompletionQueueSize = cq_size;
+        ring.info.CompletionQueueRingMask = cq_size - 1;
+        ring.info.__bindgen_anon_1 = _NT_IORING_INFO__bindgen_ty_1 {
+            SubmissionQueue: sq.as_mut_ptr().cast(),
+        };
+        ring.info.__bindgen_anon_2 = _NT_IORING_INFO__bindgen_ty_2 {
+            CompletionQueue: cq.as_mut_ptr().cast(),
+        };
+
+        Ok(Emulator {
+            ring,
+            _sq: sq,
+            _cq: cq,
+            pending: VecDeque::new(),
+            overflow: VecDeque::new(),
+            overflowed: 0,
+            files: Vec::new(),
+            buffers: Vec::new(),
+            stalled: HashSet::new(),
+            order: CompletionOrder::default(),
+            faults: None,
+            delayed: Vec::new(),
+        })
+    }
+
+    /// The ring to hand to the queues and opcodes.
+    pub(crate) fn ring(&mut self) -> *mut win_ring {
+        &mut *self.ring
+    }
+
+    /// Set the order in which operations that are free to run complete.
+    pub fn set_completion_order(&mut self, order: CompletionOrder) {
+        self.order = order;
+    }
+
+    /// Inject the faults of `plan` into the operations submitted from now on, or stop with
+    /// `None`. The plan in use before is returned.
+    pub fn set_faults(&mut self, plan: Option<fault::Plan>) -> Option<fault::Plan> {
+        mem::replace(&mut self.faults, plan)
+    }
+
+    /// The faults being injected, see [`set_faults`](Self::set_faults).
+    pub fn faults(&self) -> Option<&fault::Plan> {
+        self.faults.as_ref()
+    }
+
+    /// Hold back every operation on `handle` until it is [resumed](Self::resume). Stalled
+    /// operations can still be cancelled.
+    pub fn stall(&mut self, handle: HANDLE) {
+        self.stalled.insert(handle as u64);
+    }
+
+    /// Let the operations on `handle` run again. They are performed by the next submit.
+    pub fn resume(&mut self, handle: HANDLE) {
+        self.stalled.remove(&(handle as u64));
+    }
+
+    /// The number of submitted operations that have not completed yet, including those whose
+    /// completion is [delayed](Action::Delay).
+    pub fn in_flight(&self) -> usize {
+        self.pending.len() + self.delayed.len()
+    }
+
+    /// Consume the submission queue and perform every operation that is free to run.
+    pub(crate) fn submit(&mut self) {
+        unsafe {
+            let sq = Indices::submission(&*self.ring);
+            let entries = (*self.ring.info.__bindgen_anon_1.SubmissionQueue)
+                .Entries
+                .as_ptr();
+            while let Some(sqe) = sq.pop(|slot| *entries.add(slot)) {
+                self.pending.push_back(sqe);
+            }
+        }
+        self.flush_overflow();
+        for (submits, user_data, result, information) in mem::take(&mut self.delayed) {
+            if submits > 1 {
+                self.delayed
+                    .push((submits - 1, user_data, result, information));
+            } else {
+                self.post(user_data, result, information);
+            }
+        }
+        while let Some(index) = self.next_ready() {
+            let sqe = self.pending.remove(index).unwrap();
+            self.execute(&sqe);
+        }
+    }
+
+    /// Pick the next operation to perform. An operation with
+    /// [`DRAIN_PRECEDING_OPS`](crate::windows::_NT_IORING_SQE_FLAGS_NT_IORING_SQE_FLAG_DRAIN_PRECEDING_OPS)
+    /// is only eligible once every operation submitted before it has completed.
+    fn next_ready(&mut self) -> Option<usize> {
+        let ready: Vec<_> = (0..self.pending.len())
+            .filter(|&i| {
+                let sqe = &self.pending[i];
+                (i == 0 || !drains(sqe)) && !self.is_stalled(sqe)
+            })
+            .collect();
+        if ready.is_empty() {
+            return None;
+        }
+        match (self.faults.as_mut(), self.order) {
+            (Some(plan), _) if plan.reorders() => Some(ready[plan.index(ready.len())]),
+            (_, CompletionOrder::Submission) => ready.first().copied(),
+            (_, CompletionOrder::Reversed) => ready.last().copied(),
+        }
+    }
+
+    fn is_stalled(&self, sqe: &_NT_IORING_SQE) -> bool {
+        match target(sqe) {
+            Some((file, flags)) if sqe.OpCode != _IORING_OP_CODE_IORING_OP_CANCEL => self
+                .handle(file, flags)
+                .is_ok_and(|handle| self.stalled.contains(&handle)),
+            _ => false,
+        }
+    }
+
+    fn execute(&mut self, sqe: &_NT_IORING_SQE) {
+        let mut sqe = *sqe;
+        let mut delay = 0;
+        match self.fault(&sqe) {
+            Some(Action::Fail(hr)) => return self.post(sqe.UserData, hr, 0),
+            Some(Action::Truncate(len)) => unsafe {
+                let op = &mut sqe.__bindgen_anon_1;
+                match sqe.OpCode {
+                    _IORING_OP_CODE_IORING_OP_READ => op.Read.Length = op.Read.Length.min(len),
+                    _IORING_OP_CODE_IORING_OP_WRITE => op.Write.Length = op.Write.Length.min(len),
+                    _ => {}
+                }
+            },
+            Some(Action::Delay(submits)) => delay = submits,
+            None => {}
+        }
+        let (result, information) = self.perform(&sqe);
+        if delay == 0 {
+            self.post(sqe.UserData, result, information);
+        } else {
+            self.delayed
+                .push((delay, sqe.UserData, result, information));
+        }
+    }
+
+    /// Pick the fault to inject into `sqe`, if any.
+    fn fault(&mut self, sqe: &_NT_IORING_SQE) -> Option<Action> {
+        if cqueue::is_hidden(sqe.UserData) {
+            return None;
+        }
+        let handle = target(sqe).and_then(|(file, flags)| self.handle(file, flags).ok());
+        self.faults.as_mut()?.pick(sqe.OpCode, handle)
+    }
+
+    /// Carry out `sqe`, and return its result and information.
+    fn perform(&mut self, sqe: &_NT_IORING_SQE) -> (i32, u64) {
+        if sqe.OpCode == _IORING_OP_CODE_IORING_OP_NOP {
+            // A NOP completes with what it was built with, see `opcode::Nop::result`.
+            let payload = unsafe { &sqe.__bindgen_anon_1.ReservedMaxSizePadding };
+            return (payload.Argument1 as i32, payload.Argument2);
+        }
+        let result = unsafe {
+            let op = &sqe.__bindgen_anon_1;
+            match sqe.OpCode {
+                _IORING_OP_CODE_IORING_OP_READ => {
+                    let read = &op.Read;
+                    self.transfer(read.File, read.Buffer, read.Length, read.CommonOpFlags)
+                        .and_then(|(handle, buffer)| {
+                            match with_file(handle, |file| read_at(file, buffer, read.Offset)) {
+                                Ok(0) if read.Length > 0 => Err(END_OF_FILE),
+                                result => result.map_err(io_to_hresult),
+                            }
+                        })
+                }
+                _IORING_OP_CODE_IORING_OP_WRITE => {
+                    let write = &op.Write;
+                    self.transfer(write.File, write.Buffer, write.Length, write.CommonOpFlags)
+                        .and_then(|(handle, buffer)| {
+                            with_file(handle, |file| write_at(file, buffer, write.Offset))
+                                .map_err(io_to_hresult)
+                        })
+                }
+                _IORING_OP_CODE_IORING_OP_FLUSH => {
+                    let flush = &op.Flush;
+                    self.handle(flush.File, flush.CommonOpFlags)
+                        .and_then(|handle| {
+                            with_file(handle, |file| file.sync_all().map(|()| 0))
+                                .map_err(io_to_hresult)
+                        })
+                }
+                _IORING_OP_CODE_IORING_OP_REGISTER_FILES => {
+                    let register = &op.RegisterFiles;
+                    let handles = raw_slice(register.__bindgen_anon_1.Handles, register.Count);
+                    self.files = handles.iter().map(|&handle| handle as u64).collect();
+                    Ok(0)
+                }
+                _IORING_OP_CODE_IORING_OP_REGISTER_BUFFERS => {
+                    let register = &op.RegisterBuffers;
+                    self.buffers =
+                        raw_slice(register.__bindgen_anon_1.Buffers, register.Count).to_vec();
+                    Ok(0)
+                }
+                _IORING_OP_CODE_IORING_OP_CANCEL => {
+                    let cancel = &op.Cancel;
+                    self.handle(cancel.File, cancel.CommonOpFlags)
+                        .and_then(|handle| self.cancel(handle, cancel.CancelId))
+                }
+                _ => Err(hresult_from_win32(ERROR_INVALID_PARAMETER)),
+            }
+        };
+        match result {
+            Ok(information) => (0, information),
+            Err(hr) => (hr, 0),
+        }
+    }
+
+    /// Abort the pending operations on `handle` whose user data is `cancel_id`, or all of them if
+    /// it is `0`.
+    fn cancel(&mut self, handle: u64, cancel_id: u64) -> Result<u64, i32> {
+        let (cancelled, pending): (VecDeque<_>, VecDeque<_>) =
+            mem::take(&mut self.pending).into_iter().partition(|sqe| {
+                (cancel_id == 0 || sqe.UserData == cancel_id)
+                    && sqe.OpCode != _IORING_OP_CODE_IORING_OP_CANCEL
+                    && target(sqe).and_then(|(file, flags)| self.handle(file, flags).ok())
+                        == Some(handle)
+            });
+        self.pending = pending;
+        if cancelled.is_empty() {
+            return Err(hresult_from_win32(ERROR_NOT_FOUND));
+        }
+        for sqe in cancelled {
+            self.post(sqe.UserData, OPERATION_ABORTED, 0);
+        }
+        Ok(0)
+    }
+
+    /// Resolve the file and buffer of a read or write.
+    unsafe fn transfer<'b>(
+        &self,
+        file: NT_IORING_HANDLEREF,
+        buffer: NT_IORING_BUFFERREF,
+        len: u32,
+        flags: _NT_IORING_OP_FLAGS,
+    ) -> Result<(u64, &'b mut [u8]), i32> {
+        let handle = self.handle(file, flags)?;
+        let address = if flags & _NT_IORING_OP_FLAGS_NT_IORING_OP_FLAG_REGISTERED_BUFFER != 0 {
+            let fixed = buffer.FixedBuffer;
+            match self.buffers.get(fixed.BufferIndex as usize) {
+                Some(info) if fixed.Offset as u64 + len as u64 <= info.Length as u64 => {
+                    (info.Address as *mut u8).add(fixed.Offset as usize)
+                }
+                _ => return Err(hresult_from_win32(ERROR_INVALID_PARAMETER)),
+            }
+        } else {
+            buffer.Address as *mut u8
+        };
+        if address.is_null() && len > 0 {
+            return Err(hresult_from_win32(ERROR_INVALID_PARAMETER));
+        }
+        Ok((handle, raw_slice_mut(address, len)))
+    }
+
+    /// Resolve a handle reference to the handle itself.
+    fn handle(&self, file: NT_IORING_HANDLEREF, flags: _NT_IORING_OP_FLAGS) -> Result<u64, i32> {
+        unsafe {
+            if flags & _NT_IORING_OP_FLAGS_NT_IORING_OP_FLAG_REGISTERED_FILE != 0 {
+                self.files
+                    .get(file.HandleIndex as usize)
+                    .copied()
+                    .ok_or_else(|| hresult_from_win32(ERROR_INVALID_HANDLE))
+            } else {
+                Ok(file.Handle)
+            }
+        }
+    }
+
+    /// The number of completions that found the completion queue full and had to wait for room.
+    /// A kernel ring would not be as forgiving.
+    pub fn overflowed(&self) -> u64 {
+        self.overflowed
+    }
+
+    /// Add a completion to the completion queue, or keep it until there is room.
+    fn post(&mut self, user_data: u64, result: i32, information: u64) {
+        self.overflow
+            .push_back(cqueue::Entry::synthetic(user_data, result, information).0);
+        self.flush_overflow();
+        if !self.overflow.is_empty() {
+            self.overflowed += 1;
+        }
+    }
+
+    fn flush_overflow(&mut self) {
+        unsafe {
+            let cq = Indices::completion(&*self.ring);
+            let entries = (*self.ring.info.__bindgen_anon_2.CompletionQueue)
+                .Entries
+                .as_mut_ptr();
+            while cq.room() > 0 {
+                let cqe = match self.overflow.pop_front() {
+                    Some(cqe) => cqe,
+                    None => break,
+                };
+                cq.push(|slot| ptr::write(entries.add(slot), cqe));
+            }
+        }
+    }
+}
+
+fn drains(sqe: &_NT_IORING_SQE) -> bool {
+    sqe.Flags & _NT_IORING_SQE_FLAGS_NT_IORING_SQE_FLAG_DRAIN_PRECEDING_OPS != 0
+}
+
+/// Allocate zeroed memory for a queue header `H` followed by `len` entries `E`.
+fn queue_memory<H, E>(len: u32) -> Box<[u64]> {
+    let bytes = mem::size_of::<H>() + mem::size_of::<E>() * len as usize;
+    vec![0; bytes.div_ceil(8)].into_boxed_slice()
+}
+
+unsafe fn raw_slice<'a, T>(data: *const T, len: u32) -> &'a [T] {
+    if len == 0 {
+        &[]
+    } else {
+        slice::from_raw_parts(data, len as usize)
+    }
+}
+
+unsafe fn raw_slice_mut<'a>(data: *mut u8, len: u32) -> &'a mut [u8] {
+    if len == 0 {
+        &mut []
+    } else {
+        slice::from_raw_parts_mut(data, len as usize)
+    }
+}
+
+fn io_to_hresult(error: io::Error) -> i32 {
+    error.raw_os_error().map_or(E_FAIL, hresult_from_win32)
+}
+
+/// Run `f` on the file behind `handle` without taking ownership of it.
+fn with_file<T>(handle: u64, f: impl FnOnce(&std::fs::File) -> io::Result<T>) -> io::Result<T> {
+    #[cfg(windows)]
+    let file = {
+        use std::os::windows::io::FromRawHandle;
+        unsafe { std::fs::File::from_raw_handle(handle as HANDLE) }
+    };
+    #[cfg(unix)]
+    let file = {
+        use std::os::unix::io::FromRawFd;
+        let fd = match i32::try_from(handle as i64) {
+            Ok(fd) if fd >= 0 => fd,
+            _ => return Err(io::Error::from_raw_os_error(EBADF)),
+        };
+        unsafe { std::fs::File::from_raw_fd(fd) }
+    };
+    f(&ManuallyDrop::new(file))
+}
+
+fn read_at(file: &std::fs::File, buffer: &mut [u8], offset: u64) -> io::Result<u64> {
+    #[cfg(unix)]
+    let read = std::os::unix::fs::FileExt::read_at(file, buffer, offset);
+    #[cfg(windows)]
+    let read = std::os::windows::fs::FileExt::seek_read(file, buffer, offset);
+    read.map(|n| n as u64)
+}
+
+fn write_at(file: &std::fs::File, buffer: &[u8], offset: u64) -> io::Result<u64> {
+    #[cfg(unix)]
+    let written = std::os::unix::fs::FileExt::write_at(file, buffer, offset);
+    #[cfg(windows)]
+    let written = std::os::windows::fs::FileExt::seek_write(file, buffer, offset);
+    written.map(|n| n as u64)
+}
@@ -0,0 +1,362 @@
+//! Faults injected into the operations of an [emulated](crate::IoRing::emulated) ring, to test
+//! the error paths that a kernel ring rarely takes.
+//!
+//! A [`Plan`] is a list of [`Rule`]s, each matching operations by opcode and handle, and firing
+//! on every match, on the nth match only, or at random. When a rule fires, its [`Action`] fails
+//! the operation, shortens it or holds its completion back. A plan can also have the operations
+//! that are free to run complete in a random order. Randomness comes from a generator seeded
+//! with [`Plan::seed`], so that a failing test can be run again with the same faults.
+//!
+//! The operations the crate submits on its own behalf, such as the cancels behind
+//! [timeouts](crate::squeue::Entry::timeout), are left alone.
+//!
+//! # Examples
+//!
+//! ```
+//! use ioring_rs::fault::{Action, Plan, Rule, OPERATION_ABORTED};
+//! use ioring_rs::{opcode, IoRing};
+//!
+//! # fn main() -> std::io::Result<()> {
+//! let mut ring = IoRing::emulated(8)?;
+//! let plan = Plan::new().rule(
+//!     Rule::new(Action::Fail(OPERATION_ABORTED))
+//!         .opcode(opcode::Nop::CODE)
+//!         .nth(2),
+//! );
+//! ring.emulator().unwrap().set_faults(Some(plan));
+//! for user_data in 1..=3 {
+//!     let nop = opcode::Nop::new(ring.info.0).build().user_data(user_data);
+//!     unsafe { ring.submission().push(&nop).expect("submission queue is full") };
+//! }
+//! ring.submit()?;
+//!
+//! let results: Vec<_> = ring.completion().map(|cqe| cqe.result()).collect();
+//! assert_eq!(results, [0, OPERATION_ABORTED, 0]);
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::cqueue;
+use crate::windows::HANDLE;
+
+/// The `HRESULT` of an operation that was cancelled.
+pub const OPERATION_ABORTED: i32 = cqueue::OPERATION_ABORTED;
+/// The `HRESULT` of a read at or past the end of a file, from `STATUS_END_OF_FILE`.
+pub const END_OF_FILE: i32 = cqueue::END_OF_FILE;
+
+/// What happens to an operation when a [`Rule`] fires.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Action {
+    /// Complete the operation with this `HRESULT` without carrying it out.
+    Fail(i32),
+    /// Carry out a read or write on at most this many bytes, for a short read or write. Other
+    /// operations are not affected.
+    Truncate(u32),
+    /// Carry out the operation, but only post its completion after the ring has been entered
+    /// this many more times.
+    Delay(u32),
+}
+
+/// When a [`Rule`] fires, among the operations it matches.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum When {
+    Always,
+    Nth(u64),
+    Probability(f64),
+}
+
+/// A fault to inject, and the operations to inject it into.
+#[derive(Clone, Debug)]
+pub struct Rule {
+    action: Action,
+    op_code: Option<i32>,
+    handle: Option<u64>,
+    when: When,
+    /// The number of operations matched so far.
+    matched: u64,
+}
+
+impl Rule {
+    /// A rule that fires on every operation.
+    pub fn new(action: Action) -> Rule {
+        Rule {
+            action,
+            op_code: None,
+            handle: None,
+            when: When::Always,
+            matched: 0,
+        }
+    }
+
+    /// Only match operations with this opcode, such as
+    /// [`opcode::Write::CODE`](crate::opcode::Write::CODE).
+    pub fn opcode(mut self, op_code: i32) -> Rule {
+        self.op_code = Some(op_code);
+        self
+    }
+
+    /// Only match operations on the file with this handle, whether it is given directly or by
+    /// its index among the registered files.
+    pub fn handle(mut self, handle: HANDLE) -> Rule {
+        self.handle = Some(handle as u64);
+        self
+    }
+
+    /// Only fire on the `n`th operation matched, counting from 1.
+    pub fn nth(mut self, n: u64) -> Rule {
+        self.when = When::Nth(n);
+        self
+    }
+
+    /// Fire on each operation matched with the given probability, between `0.0` and `1.0`.
+    pub fn probability(mut self, probability: f64) -> Rule {
+        self.when = When::Probability(probability);
+        self
+    }
+}
+
+/// The faults to inject into an emulated ring, see the [module documentation](self).
+#[derive(Clone, Debug)]
+pub struct Plan {
+    rules: Vec<Rule>,
+    reorder: bool,
+    rng: u64,
+    injected: u64,
+}
+
+impl Default for Plan {
+    fn default() -> Self {
+        Plan::new()
+    }
+}
+
+impl Plan {
+    /// An empty plan, which injects nothing until [rules](Self::rule) are added.
+    pub fn new() -> Plan {
+        Plan {
+            rules: Vec::new(),
+            reorder: false,
+            rng: 0x9e37_79b9_7f4a_7c15,
+            injected: 0,
+        }
+    }
+
+    /// Add a rule. When several rules fire on the same operation, the first one added wins.
+    pub fn rule(mut self, rule: Rule) -> Plan {
+        self.rules.push(rule);
+        self
+    }
+
+    /// Complete the operations that are free to run in a random order, rather than in the
+    /// [`CompletionOrder`](crate::emulate::CompletionOrder) of the emulator.
+    pub fn reorder(mut self, reorder: bool) -> Plan {
+        self.reorder = reorder;
+        self
+    }
+
+    /// Seed the generator behind [`probability`](Rule::probability) and
+    /// [`reorder`](Self::reorder).
+    pub fn seed(mut self, seed: u64) -> Plan {
+        // Zero would be a fixed point of the generator.
+        self.rng = seed | 1;
+        self
+    }
+
+    /// The number of faults injected so far.
+    pub fn injected(&self) -> u64 {
+        self.injected
+    }
+
+    pub(crate) fn reorders(&self) -> bool {
+        self.reorder
+    }
+
+    /// Pick the fault to inject into an operation with `op_code` on `handle`, if any.
+    pub(crate) fn pick(&mut self, op_code: i32, handle: Option<u64>) -> Option<Action> {
+        let mut picked = None;
+        for i in 0..self.rules.len() {
+            let rule = &mut self.rules[i];
+            if rule.op_code.is_some_and(|code| code != op_code)
+                || rule.handle.is_some_and(|wanted| Some(wanted) != handle)
+            {
+                continue;
+            }
+            rule.matched += 1;
+            let (when, matched, action) = (rule.when, rule.matched, rule.action);
+            let fires = match when {
+                When::Always => true,
+                When::Nth(n) => matched == n,
+                When::Probability(probability) => self.chance() < probability,
+            };
+            if fires && picked.is_none() {
+                picked = Some(action);
+            }
+        }
+        self.injected += picked.is_some() as u64;
+        picked
+    }
+
+    /// A random index below `len`, which must not be 0.
+    pub(crate) fn index(&mut self, len: usize) -> usize {
+        (self.next() % len as u64) as usize
+    }
+
+    /// A random number between 0 and 1.
+    fn chance(&mut self) -> f64 {
+        (self.next() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// xorshift64*.
+    fn next(&mut self) -> u64 {
+        self.rng ^= self.rng >> 12;
+        self.rng ^= self.rng << 25;
+        self.rng ^= self.rng >> 27;
+        self.rng.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testfile::TestFile;
+    use crate::windows::{
+        NT_IORING_BUFFERREF, NT_IORING_HANDLEREF, _NT_IORING_OP_FLAGS_NT_IORING_OP_FLAG_NONE,
+    };
+    use crate::{fs, opcode, IoRing};
+
+    fn completed(ring: &mut IoRing) -> Vec<(u64, i32, usize)> {
+        ring.completion()
+            .map(|cqe| (cqe.user_data(), cqe.result(), cqe.information()))
+            .collect()
+    }
+
+    fn push_nops(ring: &mut IoRing, user_data: std::ops::RangeInclusive<u64>) {
+        for user_data in user_data {
+            let nop = opcode::Nop::new(ring.info.0).build().user_data(user_data);
+            unsafe { ring.submission().push(&nop).unwrap() };
+        }
+    }
+
+    #[test]
+    fn reads_and_writes_are_failed_or_shortened() {
+        let path = TestFile::new("fault", b"0123456789");
+        let file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(path.path())
+            .unwrap();
+        let handle = fs::raw_handle(&file);
+
+        let mut ring = IoRing::emulated(8).unwrap();
+        let plan = Plan::new()
+            .rule(Rule::new(Action::Truncate(3)).opcode(opcode::Read::CODE))
+            .rule(
+                Rule::new(Action::Fail(END_OF_FILE))
+                    .opcode(opcode::Read::CODE)
+                    .handle(handle)
+                    .nth(2),
+            )
+            .rule(Rule::new(Action::Truncate(1)).opcode(opcode::Write::CODE));
+        ring.emulator().unwrap().set_faults(Some(plan));
+
+        let mut bufs = [[0u8; 8]; 2];
+        let file_ref = NT_IORING_HANDLEREF {
+            Handle: handle as u64,
+        };
+        for (i, buf) in bufs.iter_mut().enumerate() {
+            let read = opcode::Read::new(
+                ring.info.0,
+                file_ref,
+                NT_IORING_BUFFERREF {
+                    Address: buf.as_mut_ptr() as _,
+                },
+                buf.len() as u32,
+                0,
+                _NT_IORING_OP_FLAGS_NT_IORING_OP_FLAG_NONE,
+            )
+            .build()
+            .user_data(i as u64 + 1);
+            unsafe { ring.submission().push(&read).unwrap() };
+        }
+        let data = *b"ab";
+        let write = opcode::Write::new(
+            ring.info.0,
+            file_ref,
+            NT_IORING_BUFFERREF {
+                Address: data.as_ptr() as _,
+            },
+            data.len() as u32,
+            0,
+            _NT_IORING_OP_FLAGS_NT_IORING_OP_FLAG_NONE,
+        )
+        .build()
+        .user_data(3);
+        unsafe { ring.submission().push(&write).unwrap() };
+        ring.submit().unwrap();
+
+        // The second read is both truncated and failed, and the first rule wins.
+        assert_eq!(completed(&mut ring), [(1, 0, 3), (2, 0, 3), (3, 0, 1)]);
+        assert_eq!(&bufs[0][..4], b"012\0");
+        assert_eq!(std::fs::read(path.path()).unwrap(), b"a123456789");
+        assert_eq!(ring.emulator().unwrap().faults().unwrap().injected(), 3);
+
+        let plan = Plan::new().rule(Rule::new(Action::Fail(END_OF_FILE)).handle(handle).nth(1));
+        ring.emulator().unwrap().set_faults(Some(plan));
+        let flush = opcode::Flush::new(
+            ring.info.0,
+            file_ref,
+            crate::windows::FILE_FLUSH_MODE_FILE_FLUSH_DEFAULT,
+            _NT_IORING_OP_FLAGS_NT_IORING_OP_FLAG_NONE,
+        )
+        .build()
+        .user_data(4);
+        unsafe { ring.submission().push(&flush).unwrap() };
+        push_nops(&mut ring, 5..=5);
+        ring.submit().unwrap();
+        assert_eq!(completed(&mut ring), [(4, END_OF_FILE, 0), (5, 0, 0)]);
+    }
+
+    #[test]
+    fn completions_are_delayed() {
+        let mut ring = IoRing::emulated(8).unwrap();
+        let plan = Plan::new().rule(Rule::new(Action::Delay(2)).nth(1));
+        ring.emulator().unwrap().set_faults(Some(plan));
+        push_nops(&mut ring, 1..=2);
+        ring.submit().unwrap();
+        assert_eq!(completed(&mut ring), [(2, 0, 0)]);
+        assert_eq!(ring.emulator().unwrap().in_flight(), 1);
+        assert_eq!(ring.in_flight(), 1);
+
+        ring.submit().unwrap();
+        assert!(completed(&mut ring).is_empty());
+        ring.submit().unwrap();
+        assert_eq!(completed(&mut ring), [(1, 0, 0)]);
+        assert_eq!(ring.in_flight(), 0);
+    }
+
+    #[test]
+    fn random_faults_follow_the_seed() {
+        let run = |seed| {
+            let mut ring = IoRing::emulated(16).unwrap();
+            let plan = Plan::new()
+                .rule(Rule::new(Action::Fail(OPERATION_ABORTED)).probability(0.5))
+                .reorder(true)
+                .seed(seed);
+            ring.emulator().unwrap().set_faults(Some(plan));
+            push_nops(&mut ring, 1..=16);
+            ring.submit().unwrap();
+            completed(&mut ring)
+        };
+        let first = run(7);
+        assert_eq!(first, run(7));
+        assert_ne!(first, run(8));
+
+        let failed = first.iter().filter(|c| c.1 == OPERATION_ABORTED).count();
+        assert!(0 < failed && failed < 16);
+        let mut user_data: Vec<_> = first.iter().map(|c| c.0).collect();
+        assert_ne!(user_data, (1..=16).collect::<Vec<_>>());
+        user_data.sort_unstable();
+        assert_eq!(user_data, (1..=16).collect::<Vec<_>>());
+    }
+}
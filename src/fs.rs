@@ -0,0 +1,737 @@
+//! Filesystem manipulation operations driven through an [`IoRing`].
+//!
+//! [`File`] wraps a [`std::fs::File`] and performs positional reads, writes and flushes as
+//! ring operations, so callers don't need to build SQEs or match CQEs themselves. [`copy`]
+//! copies a whole file with many reads and writes in flight at once.
+
+use std::io;
+use std::path::Path;
+use std::ptr;
+use std::time::{Duration, Instant};
+
+use crate::cqueue::END_OF_FILE;
+use crate::windows::{
+    FILE_FLUSH_MODE_FILE_FLUSH_DEFAULT, HANDLE, IORING_BUFFER_INFO, IORING_REGISTERED_BUFFER,
+    NT_IORING_BUFFERREF, NT_IORING_HANDLEREF, _NT_IORING_OP_FLAGS,
+    _NT_IORING_OP_FLAGS_NT_IORING_OP_FLAG_NONE,
+    _NT_IORING_OP_FLAGS_NT_IORING_OP_FLAG_REGISTERED_BUFFER,
+    _NT_IORING_OP_FLAGS_NT_IORING_OP_FLAG_REGISTERED_FILE,
+};
+use crate::{cqueue, opcode, squeue, Builder, IoRing};
+
+/// The handle of `file` as seen by a ring: its `HANDLE` on Windows, and its file descriptor
+/// elsewhere, where only the [emulated](crate::emulate) ring can use it.
+pub fn raw_handle(file: &std::fs::File) -> HANDLE {
+    #[cfg(windows)]
+    {
+        use std::os::windows::io::AsRawHandle;
+        file.as_raw_handle() as HANDLE
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::io::AsRawFd;
+        file.as_raw_fd() as isize as HANDLE
+    }
+}
+
+/// An open file on the filesystem whose I/O is submitted to an [`IoRing`].
+///
+/// Every method submits a single operation and waits for its completion. Methods called from
+/// several threads on the same ring take turns, and the completions of the other operations in
+/// flight on the ring meanwhile are kept for its
+/// [`CompletionQueue`](crate::cqueue::CompletionQueue).
+///
+/// # Examples
+///
+/// ```no_run
+/// use ioring_rs::{fs::File, IoRing};
+///
+/// # fn main() -> std::io::Result<()> {
+/// let ring = IoRing::new(32)?;
+/// let file = File::open(&ring, "README.md")?;
+///
+/// let mut buf = [0u8; 32];
+/// file.read_exact_at(&mut buf, 0)?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct File<'a> {
+    ring: &'a IoRing,
+    inner: std::fs::File,
+    registered: Option<u32>,
+}
+
+/// Options and flags which can be used to configure how a [`File`] is opened.
+///
+/// This mirrors [`std::fs::OpenOptions`], with the addition of [`register`](Self::register).
+#[derive(Clone, Debug)]
+pub struct OpenOptions {
+    inner: std::fs::OpenOptions,
+    register: bool,
+}
+
+impl OpenOptions {
+    /// Create a blank new set of options, see [`std::fs::OpenOptions::new`].
+    pub fn new() -> OpenOptions {
+        OpenOptions {
+            inner: std::fs::OpenOptions::new(),
+            register: false,
+        }
+    }
+
+    /// Sets the option for read access.
+    pub fn read(&mut self, read: bool) -> &mut OpenOptions {
+        self.inner.read(read);
+        self
+    }
+
+    /// Sets the option for write access.
+    pub fn write(&mut self, write: bool) -> &mut OpenOptions {
+        self.inner.write(write);
+        self
+    }
+
+    /// Sets the option for the append mode.
+    pub fn append(&mut self, append: bool) -> &mut OpenOptions {
+        self.inner.append(append);
+        self
+    }
+
+    /// Sets the option for truncating a previous file.
+    pub fn truncate(&mut self, truncate: bool) -> &mut OpenOptions {
+        self.inner.truncate(truncate);
+        self
+    }
+
+    /// Sets the option to create a new file, or open it if it already exists.
+    pub fn create(&mut self, create: bool) -> &mut OpenOptions {
+        self.inner.create(create);
+        self
+    }
+
+    /// Sets the option to create a new file, failing if it already exists.
+    pub fn create_new(&mut self, create_new: bool) -> &mut OpenOptions {
+        self.inner.create_new(create_new);
+        self
+    }
+
+    /// Add the file to the ring's registered file table once it is opened, see
+    /// [`File::register`].
+    pub fn register(&mut self, register: bool) -> &mut OpenOptions {
+        self.register = register;
+        self
+    }
+
+    /// Open the file at `path` with the options specified by `self`.
+    pub fn open<'a, P: AsRef<Path>>(&self, ring: &'a IoRing, path: P) -> io::Result<File<'a>> {
+        let mut file = File::from_std(ring, self.inner.open(path)?);
+        if self.register {
+            file.register()?;
+        }
+        Ok(file)
+    }
+}
+
+impl Default for OpenOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a> File<'a> {
+    /// Attempt to open a file in read-only mode.
+    pub fn open<P: AsRef<Path>>(ring: &'a IoRing, path: P) -> io::Result<File<'a>> {
+        OpenOptions::new().read(true).open(ring, path)
+    }
+
+    /// Open a file in write-only mode, creating it if it does not exist and truncating it if it
+    /// does.
+    pub fn create<P: AsRef<Path>>(ring: &'a IoRing, path: P) -> io::Result<File<'a>> {
+        OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(ring, path)
+    }
+
+    /// Wrap an already opened [`std::fs::File`].
+    pub fn from_std(ring: &'a IoRing, file: std::fs::File) -> File<'a> {
+        File {
+            ring,
+            inner: file,
+            registered: None,
+        }
+    }
+
+    /// Add this file to the ring's registered file table. Subsequent operations refer to the
+    /// file by its index instead of its handle.
+    ///
+    /// Registering an already registered file does nothing.
+    pub fn register(&mut self) -> io::Result<u32> {
+        if let Some(index) = self.registered {
+            return Ok(index);
+        }
+        let index = self.ring.register_file(raw_handle(&self.inner))?;
+        self.registered = Some(index);
+        Ok(index)
+    }
+
+    /// The index of this file in the ring's registered file table, if it was registered.
+    pub fn registered_index(&self) -> Option<u32> {
+        self.registered
+    }
+
+    /// Get a reference to the underlying [`std::fs::File`].
+    pub fn get_ref(&self) -> &std::fs::File {
+        &self.inner
+    }
+
+    /// Unwrap the underlying [`std::fs::File`].
+    pub fn into_std(self) -> std::fs::File {
+        self.inner
+    }
+
+    /// Read some bytes starting from `offset`, returning how many bytes were read.
+    ///
+    /// Like [`std::os::windows::fs::FileExt::seek_read`], a return value of `0` means the end of
+    /// the file was reached.
+    pub fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        let (file, flags) = self.handle_ref();
+        let entry = opcode::Read::new(
+            ptr::null_mut(),
+            file,
+            NT_IORING_BUFFERREF {
+                Address: buf.as_mut_ptr() as _,
+            },
+            clamp_len(buf.len()),
+            offset,
+            flags,
+        )
+        .build();
+
+        let cqe = self.submit(entry)?;
+        if cqe.result() == END_OF_FILE {
+            Ok(0)
+        } else {
+            cqe.into_result()
+        }
+    }
+
+    /// Write some bytes starting from `offset`, returning how many bytes were written.
+    pub fn write_at(&self, buf: &[u8], offset: u64) -> io::Result<usize> {
+        let (file, flags) = self.handle_ref();
+        let entry = opcode::Write::new(
+            ptr::null_mut(),
+            file,
+            NT_IORING_BUFFERREF {
+                Address: buf.as_ptr() as _,
+            },
+            clamp_len(buf.len()),
+            offset,
+            flags,
+        )
+        .build();
+
+        self.submit(entry)?.into_result()
+    }
+
+    /// Read the exact number of bytes required to fill `buf` starting from `offset`.
+    ///
+    /// Short reads are retried from where they stopped. If the end of the file is reached
+    /// first, an error of kind [`io::ErrorKind::UnexpectedEof`] is returned.
+    pub fn read_exact_at(&self, mut buf: &mut [u8], mut offset: u64) -> io::Result<()> {
+        while !buf.is_empty() {
+            match self.read_at(buf, offset) {
+                Ok(0) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        "failed to fill whole buffer",
+                    ))
+                }
+                Ok(n) => {
+                    buf = &mut buf[n..];
+                    offset += n as u64;
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    /// Write the whole of `buf` starting from `offset`.
+    ///
+    /// Short writes are retried from where they stopped.
+    pub fn write_all_at(&self, mut buf: &[u8], mut offset: u64) -> io::Result<()> {
+        while !buf.is_empty() {
+            match self.write_at(buf, offset) {
+                Ok(0) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::WriteZero,
+                        "failed to write whole buffer",
+                    ))
+                }
+                Ok(n) => {
+                    buf = &buf[n..];
+                    offset += n as u64;
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    /// Flush all OS-internal metadata and data to disk.
+    pub fn sync_all(&self) -> io::Result<()> {
+        let (file, flags) = self.handle_ref();
+        let entry = opcode::Flush::new(
+            ptr::null_mut(),
+            file,
+            FILE_FLUSH_MODE_FILE_FLUSH_DEFAULT,
+            flags,
+        )
+        .build();
+
+        self.submit(entry)?.into_result().map(drop)
+    }
+
+    fn handle_ref(&self) -> (NT_IORING_HANDLEREF, _NT_IORING_OP_FLAGS) {
+        match self.registered {
+            Some(index) => (
+                NT_IORING_HANDLEREF {
+                    HandleIndex: index as _,
+                },
+                _NT_IORING_OP_FLAGS_NT_IORING_OP_FLAG_REGISTERED_FILE,
+            ),
+            None => (
+                NT_IORING_HANDLEREF {
+                    Handle: raw_handle(&self.inner) as _,
+                },
+                _NT_IORING_OP_FLAGS_NT_IORING_OP_FLAG_NONE,
+            ),
+        }
+    }
+
+    fn submit(&self, entry: squeue::Entry) -> io::Result<cqueue::Entry> {
+        self.ring.submit_one(entry)
+    }
+}
+
+/// A single operation can transfer at most `u32::MAX` bytes; longer buffers are short transfers.
+fn clamp_len(len: usize) -> u32 {
+    len.min(u32::MAX as usize) as u32
+}
+
+/// Options for [`copy`].
+#[derive(Clone, Debug)]
+pub struct CopyOptions {
+    chunk_size: u32,
+    window: u32,
+    flush: bool,
+    register: bool,
+    emulated: bool,
+}
+
+impl CopyOptions {
+    /// Create the default options: 32 KiB chunks, 16 chunks in flight, registered files and
+    /// buffers and no final flush.
+    pub const fn new() -> CopyOptions {
+        CopyOptions {
+            chunk_size: 32 * 1024,
+            window: 16,
+            flush: false,
+            register: true,
+            emulated: false,
+        }
+    }
+
+    /// The size of each read/write pair. Must not be zero.
+    pub const fn chunk_size(mut self, chunk_size: u32) -> Self {
+        self.chunk_size = chunk_size;
+        self
+    }
+
+    /// How many chunks are kept in flight at once. Must not be zero.
+    pub const fn window(mut self, window: u32) -> Self {
+        self.window = window;
+        self
+    }
+
+    /// Flush the destination to disk before returning.
+    pub const fn flush(mut self, flush: bool) -> Self {
+        self.flush = flush;
+        self
+    }
+
+    /// Use registered files and buffers instead of passing handles and addresses with every
+    /// operation.
+    pub const fn register(mut self, register: bool) -> Self {
+        self.register = register;
+        self
+    }
+
+    /// Run the copy on an [emulated](crate::IoRing::emulated) ring, which works where IoRing
+    /// is unavailable.
+    pub const fn emulated(mut self, emulated: bool) -> Self {
+        self.emulated = emulated;
+        self
+    }
+}
+
+impl Default for CopyOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// What a finished [`copy`] did.
+#[derive(Clone, Copy, Debug)]
+pub struct CopyStats {
+    /// The number of bytes copied.
+    pub bytes: u64,
+    /// Wall-clock time taken by the whole copy, including opening the files.
+    pub elapsed: Duration,
+}
+
+impl CopyStats {
+    /// Throughput of the copy, in bytes per second.
+    pub fn throughput(&self) -> f64 {
+        let secs = self.elapsed.as_secs_f64();
+        if secs > 0.0 {
+            self.bytes as f64 / secs
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Copy the contents of one file to another, creating or truncating the destination.
+///
+/// The copy runs on its own ring and keeps up to [`CopyOptions::window`] chunks in flight. Each
+/// chunk is read into its own buffer and written out as soon as the read completes, then the
+/// buffer is reused for the next unread chunk. Short reads and writes are resumed where they
+/// stopped.
+///
+/// # Examples
+///
+/// ```no_run
+/// use ioring_rs::fs::{self, CopyOptions};
+///
+/// # fn main() -> std::io::Result<()> {
+/// let stats = fs::copy("foo.txt", "bar.txt", CopyOptions::new().flush(true))?;
+/// println!("{} bytes at {:.0} B/s", stats.bytes, stats.throughput());
+/// # Ok(())
+/// # }
+/// ```
+pub fn copy<P: AsRef<Path>, Q: AsRef<Path>>(
+    from: P,
+    to: Q,
+    options: CopyOptions,
+) -> io::Result<CopyStats> {
+    if options.chunk_size == 0 || options.window == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "chunk size and window must not be zero",
+        ));
+    }
+    let start = Instant::now();
+
+    let src = std::fs::File::open(from)?;
+    let size = src.metadata()?.len();
+    let dst = std::fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(to)?;
+    dst.set_len(size)?;
+
+    let chunk_size = options.chunk_size as u64;
+    let window = (size.div_ceil(chunk_size)).min(options.window as u64) as u32;
+    let mut copier = Copier {
+        ring: Builder::new(window.max(1).next_power_of_two())
+            .emulated(options.emulated)
+            .build()?,
+        buffers: vec![0u8; window as usize * options.chunk_size as usize],
+        src,
+        dst,
+        files: None,
+        size,
+        chunk_size,
+        next_offset: 0,
+        slots: Vec::with_capacity(window as usize),
+        in_flight: 0,
+        written: 0,
+    };
+    if window > 0 {
+        copier.run(window, options.register)?;
+    }
+    if copier.written < size {
+        // The source shrank while it was copied.
+        copier.dst.set_len(copier.written)?;
+    }
+
+    if options.flush {
+        let (file, flags) = copier.file_ref(DST);
+        let entry = opcode::Flush::new(
+            ptr::null_mut(),
+            file,
+            FILE_FLUSH_MODE_FILE_FLUSH_DEFAULT,
+            flags,
+        )
+        .build();
+        copier.ring.submit_one(entry)?.into_result()?;
+    }
+
+    Ok(CopyStats {
+        bytes: copier.written,
+        elapsed: start.elapsed(),
+    })
+}
+
+const SRC: usize = 0;
+const DST: usize = 1;
+
+/// One buffer of a [`copy`], moving a single chunk from the source to the destination.
+struct Slot {
+    offset: u64,
+    len: u32,
+    /// Bytes of the chunk read into the buffer so far.
+    read: u32,
+    /// Bytes of the chunk written out so far.
+    written: u32,
+}
+
+struct Copier {
+    ring: IoRing,
+    // Declared after the ring, which waits for the operations in flight when it is dropped, so
+    // that the kernel never sees the buffers freed.
+    buffers: Vec<u8>,
+    src: std::fs::File,
+    dst: std::fs::File,
+    /// Indices of the source and destination in the registered file table.
+    files: Option<[u32; 2]>,
+    size: u64,
+    chunk_size: u64,
+    next_offset: u64,
+    slots: Vec<Slot>,
+    in_flight: usize,
+    /// Bytes written to the destination so far.
+    written: u64,
+}
+
+impl Copier {
+    fn run(&mut self, window: u32, register: bool) -> io::Result<()> {
+        if register {
+            let src = self.ring.register_file(raw_handle(&self.src))?;
+            let dst = self.ring.register_file(raw_handle(&self.dst))?;
+            self.files = Some([src, dst]);
+
+            let infos: Vec<IORING_BUFFER_INFO> = self
+                .buffers
+                .chunks_exact_mut(self.chunk_size as usize)
+                .map(|buf| IORING_BUFFER_INFO {
+                    Address: buf.as_mut_ptr() as _,
+                    Length: buf.len() as u32,
+                })
+                .collect();
+            // Safety: `buffers` outlives the ring and is only touched by the kernel.
+            unsafe { self.ring.register_buffers(&infos)? };
+        }
+
+        for index in 0..window as usize {
+            self.slots.push(Slot {
+                offset: 0,
+                len: 0,
+                read: 0,
+                written: 0,
+            });
+            self.next_chunk(index);
+        }
+
+        // Once an operation fails no new ones are queued, but those in flight still own their
+        // buffers and have to be reaped before returning.
+        let mut error = None;
+        while self.in_flight > 0 {
+            let submitted = self.ring.submit_and_wait(1);
+            let cqes: Vec<cqueue::Entry> = self.ring.completion().collect();
+            if let Err(e) = submitted {
+                if error.is_some() && cqes.is_empty() {
+                    // Nothing completes anymore: what is left may never have reached the ring.
+                    // Dropping the ring cancels it and waits for it before the buffers go.
+                    break;
+                }
+                error = error.or(Some(e));
+            }
+            for cqe in cqes {
+                self.in_flight -= 1;
+                let index = (cqe.user_data() >> 1) as usize;
+                let is_write = cqe.user_data() & 1 == 1;
+                let result = match cqe.into_result() {
+                    Err(_) if !is_write && cqe.result() == END_OF_FILE => Ok(0),
+                    result => result,
+                };
+                match result {
+                    Ok(_) if error.is_some() => {}
+                    Ok(n) if is_write => self.on_write(index, n as u32),
+                    Ok(n) => self.on_read(index, n as u32),
+                    Err(e) => error = error.or(Some(e)),
+                }
+            }
+        }
+        match error {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+
+    /// Assign the next unread chunk, if any, to a free slot and start reading it.
+    fn next_chunk(&mut self, index: usize) {
+        if self.next_offset >= self.size {
+            return;
+        }
+        let len = self.chunk_size.min(self.size - self.next_offset) as u32;
+        self.slots[index] = Slot {
+            offset: self.next_offset,
+            len,
+            read: 0,
+            written: 0,
+        };
+        self.next_offset += len as u64;
+        self.queue(index, false);
+    }
+
+    fn on_read(&mut self, index: usize, n: u32) {
+        let slot = &mut self.slots[index];
+        if n == 0 {
+            // The source shrank under us; write out whatever made it into the buffer.
+            slot.len = slot.read;
+        }
+        slot.read += n;
+        if slot.len == 0 {
+            self.next_chunk(index);
+        } else {
+            let is_write = slot.read == slot.len;
+            self.queue(index, is_write);
+        }
+    }
+
+    fn on_write(&mut self, index: usize, n: u32) {
+        let slot = &mut self.slots[index];
+        slot.written += n;
+        self.written += n as u64;
+        if slot.written < slot.len {
+            self.queue(index, true);
+        } else {
+            self.next_chunk(index);
+        }
+    }
+
+    /// Queue the remaining part of the current read or write of slot `index`.
+    fn queue(&mut self, index: usize, is_write: bool) {
+        let slot = &self.slots[index];
+        let done = if is_write { slot.written } else { slot.read };
+        let (file, file_flags) = self.file_ref(if is_write { DST } else { SRC });
+        let (buffer, buffer_flags) = if self.files.is_some() {
+            (
+                NT_IORING_BUFFERREF {
+                    FixedBuffer: IORING_REGISTERED_BUFFER {
+                        BufferIndex: index as u32,
+                        Offset: done,
+                    },
+                },
+                _NT_IORING_OP_FLAGS_NT_IORING_OP_FLAG_REGISTERED_BUFFER,
+            )
+        } else {
+            let start = index * self.chunk_size as usize + done as usize;
+            (
+                NT_IORING_BUFFERREF {
+                    Address: self.buffers[start..].as_mut_ptr() as _,
+                },
+                _NT_IORING_OP_FLAGS_NT_IORING_OP_FLAG_NONE,
+            )
+        };
+        let ring = self.ring.info.0;
+        let len = slot.len - done;
+        let offset = slot.offset + done as u64;
+        let flags = file_flags | buffer_flags;
+        let user_data = (index as u64) << 1 | is_write as u64;
+        if is_write {
+            opcode::Write::new(ring, file, buffer, len, offset, flags)
+                .build()
+                .user_data(user_data);
+        } else {
+            opcode::Read::new(ring, file, buffer, len, offset, flags)
+                .build()
+                .user_data(user_data);
+        }
+        self.in_flight += 1;
+    }
+
+    fn file_ref(&self, which: usize) -> (NT_IORING_HANDLEREF, _NT_IORING_OP_FLAGS) {
+        match self.files {
+            Some(files) => (
+                NT_IORING_HANDLEREF {
+                    HandleIndex: files[which] as _,
+                },
+                _NT_IORING_OP_FLAGS_NT_IORING_OP_FLAG_REGISTERED_FILE,
+            ),
+            None => {
+                let file = if which == SRC { &self.src } else { &self.dst };
+                (
+                    NT_IORING_HANDLEREF {
+                        Handle: raw_handle(file) as _,
+                    },
+                    _NT_IORING_OP_FLAGS_NT_IORING_OP_FLAG_NONE,
+                )
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::File;
+    use crate::testfile::TestFile;
+    use crate::{opcode, IoRing};
+    use std::thread;
+
+    #[test]
+    fn threads_sharing_a_ring_get_their_own_reads() {
+        let contents: Vec<u8> = (0..=255).collect();
+        let path = TestFile::new("fs-threads", &contents);
+        let ring = IoRing::emulated(8).unwrap();
+        let file = File::open(&ring, path.path()).unwrap();
+
+        thread::scope(|scope| {
+            for start in [0, 128] {
+                let file = &file;
+                scope.spawn(move || {
+                    for offset in start..start + 128 {
+                        let mut buf = [0u8; 1];
+                        assert_eq!(file.read_at(&mut buf, offset).unwrap(), 1);
+                        assert_eq!(buf[0], offset as u8);
+                    }
+                });
+            }
+        });
+    }
+
+    #[test]
+    fn other_completions_are_kept_for_the_completion_queue() {
+        let path = TestFile::new("fs-other", b"other");
+        let mut ring = IoRing::emulated(8).unwrap();
+        let nop = opcode::Nop::new(ring.info.0).build().user_data(7);
+        unsafe { ring.submission().push(&nop).unwrap() };
+        ring.submit().unwrap();
+
+        let file = File::open(&ring, path.path()).unwrap();
+        let mut buf = [0u8; 5];
+        file.read_exact_at(&mut buf, 0).unwrap();
+        assert_eq!(&buf, b"other");
+        drop(file);
+
+        let cqes: Vec<_> = ring.completion().map(|cqe| cqe.user_data()).collect();
+        assert_eq!(cqes, [7]);
+    }
+}
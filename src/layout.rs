@@ -0,0 +1,273 @@
+//! Layout of the ring ABI, checked against the offsets and `static_assert`s of `ioringnt.h`.
+//!
+//! The header describes one layout for x64 and x86: pointers live in `PadX86_*` unions and every
+//! 64-bit field is 8-aligned, so the numbers below hold on 32-bit targets too. The only exception
+//! is `IORING_BUFFER_INFO`, whose address is a bare pointer and which follows the pointer width.
+
+use std::mem::{align_of, offset_of, size_of};
+
+use crate::windows::*;
+
+/// Assert the size and alignment of `$ty`, and the offsets of the listed (possibly nested)
+/// fields, naming the first one that is off.
+macro_rules! assert_layout {
+    ($ty:ty, size = $size:expr, align = $align:expr $(, $($field:ident).+ = $offset:expr)* $(,)?) => {
+        assert_eq!(size_of::<$ty>(), $size, "size of {}", stringify!($ty));
+        assert_eq!(align_of::<$ty>(), $align, "alignment of {}", stringify!($ty));
+        $(
+            assert_eq!(
+                offset_of!($ty, $($field).+),
+                $offset,
+                "offset of {}::{}",
+                stringify!($ty),
+                stringify!($($field).+)
+            );
+        )*
+    };
+}
+
+#[test]
+fn references() {
+    assert_layout!(
+        NT_IORING_HANDLEREF,
+        size = 0x8,
+        align = 8,
+        Handle = 0x0,
+        HandleIndex = 0x0,
+    );
+    assert_layout!(
+        IORING_REGISTERED_BUFFER,
+        size = 0x8,
+        align = 4,
+        BufferIndex = 0x0,
+        Offset = 0x4,
+    );
+    assert_layout!(
+        NT_IORING_BUFFERREF,
+        size = 0x8,
+        align = 8,
+        Address = 0x0,
+        FixedBuffer = 0x0,
+    );
+}
+
+#[test]
+fn buffer_info() {
+    // Not padded like the NT structures: the address is pointer sized, and so is the alignment.
+    let pointer = size_of::<usize>();
+    assert_layout!(
+        IORING_BUFFER_INFO,
+        size = 2 * pointer,
+        align = align_of::<usize>(),
+        Address = 0x0,
+        Length = pointer,
+    );
+}
+
+#[test]
+fn read_and_write() {
+    assert_layout!(
+        NT_IORING_OP_READ,
+        size = 0x28,
+        align = 8,
+        CommonOpFlags = 0x0,
+        Padding = 0x4,
+        File = 0x8,
+        Buffer = 0x10,
+        Offset = 0x18,
+        Length = 0x20,
+        Key = 0x24,
+    );
+    assert_layout!(
+        NT_IORING_OP_WRITE,
+        size = 0x28,
+        align = 8,
+        CommonOpFlags = 0x0,
+        Flags = 0x4,
+        File = 0x8,
+        Buffer = 0x10,
+        Offset = 0x18,
+        Length = 0x20,
+        Key = 0x24,
+    );
+}
+
+#[test]
+fn register_files() {
+    assert_layout!(
+        NT_IORING_REG_FILES_FLAGS,
+        size = 0x8,
+        align = 4,
+        Required = 0x0,
+        Advisory = 0x4,
+    );
+    assert_layout!(
+        NT_IORING_OP_REGISTER_FILES,
+        size = 0x18,
+        align = 8,
+        CommonOpFlags = 0x0,
+        Flags = 0x4,
+        Count = 0xc,
+        __bindgen_anon_1.Handles = 0x10,
+        __bindgen_anon_1.PadX86_Handles = 0x10,
+    );
+}
+
+#[test]
+fn register_buffers() {
+    assert_layout!(
+        NT_IORING_REG_BUFFERS_FLAGS,
+        size = 0x8,
+        align = 4,
+        Required = 0x0,
+        Advisory = 0x4,
+    );
+    assert_layout!(
+        NT_IORING_OP_REGISTER_BUFFERS,
+        size = 0x18,
+        align = 8,
+        CommonOpFlags = 0x0,
+        Flags = 0x4,
+        Count = 0xc,
+        __bindgen_anon_1.Buffers = 0x10,
+        __bindgen_anon_1.PadX86_Buffers = 0x10,
+    );
+}
+
+#[test]
+fn cancel_and_flush() {
+    assert_layout!(
+        NT_IORING_OP_CANCEL,
+        size = 0x18,
+        align = 8,
+        CommonOpFlags = 0x0,
+        File = 0x8,
+        CancelId = 0x10,
+    );
+    assert_layout!(
+        NT_IORING_OP_FLUSH,
+        size = 0x10,
+        align = 8,
+        CommonOpFlags = 0x0,
+        FlushMode = 0x4,
+        File = 0x8,
+    );
+}
+
+#[test]
+fn submission_entry() {
+    assert_layout!(
+        NT_IORING_OP_RESERVED,
+        size = 0x30,
+        align = 8,
+        Argument1 = 0x0,
+        Argument2 = 0x8,
+        Argument3 = 0x10,
+        Argument4 = 0x18,
+        Argument5 = 0x20,
+        Argument6 = 0x28,
+    );
+    assert_layout!(
+        NT_IORING_SQE,
+        size = 0x40,
+        align = 8,
+        OpCode = 0x0,
+        Flags = 0x4,
+        UserData = 0x8,
+        __bindgen_anon_1.Read = 0x10,
+        __bindgen_anon_1.RegisterFiles = 0x10,
+        __bindgen_anon_1.RegisterBuffers = 0x10,
+        __bindgen_anon_1.Cancel = 0x10,
+        __bindgen_anon_1.Write = 0x10,
+        __bindgen_anon_1.Flush = 0x10,
+        __bindgen_anon_1.ReservedMaxSizePadding = 0x10,
+    );
+    // No operation may grow the entry past the reserved padding.
+    assert_eq!(size_of::<_NT_IORING_SQE__bindgen_ty_1>(), 0x30);
+}
+
+#[test]
+fn completion_entry() {
+    assert_layout!(
+        NT_IORING_CQE,
+        size = 0x18,
+        align = 8,
+        UserData = 0x0,
+        __bindgen_anon_1.ResultCode = 0x8,
+        __bindgen_anon_1.Pointer = 0x8,
+        __bindgen_anon_1.PadX86_Pointer = 0x8,
+        Information = 0x10,
+    );
+    assert_eq!(size_of::<_NT_IORING_CQE__bindgen_ty_1>(), 0x8);
+}
+
+#[test]
+fn queues() {
+    assert_layout!(
+        NT_IORING_SUBMISSION_QUEUE,
+        size = 0x10,
+        align = 8,
+        Head = 0x0,
+        Tail = 0x4,
+        Flags = 0x8,
+        Entries = 0x10,
+    );
+    assert_layout!(
+        NT_IORING_COMPLETION_QUEUE,
+        size = 0x8,
+        align = 8,
+        Head = 0x0,
+        Tail = 0x4,
+        Entries = 0x8,
+    );
+}
+
+#[test]
+fn info() {
+    assert_layout!(
+        NT_IORING_CREATE_FLAGS,
+        size = 0x8,
+        align = 4,
+        Required = 0x0,
+        Advisory = 0x4,
+    );
+    assert_layout!(
+        NT_IORING_INFO,
+        size = 0x30,
+        align = 8,
+        IoRingVersion = 0x0,
+        Flags = 0x4,
+        SubmissionQueueSize = 0xc,
+        SubmissionQueueRingMask = 0x10,
+        CompletionQueueSize = 0x14,
+        CompletionQueueRingMask = 0x18,
+        __bindgen_anon_1.SubmissionQueue = 0x20,
+        __bindgen_anon_1.PadX86_SubmissionQueue = 0x20,
+        __bindgen_anon_2.CompletionQueue = 0x28,
+        __bindgen_anon_2.PadX86_CompletionQueue = 0x28,
+    );
+    assert_eq!(size_of::<NT_IORING_INFO_CLASS>(), 4);
+}
+
+#[test]
+fn create_arguments() {
+    assert_layout!(
+        NT_IORING_STRUCTV1,
+        size = 0x14,
+        align = 4,
+        IoRingVersion = 0x0,
+        SubmissionQueueSize = 0x4,
+        CompletionQueueSize = 0x8,
+        Flags = 0xc,
+    );
+    assert_layout!(
+        NT_IORING_CAPABILITIES,
+        size = 0x14,
+        align = 4,
+        IoRingVersion = 0x0,
+        MaxOpCode = 0x4,
+        FlagsSupported = 0x8,
+        SubmissionQueueSize = 0xc,
+        CompletionQueueSize = 0x10,
+    );
+}
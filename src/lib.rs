@@ -1,21 +1,257 @@
 #![allow(clippy::uninit_assumed_init)]
 #[macro_use]
 pub mod squeue;
+pub mod completions;
 pub mod cqueue;
+pub mod emulate;
+pub mod fault;
+pub mod fs;
+#[cfg(test)]
+mod layout;
 pub mod opcode;
+pub mod pool;
+pub mod record;
+mod ring;
+pub mod stats;
 pub mod submit;
+#[cfg(test)]
+mod testfile;
+mod timer;
+mod trace;
 pub mod windows;
+#[cfg(not(windows))]
+mod winring;
 
-use crate::windows::{win_ring, win_ring_peek_cqe, win_ring_queue_exit, win_ring_queue_init_ref};
+use crate::windows::{
+    win_ring, win_ring_peek_cqe, win_ring_queue_exit, win_ring_queue_init, HANDLE,
+    IORING_BUFFER_INFO, NTSTATUS, NT_IORING_HANDLEREF, _NT_IORING_INFO, _NT_IORING_OP_FLAGS,
+    _NT_IORING_OP_FLAGS_NT_IORING_OP_FLAG_NONE, _NT_IORING_REG_BUFFERS_FLAGS,
+    _NT_IORING_REG_FILES_FLAGS,
+};
 use cqueue::CompletionQueue;
+use ring::Indices;
 use squeue::SubmissionQueue;
-use std::io;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt::{self, Display, Formatter};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+use std::{io, mem, ptr};
 use submit::Submitter;
 
+/// `user_data` of the operations submitted by [`IoRing::submit_one`].
+pub(crate) const SUBMIT_ONE_USER_DATA: u64 = u64::MAX - 1;
+
+/// How long dropping a ring waits for the operations it cancels.
+const DROP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Set by `HRESULT_FROM_NT` on the `NTSTATUS` it wraps.
+const FACILITY_NT_BIT: i32 = 0x1000_0000;
+
+const STATUS_NOT_IMPLEMENTED: NTSTATUS = 0xC000_0002_u32 as i32;
+const STATUS_INVALID_PARAMETER: NTSTATUS = 0xC000_000D_u32 as i32;
+const STATUS_NOT_SUPPORTED: NTSTATUS = 0xC000_00BB_u32 as i32;
+const STATUS_IORING_REQUIRED_FLAGS_NOT_SUPPORTED: NTSTATUS = 0xC000_0516_u32 as i32;
+const STATUS_IORING_VERSION_NOT_SUPPORTED: NTSTATUS = 0xC000_0518_u32 as i32;
+const STATUS_IORING_SUBMISSION_QUEUE_TOO_BIG: NTSTATUS = 0xC000_0519_u32 as i32;
+const STATUS_IORING_COMPLETION_QUEUE_TOO_BIG: NTSTATUS = 0xC000_051A_u32 as i32;
+
 pub struct IoRing {
     sq: squeue::Inner,
     cq: cqueue::Inner,
     pub info: Info,
+    /// The ring's registered file table, see [`IoRing::register_file`].
+    files: Mutex<Vec<HANDLE>>,
+    /// Whether a buffer table is registered, see [`IoRing::register_buffers`].
+    buffers: AtomicBool,
+    shared: Box<Shared>,
+}
+
+/// What [`IoRing::shutdown`] does with the operations in flight.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ShutdownMode {
+    /// Submit everything that has been queued, and wait for all of it to complete.
+    Drain,
+    /// Drop what has not been handed over to the kernel yet, and cancel the rest.
+    Cancel,
+}
+
+/// State kept in user space on behalf of the ring, shared by the submission and completion
+/// queues. It is boxed so that the queues can keep a pointer to it while the ring moves.
+#[derive(Default)]
+pub(crate) struct Shared {
+    /// The rest of each pending [`Chain`](squeue::Chain), keyed by the `user_data` of the
+    /// operation it is waiting on.
+    pub(crate) chains: Mutex<HashMap<u64, VecDeque<squeue::Link>>>,
+    /// Completions produced in user space, handed out by the completion queue before the
+    /// kernel's. [`Notifier`](cqueue::Notifier)s hold a weak reference to it.
+    pub(crate) synthetic: Arc<Mutex<VecDeque<cqueue::Entry>>>,
+    /// Completions taken out of the completion queue by [`IoRing::submit_one`] while it looked
+    /// for its own, handed out next.
+    pub(crate) deferred: Mutex<VecDeque<cqueue::Entry>>,
+    /// Held by [`IoRing::submit_one`] from the moment it queues its entry until it has reaped
+    /// the completion, so that only one such operation is in flight at a time.
+    pub(crate) submit_one: Mutex<()>,
+    /// Entries waiting for room in the submission queue, see [`IoRing::set_backlog`].
+    pub(crate) backlog: Mutex<squeue::Backlog>,
+    /// The number of times an entry was held back for lack of room in the completion queue.
+    pub(crate) overflow: AtomicU64,
+    /// Entries queued by the [`SharedSubmitter`](submit::SharedSubmitter)s of the ring.
+    pub(crate) staging: submit::Staging,
+    /// The files that operations have been pushed on since the ring was last idle, as raw
+    /// handles or indices with their `NT_IORING_OP_FLAG_REGISTERED_FILE` flag, so that
+    /// [`ShutdownMode::Cancel`] knows what to cancel.
+    pub(crate) targets: Mutex<HashSet<(u64, _NT_IORING_OP_FLAGS)>>,
+    /// Where the operations are written, see [`IoRing::set_recorder`].
+    pub(crate) recorder: Mutex<Option<record::Recorder>>,
+    /// Counters and timestamps behind [`IoRing::stats`].
+    pub(crate) stats: Mutex<stats::Recorder>,
+    /// Deadlines of the operations pushed with a [timeout](squeue::Entry::timeout).
+    pub(crate) timers: Mutex<timer::TimerWheel>,
+    /// Locked by a thread while it submits, and released while it waits in the kernel, during
+    /// which it holds the number of entries hidden from the kernel. See
+    /// [`Submitter::wake`].
+    pub(crate) waiting: Mutex<Option<u32>>,
+    /// The backend of an [emulated](IoRing::emulated) ring, which owns the queues.
+    pub(crate) emulator: Option<Mutex<emulate::Emulator>>,
+}
+
+/// Why [`IoRing::new`] could not create a ring.
+///
+/// It converts into an [`io::Error`] of the matching kind, from which it can be recovered with
+/// [`get_ref`](io::Error::get_ref) and `downcast_ref`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Error {
+    /// This version of Windows has no I/O rings, or not the version the crate asks for, which
+    /// appeared in Windows 11 22H2. [`IoRing::emulated`] is always available.
+    Unsupported(NTSTATUS),
+    /// The kernel refused the parameters of the ring, such as a queue size out of range.
+    InvalidParameter(NTSTATUS),
+    /// `NtCreateIoRing` failed with another status.
+    Status(NTSTATUS),
+    /// The kernel created a ring whose queues cannot be used.
+    InvalidInfo(&'static str),
+}
+
+impl Error {
+    fn from_status(status: NTSTATUS) -> Error {
+        match status {
+            STATUS_NOT_IMPLEMENTED
+            | STATUS_NOT_SUPPORTED
+            | STATUS_IORING_REQUIRED_FLAGS_NOT_SUPPORTED
+            | STATUS_IORING_VERSION_NOT_SUPPORTED => Error::Unsupported(status),
+            STATUS_INVALID_PARAMETER
+            | STATUS_IORING_SUBMISSION_QUEUE_TOO_BIG
+            | STATUS_IORING_COMPLETION_QUEUE_TOO_BIG => Error::InvalidParameter(status),
+            _ => Error::Status(status),
+        }
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Unsupported(status) => write!(
+                f,
+                "IoRing is not available (status {:#010x}), see IoRing::emulated",
+                status
+            ),
+            Error::InvalidParameter(status) => {
+                write!(f, "invalid IoRing parameters (status {:#010x})", status)
+            }
+            Error::Status(status) => write!(f, "NtCreateIoRing failed (status {:#010x})", status),
+            Error::InvalidInfo(reason) => write!(f, "unusable IoRing: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<Error> for io::Error {
+    fn from(err: Error) -> io::Error {
+        let kind = match err {
+            Error::Unsupported(_) => io::ErrorKind::Unsupported,
+            Error::InvalidParameter(_) => io::ErrorKind::InvalidInput,
+            Error::Status(_) => io::ErrorKind::Other,
+            Error::InvalidInfo(_) => io::ErrorKind::InvalidData,
+        };
+        io::Error::new(kind, err)
+    }
+}
+
+/// Configuration for creating an [`IoRing`]: its size, its backend, and the resources registered
+/// with it. A [`RingPool`](pool::RingPool) creates all of its rings from the same one.
+#[derive(Clone, Debug)]
+pub struct Builder {
+    entries: u32,
+    emulated: bool,
+    backlog: bool,
+    files: Vec<HANDLE>,
+    buffers: Vec<IORING_BUFFER_INFO>,
+}
+
+// The handles and buffers are only registered by `build`, whose caller vouched for them.
+unsafe impl Send for Builder {}
+unsafe impl Sync for Builder {}
+
+impl Builder {
+    /// Start configuring a ring with a submission queue of `entries`, see [`IoRing::new`].
+    pub fn new(entries: u32) -> Builder {
+        Builder {
+            entries,
+            emulated: false,
+            backlog: false,
+            files: Vec::new(),
+            buffers: Vec::new(),
+        }
+    }
+
+    /// Perform the operations in user space, see [`IoRing::emulated`].
+    pub fn emulated(mut self, emulated: bool) -> Builder {
+        self.emulated = emulated;
+        self
+    }
+
+    /// Enable the backlog, see [`IoRing::set_backlog`].
+    pub fn backlog(mut self, backlog: bool) -> Builder {
+        self.backlog = backlog;
+        self
+    }
+
+    /// Register `files`, in that order, see [`IoRing::register_file`].
+    pub fn files(mut self, files: &[HANDLE]) -> Builder {
+        self.files = files.to_vec();
+        self
+    }
+
+    /// Register `buffers`, see [`IoRing::register_buffers`].
+    ///
+    /// # Safety
+    ///
+    /// The buffers must meet the requirements of [`IoRing::register_buffers`] for every ring
+    /// built, and every ring using them at once must not use a buffer for two operations at once.
+    pub unsafe fn buffers(mut self, buffers: &[IORING_BUFFER_INFO]) -> Builder {
+        self.buffers = buffers.to_vec();
+        self
+    }
+
+    /// Create a ring with this configuration.
+    pub fn build(&self) -> io::Result<IoRing> {
+        let ring = if self.emulated {
+            IoRing::emulated(self.entries)?
+        } else {
+            IoRing::new(self.entries)?
+        };
+        ring.set_backlog(self.backlog);
+        for &file in &self.files {
+            ring.register_file(file)?;
+        }
+        if !self.buffers.is_empty() {
+            unsafe { ring.register_buffers(&self.buffers)? };
+        }
+        Ok(ring)
+    }
 }
 
 /// The Info that were used to construct an [`IoRing`].
@@ -31,19 +267,72 @@ impl IoRing {
     ///
     /// The `entries` sets the size of queue,
     /// and its value should be the power of two.
-    pub fn new(entries: u32) -> std::io::Result<IoRing> {
-        let ring = unsafe { win_ring_queue_init_ref(entries) };
-        unsafe fn setup_queue(p: *mut win_ring) -> io::Result<(squeue::Inner, cqueue::Inner)> {
-            let sq = squeue::Inner::new(p);
-            let cq = cqueue::Inner::new(p);
+    ///
+    /// This fails with [`Error::Unsupported`] before Windows 11 22H2, and everywhere else than
+    /// on Windows.
+    pub fn new(entries: u32) -> Result<IoRing, Error> {
+        let ring = Box::into_raw(Box::new(unsafe { mem::zeroed::<win_ring>() }));
+        let hr = unsafe { win_ring_queue_init(entries, ring) };
+        if hr < 0 {
+            drop(unsafe { Box::from_raw(ring) });
+            return Err(Error::from_status(hr & !FACILITY_NT_BIT));
+        }
+        unsafe { IoRing::from_raw(ring, Box::default()) }.inspect_err(|_| unsafe {
+            win_ring_queue_exit(ring);
+            drop(Box::from_raw(ring));
+        })
+    }
+
+    /// Create an `IoRing` whose operations are performed in user space by an
+    /// [`Emulator`](emulate::Emulator) instead of the kernel. It is available on every platform.
+    ///
+    /// The `entries` sets the size of queue and is rounded up to a power of two.
+    pub fn emulated(entries: u32) -> io::Result<IoRing> {
+        let mut emulator = emulate::Emulator::new(entries)?;
+        let ring = emulator.ring();
+        let shared = Box::new(Shared {
+            emulator: Some(Mutex::new(emulator)),
+            ..Shared::default()
+        });
+        Ok(unsafe { IoRing::from_raw(ring, shared) }?)
+    }
+
+    unsafe fn from_raw(ring: *mut win_ring, shared: Box<Shared>) -> Result<IoRing, Error> {
+        unsafe fn setup_queue(
+            p: *mut win_ring,
+            shared: *const Shared,
+        ) -> Result<(squeue::Inner, cqueue::Inner), Error> {
+            validate(&(*p).info)?;
+            let sq = squeue::Inner::new(p, shared);
+            let cq = cqueue::Inner::new(p, shared);
 
             Ok((sq, cq))
         }
-        let (sq, cq) = unsafe { setup_queue(ring)? };
+        /// Check that the queues can be indexed with their masks.
+        unsafe fn validate(info: &_NT_IORING_INFO) -> Result<(), Error> {
+            if info.__bindgen_anon_1.SubmissionQueue.is_null() {
+                return Err(Error::InvalidInfo("no submission queue"));
+            }
+            if info.__bindgen_anon_2.CompletionQueue.is_null() {
+                return Err(Error::InvalidInfo("no completion queue"));
+            }
+            let ring_mask = |size: u32| size.is_power_of_two().then(|| size - 1);
+            if ring_mask(info.SubmissionQueueSize) != Some(info.SubmissionQueueRingMask) {
+                return Err(Error::InvalidInfo("bad submission queue mask"));
+            }
+            if ring_mask(info.CompletionQueueSize) != Some(info.CompletionQueueRingMask) {
+                return Err(Error::InvalidInfo("bad completion queue mask"));
+            }
+            Ok(())
+        }
+        let (sq, cq) = setup_queue(ring, &*shared)?;
         Ok(IoRing {
             sq,
             cq,
-            info: Info(unsafe { &mut *ring }),
+            info: Info(ring),
+            files: Mutex::new(Vec::new()),
+            buffers: AtomicBool::new(false),
+            shared,
         })
     }
 
@@ -53,6 +342,15 @@ impl IoRing {
         &self.info
     }
 
+    /// Get the [`Emulator`](emulate::Emulator) performing the operations of an
+    /// [emulated](IoRing::emulated) ring, or `None` if the kernel does.
+    pub fn emulator(&self) -> Option<MutexGuard<'_, emulate::Emulator>> {
+        self.shared
+            .emulator
+            .as_ref()
+            .map(|emulator| emulator.lock().unwrap())
+    }
+
     /// Initiate asynchronous I/O. See [`Submitter::submit`] for more details.
     #[inline]
     pub fn submit(&self) -> io::Result<usize> {
@@ -63,8 +361,86 @@ impl IoRing {
     /// details.
     #[inline]
     pub fn submit_and_wait(&self, want: u32) -> io::Result<usize> {
-        self.submitter()
-            .submit_and_wait(want, std::u32::MAX as usize)
+        self.submitter().submit_and_wait(want, u32::MAX as usize)
+    }
+
+    /// Enable or disable the backlog, which is off by default.
+    ///
+    /// With the backlog enabled, entries [pushed](SubmissionQueue::push) while the submission
+    /// queue is full wait in user space instead of failing with a
+    /// [`PushError`](squeue::PushError), and every submit moves them into the queue as room is
+    /// made, submitting as many times as it takes. They keep their order among themselves, but
+    /// entries built while the queue has room go straight in and may overtake them.
+    ///
+    /// Disabling the backlog does not drop the entries already waiting.
+    pub fn set_backlog(&self, enabled: bool) {
+        self.shared.backlog.lock().unwrap().enabled = enabled;
+    }
+
+    /// Start writing every entry pushed to the ring and every completion reaped from it to
+    /// `recorder`, or stop with `None`. The recorder in use before is returned, to be
+    /// [finished](record::Recorder::finish). See the [`record`] module for more details.
+    pub fn set_recorder(&self, recorder: Option<record::Recorder>) -> Option<record::Recorder> {
+        mem::replace(&mut *self.shared.recorder.lock().unwrap(), recorder)
+    }
+
+    /// Get metrics on the backlog, see [`set_backlog`](Self::set_backlog).
+    pub fn backlog_stats(&self) -> squeue::BacklogStats {
+        self.shared.backlog.lock().unwrap().stats()
+    }
+
+    /// Get counts and latencies of the operations submitted so far, and the occupancy of the
+    /// queues. See the [`stats`] module for more details.
+    pub fn stats(&self) -> stats::Stats {
+        let mut stats = self.shared.stats.lock().unwrap().snapshot();
+        unsafe {
+            let info = &(*self.info.0).info;
+            stats.sq_len = Indices::submission(self.info.0).len() as usize;
+            stats.sq_capacity = info.SubmissionQueueSize as usize;
+            stats.cq_len = Indices::completion(self.info.0).len() as usize;
+            stats.cq_capacity = info.CompletionQueueSize as usize;
+        }
+        stats
+    }
+
+    /// The number of operations submitted whose completion has not been reaped yet. See
+    /// [`Submitter::in_flight`] for more details.
+    #[inline]
+    pub fn in_flight(&self) -> u32 {
+        self.submitter().in_flight()
+    }
+
+    /// The number of times a submit held an entry back because the completion queue might not
+    /// have had room for its completion. Such entries stay queued and are submitted once enough
+    /// completions have been reaped.
+    pub fn overflow(&self) -> u64 {
+        self.shared.overflow.load(Ordering::Relaxed)
+    }
+
+    /// Get a [`SharedSubmitter`](submit::SharedSubmitter), through which other threads can queue
+    /// operations on the ring.
+    pub fn shared_submitter(&self) -> submit::SharedSubmitter {
+        self.shared.staging.submitter()
+    }
+
+    /// Wake up the thread waiting in [`submit_and_wait`](Self::submit_and_wait), if any. See
+    /// [`Submitter::wake`] for more details.
+    #[inline]
+    pub fn wake(&self) -> io::Result<()> {
+        self.submitter().wake()
+    }
+
+    /// Get a [`Notifier`](cqueue::Notifier), through which other threads can post completions to
+    /// the ring.
+    pub fn notifier(&self) -> cqueue::Notifier {
+        cqueue::Notifier::new(&self.shared.synthetic)
+    }
+
+    /// Cancel every operation in flight on `handle`. See [`Submitter::cancel_all_for`] for more
+    /// details.
+    #[inline]
+    pub fn cancel_all_for(&self, handle: HANDLE) -> io::Result<()> {
+        self.submitter().cancel_all_for(handle)
     }
 
     #[inline]
@@ -73,8 +449,8 @@ impl IoRing {
             Submitter {
                 fd: &(*self.info.0).handle,
                 info: &self.info,
-                sq_head: std::mem::transmute(&self.sq.sqes.as_ref().unwrap().Head as *const u32),
-                sq_tail: std::mem::transmute(&self.sq.sqes.as_ref().unwrap().Tail as *const u32),
+                shared: &self.shared,
+                sq: Indices::submission(self.info.0),
             }
         }
     }
@@ -90,8 +466,8 @@ impl IoRing {
             let submit = Submitter::new(
                 &(*self.info.0).handle,
                 &self.info,
-                std::mem::transmute(&self.sq.sqes.as_ref().unwrap().Head as *const _),
-                std::mem::transmute(&self.sq.sqes.as_ref().unwrap().Tail as *const _),
+                &self.shared,
+                Indices::submission(self.info.0),
             );
             (submit, self.sq.borrow(), self.cq.borrow())
         }
@@ -127,27 +503,270 @@ impl IoRing {
     ///
     /// No other [`CompletionQueue`]s may exist when calling this function.
     #[inline]
-    pub unsafe fn completion_shared(&mut self) -> CompletionQueue<'_> {
+    pub unsafe fn completion_shared(&self) -> CompletionQueue<'_> {
         self.cq.borrow_shared()
     }
 
     /// New for win_ring_cqe.
+    ///
+    /// # Safety
+    ///
+    /// No [`CompletionQueue`] may be consuming entries at the same time.
     #[inline]
     pub unsafe fn peek_cqe(&mut self) -> Option<cqueue::Entry> {
         let ring_cqe = win_ring_peek_cqe(self.info.0);
         if ring_cqe.is_null() {
             None
         } else {
-            Some(cqueue::Entry(ring_cqe))
+            Some(cqueue::Entry(*ring_cqe))
+        }
+    }
+
+    /// Add `handle` to the ring's registered file table and return its index, which can be used
+    /// with [`NT_IORING_OP_FLAG_REGISTERED_FILE`](crate::windows::_NT_IORING_OP_FLAGS_NT_IORING_OP_FLAG_REGISTERED_FILE).
+    ///
+    /// The whole table is registered again each time, so every previously returned index stays
+    /// valid. Like the other synchronous helpers, this waits for its own completion. The
+    /// completions of the other operations in flight meanwhile are kept for the
+    /// [`CompletionQueue`].
+    pub fn register_file(&self, handle: HANDLE) -> io::Result<u32> {
+        let mut files = self.files.lock().unwrap();
+        files.push(handle);
+        let entry = opcode::RegisterFiles::new(
+            ptr::null_mut(),
+            files.as_ptr(),
+            files.len() as u32,
+            _NT_IORING_REG_FILES_FLAGS {
+                Required: 0,
+                Advisory: 0,
+            },
+            _NT_IORING_OP_FLAGS_NT_IORING_OP_FLAG_NONE,
+        )
+        .build();
+        match self.submit_one(entry).and_then(|cqe| cqe.into_result()) {
+            Ok(_) => Ok(files.len() as u32 - 1),
+            Err(e) => {
+                files.pop();
+                Err(e)
+            }
+        }
+    }
+
+    /// Register `buffers` as the ring's registered buffer table, replacing any previous one.
+    /// Entries are referred to by their position with
+    /// [`NT_IORING_OP_FLAG_REGISTERED_BUFFER`](crate::windows::_NT_IORING_OP_FLAGS_NT_IORING_OP_FLAG_REGISTERED_BUFFER).
+    ///
+    /// Like the other synchronous helpers, this waits for its own completion. The completions of
+    /// the other operations in flight meanwhile are kept for the [`CompletionQueue`].
+    ///
+    /// # Safety
+    ///
+    /// Every buffer must stay valid, and must not be accessed through any other path while an
+    /// operation is using it, until the table is replaced or the ring is dropped.
+    pub unsafe fn register_buffers(&self, buffers: &[IORING_BUFFER_INFO]) -> io::Result<()> {
+        let entry = opcode::RegisterBuffers::new(
+            ptr::null_mut(),
+            buffers.as_ptr(),
+            buffers.len() as u32,
+            _NT_IORING_REG_BUFFERS_FLAGS {
+                Required: 0,
+                Advisory: 0,
+            },
+            _NT_IORING_OP_FLAGS_NT_IORING_OP_FLAG_NONE,
+        )
+        .build();
+        self.submit_one(entry)?.into_result()?;
+        self.buffers.store(!buffers.is_empty(), Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Shut the ring down: deal with the operations in flight according to `mode`, unregister
+    /// its files and buffers, and close it.
+    ///
+    /// Completions that come in meanwhile are discarded. If operations are still in flight
+    /// after `timeout`, this fails with [`TimedOut`](io::ErrorKind::TimedOut), and the ring is
+    /// dropped, which cancels them.
+    ///
+    /// Dropping a ring does the same as shutting it down with [`ShutdownMode::Cancel`], waiting
+    /// a few seconds at most, so that the kernel is done with the buffers of its operations by
+    /// the time they are freed.
+    pub fn shutdown(mut self, mode: ShutdownMode, timeout: Duration) -> io::Result<()> {
+        self.quiesce(mode, Instant::now() + timeout)?;
+        self.release()
+    }
+
+    /// Wait until nothing is in flight anymore, see [`shutdown`](Self::shutdown).
+    fn quiesce(&mut self, mode: ShutdownMode, deadline: Instant) -> io::Result<()> {
+        if mode == ShutdownMode::Cancel {
+            self.submitter().abandon();
+            let targets = mem::take(&mut *self.shared.targets.lock().unwrap());
+            for (handle, flags) in targets {
+                let file = NT_IORING_HANDLEREF {
+                    Handle: handle as _,
+                };
+                while self.submitter().queue_cancel(file, flags, 0).is_err() {
+                    // The submission queue is full of cancels, hand them over.
+                    self.completion().for_each(drop);
+                    self.submit()?;
+                }
+            }
+        }
+        loop {
+            self.completion().for_each(drop);
+            if self.submitter().is_idle() {
+                return Ok(());
+            }
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "operations are still in flight",
+                ));
+            }
+            let left = (left.as_micros() as u64).div_ceil(1000);
+            self.submitter().submit_and_wait(1, left as usize)?;
+        }
+    }
+
+    /// Unregister the files and buffers of the ring.
+    fn release(&mut self) -> io::Result<()> {
+        let files = mem::take(self.files.get_mut().unwrap());
+        if !files.is_empty() {
+            let entry = opcode::RegisterFiles::new(
+                ptr::null_mut(),
+                files.as_ptr(),
+                0,
+                _NT_IORING_REG_FILES_FLAGS {
+                    Required: 0,
+                    Advisory: 0,
+                },
+                _NT_IORING_OP_FLAGS_NT_IORING_OP_FLAG_NONE,
+            )
+            .build();
+            self.submit_one(entry)?.into_result()?;
+        }
+        if self.buffers.load(Ordering::Relaxed) {
+            unsafe { self.register_buffers(&[])? };
+        }
+        Ok(())
+    }
+
+    /// Submit `entry`, which must have been built with a null ring, and wait for its
+    /// completion.
+    ///
+    /// Calls from several threads take turns. The completions of the other operations in flight
+    /// are set aside for the completion queue to hand out. Once the entry is queued, this only
+    /// returns after its completion has been reaped, even if submitting fails, so that the
+    /// kernel is done with the buffers it refers to.
+    pub(crate) fn submit_one(&self, entry: squeue::Entry) -> io::Result<cqueue::Entry> {
+        let entry = entry.user_data(SUBMIT_ONE_USER_DATA);
+        let _turn = self.shared.submit_one.lock().unwrap();
+        {
+            // Serialized with the submitters that queue entries of their own.
+            let _waiting = self.shared.waiting.lock().unwrap();
+            unsafe { squeue::enqueue(self.info.0, &self.shared, &entry) }
+                .map_err(io::Error::other)?;
+        }
+        let mut error = None;
+        let submitter = self.submitter();
+        loop {
+            // The completions set aside already do not count.
+            let want = submitter.notified() + 1;
+            if let Err(e) = submitter.submit_and_wait(want, u32::MAX as usize) {
+                error = error.or(Some(e));
+            }
+            let _waiting = self.shared.waiting.lock().unwrap();
+            if let Some(cqe) = unsafe { self.cq.take(SUBMIT_ONE_USER_DATA) } {
+                return error.map_or(Ok(cqe), Err);
+            }
         }
     }
 }
 
 impl Drop for IoRing {
-    #[inline]
     fn drop(&mut self) {
-        unsafe {
-            win_ring_queue_exit(self.info.0);
+        // Nothing to do if it has been shut down already.
+        let _ = self.quiesce(ShutdownMode::Cancel, Instant::now() + DROP_TIMEOUT);
+        let _ = self.release();
+        if self.shared.emulator.is_none() {
+            unsafe {
+                win_ring_queue_exit(self.info.0);
+                drop(Box::from_raw(self.info.0));
+            }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{Error, IoRing, ShutdownMode};
+    use crate::testfile::TestFile;
+    use crate::windows::{
+        NT_IORING_BUFFERREF, NT_IORING_HANDLEREF, _NT_IORING_OP_FLAGS_NT_IORING_OP_FLAG_NONE,
+    };
+    use crate::{fs, opcode};
+    use std::io;
+    use std::time::Duration;
+
+    fn stalled_read(ring: &mut IoRing, file: &std::fs::File, buf: &mut [u8]) {
+        let handle = fs::raw_handle(file);
+        ring.emulator().unwrap().stall(handle);
+        let read = opcode::Read::new(
+            ring.info.0,
+            NT_IORING_HANDLEREF {
+                Handle: handle as _,
+            },
+            NT_IORING_BUFFERREF {
+                Address: buf.as_mut_ptr() as _,
+            },
+            buf.len() as u32,
+            0,
+            _NT_IORING_OP_FLAGS_NT_IORING_OP_FLAG_NONE,
+        )
+        .build()
+        .user_data(1);
+        unsafe { ring.submission().push(&read).unwrap() };
+        ring.submit().unwrap();
+    }
+
+    #[test]
+    fn shutdown_drains_or_cancels() {
+        let path = TestFile::new("shutdown", b"shutdown");
+        let file = std::fs::File::open(path.path()).unwrap();
+        let mut buf = [0u8; 8];
+
+        // A stalled operation never drains, the ring is dropped, which cancels it.
+        let mut ring = IoRing::emulated(8).unwrap();
+        stalled_read(&mut ring, &file, &mut buf);
+        let err = ring
+            .shutdown(ShutdownMode::Drain, Duration::from_millis(20))
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+
+        let mut ring = IoRing::emulated(8).unwrap();
+        ring.register_file(fs::raw_handle(&file)).unwrap();
+        stalled_read(&mut ring, &file, &mut buf);
+        let nop = opcode::Nop::new(ring.info.0).build();
+        unsafe { ring.submission().push(&nop).unwrap() };
+        ring.shutdown(ShutdownMode::Cancel, Duration::from_secs(5))
+            .unwrap();
+        assert_eq!(buf, [0; 8]);
+    }
+
+    #[test]
+    #[cfg(not(windows))]
+    fn new_reports_the_status() {
+        let err = IoRing::new(8).err().unwrap();
+        assert_eq!(err, Error::Unsupported(super::STATUS_NOT_SUPPORTED));
+        assert_eq!(io::Error::from(err).kind(), io::ErrorKind::Unsupported);
+    }
+
+    #[test]
+    fn queues_are_validated() {
+        let mut emulator = crate::emulate::Emulator::new(8).unwrap();
+        let ring = emulator.ring();
+        unsafe { (*ring).info.CompletionQueueRingMask = 3 };
+        let err = unsafe { IoRing::from_raw(ring, Box::default()) }.err();
+        assert_eq!(err, Some(Error::InvalidInfo("bad completion queue mask")));
+    }
+}
@@ -5,12 +5,16 @@
 use crate::{
     squeue::Entry,
     windows::{
-        win_ring, win_ring_get_sqe, win_ring_prep_nop, win_ring_prep_read,
-        win_ring_prep_register_buffers, win_ring_prep_register_files, win_ring_register_event,
-        HANDLE, NT_IORING_BUFFERREF, NT_IORING_HANDLEREF, _IORING_BUFFER_INFO,
+        win_ring, win_ring_prep_cancel, win_ring_prep_flush, win_ring_prep_nop,
+        win_ring_prep_read, win_ring_prep_register_buffers, win_ring_prep_register_files,
+        win_ring_prep_write, win_ring_register_event, FILE_FLUSH_MODE, FILE_WRITE_FLAGS,
+        FILE_WRITE_FLAGS_FILE_WRITE_FLAGS_NONE, HANDLE, NT_IORING_BUFFERREF, NT_IORING_HANDLEREF,
+        _IORING_BUFFER_INFO, _IORING_OP_CODE_IORING_OP_CANCEL, _IORING_OP_CODE_IORING_OP_FLUSH,
         _IORING_OP_CODE_IORING_OP_NOP, _IORING_OP_CODE_IORING_OP_READ,
-        _IORING_OP_CODE_IORING_OP_REGISTER_FILES, _NT_IORING_OP_FLAGS,
-        _NT_IORING_REG_BUFFERS_FLAGS, _NT_IORING_REG_FILES_FLAGS,
+        _IORING_OP_CODE_IORING_OP_REGISTER_BUFFERS, _IORING_OP_CODE_IORING_OP_REGISTER_FILES,
+        _IORING_OP_CODE_IORING_OP_WRITE,
+        _NT_IORING_OP_FLAGS, _NT_IORING_REG_BUFFERS_FLAGS, _NT_IORING_REG_FILES_FLAGS,
+        _NT_IORING_SQE_FLAGS_NT_IORING_SQE_FLAG_DRAIN_PRECEDING_OPS,
     },
 };
 
@@ -82,18 +86,61 @@ macro_rules! opcode {
 opcode!(
     /// Do not perform any I/O.
     ///
-    /// This is useful for testing the performance of the io_uring implementation itself.
+    /// This is useful for testing the performance of the io_uring implementation itself. It
+    /// touches no memory, so it can be built with a null `ring` and pushed anywhere.
     #[derive(Debug)]
-    pub struct Nop {  ring:{* mut win_ring} ;; }
+    pub struct Nop {
+        ring:{* mut win_ring}
+        ;;
+        /// The result the NOP completes with, `0` by default. Only an
+        /// [emulated](crate::IoRing::emulated) ring honours it: the kernel completes every NOP
+        /// with `0`.
+        result: i32 = 0,
+        /// The [`information`](crate::cqueue::Entry::information) the NOP completes with, `0`
+        /// by default. Like [`result`](Self::result), only an emulated ring honours it.
+        information: u64 = 0
+    }
 
     pub const CODE = _IORING_OP_CODE_IORING_OP_NOP;
 
     pub fn build(self) -> Entry {
-        let Nop { ring } = self;
+        let Nop { ring, result, information } = self;
         unsafe{
-            let sqe = win_ring_get_sqe(ring);
+            let entry = Entry::claim(ring);
+            let sqe = entry.0;
             win_ring_prep_nop(sqe);
-            Entry(sqe)
+            // Only the emulator looks at the arguments of a NOP.
+            let payload = &mut (*sqe).__bindgen_anon_1.ReservedMaxSizePadding;
+            payload.Argument1 = result as u32 as u64;
+            payload.Argument2 = information;
+            entry
+        }
+    }
+);
+
+opcode!(
+    /// Do not perform any I/O, but only complete once every operation submitted before it has
+    /// completed.
+    ///
+    /// This is a [`Nop`] with
+    /// [`NT_IORING_SQE_FLAG_DRAIN_PRECEDING_OPS`](crate::windows::_NT_IORING_SQE_FLAGS_NT_IORING_SQE_FLAG_DRAIN_PRECEDING_OPS)
+    /// set, so its completion tells that all the earlier operations are done. Operations
+    /// submitted after the fence are not held back by it; use
+    /// [`SubmissionQueue::push_barrier`](crate::squeue::SubmissionQueue::push_barrier) on the
+    /// entry that has to wait instead.
+    #[derive(Debug)]
+    pub struct Fence { ring:{*mut win_ring} ;; }
+
+    pub const CODE = _IORING_OP_CODE_IORING_OP_NOP;
+
+    pub fn build(self) -> Entry {
+        let Fence { ring } = self;
+        unsafe {
+            let entry = Entry::claim(ring);
+            let sqe = entry.0;
+            win_ring_prep_nop(sqe);
+            (*sqe).Flags = _NT_IORING_SQE_FLAGS_NT_IORING_SQE_FLAG_DRAIN_PRECEDING_OPS;
+            entry
         }
     }
 );
@@ -126,7 +173,8 @@ opcode!(
         } = self;
 
         unsafe {
-            let sqe = win_ring_get_sqe(ring);
+            let entry = Entry::claim(ring);
+            let sqe = entry.0;
             win_ring_prep_read(sqe,
                 file,
                 buffer,
@@ -134,7 +182,7 @@ opcode!(
                 file_offset,
                 common_op_flags
             );
-            Entry(sqe)
+            entry
         }
     }
 );
@@ -164,14 +212,15 @@ opcode!(
          } = self;
 
          unsafe{
-            let sqe = win_ring_get_sqe(ring);
+            let entry = Entry::claim(ring);
+            let sqe = entry.0;
             win_ring_prep_register_files(sqe,
                 handles,
                 count,
                 flags,
                 common_op_flags
             );
-            Entry(sqe)
+            entry
          }
     }
 );
@@ -189,7 +238,7 @@ opcode!(
      ;;
     }
 
-    pub const CODE = _IORING_OP_CODE_IORING_OP_REGISTER_FILES;
+    pub const CODE = _IORING_OP_CODE_IORING_OP_REGISTER_BUFFERS;
 
     pub fn build(self) -> Entry {
         let RegisterBuffers {
@@ -200,7 +249,8 @@ opcode!(
             common_op_flags,
          } = self;
          unsafe {
-            let sqe = win_ring_get_sqe(ring);
+            let entry = Entry::claim(ring);
+            let sqe = entry.0;
             win_ring_prep_register_buffers(sqe,
                 handles,
                 count,
@@ -208,7 +258,7 @@ opcode!(
                 common_op_flags
             );
 
-            Entry(sqe)
+            entry
          }
     }
 );
@@ -223,7 +273,7 @@ opcode!(
      ;;
     }
 
-    pub const CODE = _IORING_OP_CODE_IORING_OP_REGISTER_FILES;
+    pub const CODE = _IORING_OP_CODE_IORING_OP_NOP;
 
     pub fn build(self) -> Entry {
         let RegisterEvents {
@@ -231,94 +281,128 @@ opcode!(
             handle
          } = self;
          unsafe {
-            let sqe = win_ring_get_sqe(ring);
+            let entry = Entry::claim(ring);
+            let sqe = entry.0;
             win_ring_register_event(ring,
                 handle
             );
             win_ring_prep_nop(sqe);
-            Entry(sqe)
+            entry
          }
     }
 );
 
 opcode!(
-    /// Do not perform any I/O.
+    /// Cancel operations in flight on `file`.
     ///
-    /// This is useful for testing the performance of the io_uring implementation itself.
+    /// Only the operation whose user data is [`cancel_id`](Self::cancel_id) is cancelled, or every
+    /// operation on `file` if it is left at `0`. A cancelled operation still completes, with
+    /// `HRESULT_FROM_WIN32(ERROR_OPERATION_ABORTED)` as its result. The cancel itself fails with
+    /// `HRESULT_FROM_WIN32(ERROR_NOT_FOUND)` if there was nothing to cancel.
     #[derive(Debug)]
     pub struct Cancel {
         ring:{*mut win_ring},
         file:{NT_IORING_HANDLEREF},
-        buffer:{NT_IORING_BUFFERREF},
-        size_to_read:{u32},
-        file_offset:{u64},
+        common_op_flags:{_NT_IORING_OP_FLAGS}
+        ;;
+        /// The user data of the operation to cancel.
+        cancel_id: u64 = 0
+    }
+
+    pub const CODE = _IORING_OP_CODE_IORING_OP_CANCEL;
+
+    pub fn build(self) -> Entry {
+        let Cancel {
+            ring,
+            file,
+            common_op_flags,
+            cancel_id,
+        } = self;
+
+        unsafe {
+            let entry = Entry::claim(ring);
+            let sqe = entry.0;
+            win_ring_prep_cancel(sqe, file, cancel_id, common_op_flags);
+            entry
+        }
+    }
+);
+
+opcode!(
+    /// Flush the buffered data of a file to disk.
+    #[derive(Debug)]
+    pub struct Flush {
+        ring:{*mut win_ring},
+        file:{NT_IORING_HANDLEREF},
+        flush_mode:{FILE_FLUSH_MODE},
         common_op_flags:{_NT_IORING_OP_FLAGS}
      ;;
      }
 
-    pub const CODE = _IORING_OP_CODE_IORING_OP_READ;
+    pub const CODE = _IORING_OP_CODE_IORING_OP_FLUSH;
 
     pub fn build(self) -> Entry {
-        let Cancel {
+        let Flush {
             ring,
             file,
-            buffer,
-            size_to_read,
-            file_offset,
+            flush_mode,
             common_op_flags,
         } = self;
 
         unsafe {
-            let sqe = win_ring_get_sqe(ring);
-            win_ring_prep_read(sqe,
+            let entry = Entry::claim(ring);
+            let sqe = entry.0;
+            win_ring_prep_flush(sqe,
                 file,
-                buffer,
-                size_to_read,
-                file_offset,
+                flush_mode,
                 common_op_flags
             );
-            Entry(sqe)
+            entry
         }
     }
 );
 
 opcode!(
-    /// Do not perform any I/O.
-    ///
-    /// This is useful for testing the performance of the io_uring implementation itself.
+    /// Write to a file, like [`Read`] but in the other direction.
     #[derive(Debug)]
-    pub struct Flush {
+    pub struct Write {
         ring:{*mut win_ring},
         file:{NT_IORING_HANDLEREF},
         buffer:{NT_IORING_BUFFERREF},
-        size_to_read:{u32},
+        size_to_write:{u32},
         file_offset:{u64},
         common_op_flags:{_NT_IORING_OP_FLAGS}
      ;;
+        /// Extra flags for the write, e.g.
+        /// [`FILE_WRITE_FLAGS_FILE_WRITE_FLAGS_WRITE_THROUGH`](crate::windows::FILE_WRITE_FLAGS_FILE_WRITE_FLAGS_WRITE_THROUGH).
+        flags: FILE_WRITE_FLAGS = FILE_WRITE_FLAGS_FILE_WRITE_FLAGS_NONE
      }
 
-    pub const CODE = _IORING_OP_CODE_IORING_OP_READ;
+    pub const CODE = _IORING_OP_CODE_IORING_OP_WRITE;
 
     pub fn build(self) -> Entry {
-        let Flush {
+        let Write {
             ring,
             file,
             buffer,
-            size_to_read,
+            size_to_write,
             file_offset,
             common_op_flags,
+            flags,
         } = self;
 
         unsafe {
-            let sqe = win_ring_get_sqe(ring);
-            win_ring_prep_read(sqe,
+            let entry = Entry::claim(ring);
+            let sqe = entry.0;
+            win_ring_prep_write(sqe,
                 file,
                 buffer,
-                size_to_read,
+                size_to_write,
                 file_offset,
+                flags,
                 common_op_flags
             );
-            Entry(sqe)
+            entry
         }
     }
 );
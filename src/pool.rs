@@ -0,0 +1,342 @@
+//! A pool of rings, each driven by a thread of its own.
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use std::{io, mem};
+
+use crate::completions::Completions;
+use crate::cqueue::{self, OPERATION_ABORTED};
+use crate::squeue::{self, target};
+use crate::windows::{_NT_IORING_OP_FLAGS_NT_IORING_OP_FLAG_REGISTERED_FILE, _NT_IORING_SQE};
+use crate::{Builder, IoRing};
+
+/// How long a ring thread waits for completions before looking for new operations again.
+const POLL: Duration = Duration::from_millis(1);
+
+/// How a [`RingPool`] picks the ring for an operation.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Routing {
+    /// Operations on the same file go to the same ring, so that they are ordered relative to
+    /// each other. Operations without a file are spread round robin.
+    #[default]
+    Affinity,
+    /// Every operation goes to the next ring in turn.
+    RoundRobin,
+}
+
+/// A number of rings, each owned by a thread that submits to it and reaps its completions.
+///
+/// Operations are handed to [`submit`](Self::submit) from any thread, together with the
+/// sending half of a channel, on which their completion is delivered with the `user_data` they
+/// were given. Completions from every ring can be gathered on a single channel by using the same
+/// sender for all of them.
+///
+/// Like for a [`SharedSubmitter`](crate::submit::SharedSubmitter), the entries have to be built
+/// with a null ring. Every ring is created from the same [`Builder`], so registered files and
+/// buffers have the same indices on all of them. The rings always have their
+/// [backlog](IoRing::set_backlog) enabled.
+///
+/// Dropping the pool waits for every operation in flight to complete.
+///
+/// # Examples
+///
+/// ```
+/// use std::sync::mpsc;
+///
+/// use ioring_rs::{opcode, pool::RingPool, Builder};
+///
+/// # fn main() -> std::io::Result<()> {
+/// let pool = RingPool::new(2, Builder::new(32).emulated(true))?;
+/// let (sender, receiver) = mpsc::channel();
+/// for user_data in 0..4 {
+///     let nop = opcode::Nop::new(std::ptr::null_mut()).build().user_data(user_data);
+///     unsafe { pool.submit(&nop, &sender)? };
+/// }
+///
+/// let mut completed: Vec<_> = receiver.iter().take(4).map(|cqe| cqe.user_data()).collect();
+/// completed.sort();
+/// assert_eq!(completed, [0, 1, 2, 3]);
+/// # Ok(())
+/// # }
+/// ```
+pub struct RingPool {
+    workers: Vec<Worker>,
+    routing: Routing,
+    next: AtomicUsize,
+}
+
+struct Worker {
+    jobs: Option<Sender<Job>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+/// An operation on its way to a ring thread.
+struct Job {
+    sqe: _NT_IORING_SQE,
+    timeout: Option<Duration>,
+    reply: Sender<cqueue::Entry>,
+}
+
+// The entry's buffers are the business of whoever submitted it, as with `SubmissionQueue::push`.
+unsafe impl Send for Job {}
+
+impl RingPool {
+    /// Create `rings` rings from `builder`, each on a thread of its own.
+    pub fn new(rings: usize, builder: Builder) -> io::Result<RingPool> {
+        if rings == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "a pool needs at least one ring",
+            ));
+        }
+        let builder = builder.backlog(true);
+        let mut workers = Vec::with_capacity(rings);
+        for i in 0..rings {
+            let ring = builder.build()?;
+            let (jobs, receiver) = mpsc::channel();
+            let thread = thread::Builder::new()
+                .name(format!("ioring-pool-{}", i))
+                .spawn(move || run(ring, receiver))?;
+            workers.push(Worker {
+                jobs: Some(jobs),
+                thread: Some(thread),
+            });
+        }
+        Ok(RingPool {
+            workers,
+            routing: Routing::default(),
+            next: AtomicUsize::new(0),
+        })
+    }
+
+    /// Set how operations are spread over the rings.
+    pub fn routing(mut self, routing: Routing) -> RingPool {
+        self.routing = routing;
+        self
+    }
+
+    /// The number of rings in the pool.
+    pub fn len(&self) -> usize {
+        self.workers.len()
+    }
+
+    /// Whether the pool has no rings, which [`new`](Self::new) does not allow.
+    pub fn is_empty(&self) -> bool {
+        self.workers.is_empty()
+    }
+
+    /// Hand `entry` over to one of the rings, and have its completion sent to `reply`.
+    ///
+    /// This fails if the entry was built in a submission queue rather than with a null ring.
+    ///
+    /// # Safety
+    ///
+    /// Developers must ensure that parameters of the entry (such as buffer) are valid and will
+    /// be valid for the entire duration of the operation, otherwise it may cause memory problems.
+    pub unsafe fn submit(
+        &self,
+        entry: &squeue::Entry,
+        reply: &Sender<cqueue::Entry>,
+    ) -> io::Result<()> {
+        let sqe = match &entry.2 {
+            Some(detached) => **detached,
+            None => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "entries for a RingPool must be built with a null ring",
+                ))
+            }
+        };
+        let job = Job {
+            sqe,
+            timeout: entry.1,
+            reply: reply.clone(),
+        };
+        let worker = &self.workers[self.route(&sqe)];
+        worker
+            .jobs
+            .as_ref()
+            .unwrap()
+            .send(job)
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "the ring thread has exited"))
+    }
+
+    /// Pick the ring for `sqe`.
+    fn route(&self, sqe: &_NT_IORING_SQE) -> usize {
+        let file = match (self.routing, target(sqe)) {
+            (Routing::Affinity, Some((file, flags))) => Some(unsafe {
+                let registered = flags & _NT_IORING_OP_FLAGS_NT_IORING_OP_FLAG_REGISTERED_FILE;
+                (registered != 0, file.Handle)
+            }),
+            _ => None,
+        };
+        match file {
+            Some(file) => {
+                let mut hasher = DefaultHasher::new();
+                file.hash(&mut hasher);
+                hasher.finish() as usize % self.workers.len()
+            }
+            None => self.next.fetch_add(1, Ordering::Relaxed) % self.workers.len(),
+        }
+    }
+}
+
+impl Drop for RingPool {
+    fn drop(&mut self) {
+        for worker in &mut self.workers {
+            worker.jobs.take();
+        }
+        for worker in &mut self.workers {
+            if let Some(thread) = worker.thread.take() {
+                let _ = thread.join();
+            }
+        }
+    }
+}
+
+/// Drive `ring` until the pool is dropped and every operation has completed.
+fn run(mut ring: IoRing, jobs: Receiver<Job>) {
+    // The operations in flight, by the `user_data` they were given on this ring.
+    let mut routes: Completions<(u64, Sender<cqueue::Entry>)> = Completions::new();
+    let mut open = true;
+    while open || !routes.is_empty() {
+        let idle = routes.is_empty();
+        let (submitter, mut sq, mut cq) = ring.split();
+        let mut queue = |job: Job| {
+            let Job {
+                mut sqe,
+                timeout,
+                reply,
+            } = job;
+            sqe.UserData = routes.insert((sqe.UserData, reply));
+            let entry = squeue::Entry::detached(sqe, timeout);
+            // With the backlog enabled, this cannot fail.
+            unsafe { sq.push(&entry).unwrap() };
+        };
+        if idle {
+            match jobs.recv() {
+                Ok(job) => queue(job),
+                Err(_) => break,
+            }
+        }
+        loop {
+            match jobs.try_recv() {
+                Ok(job) => queue(job),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    open = false;
+                    break;
+                }
+            }
+        }
+
+        let submitted = submitter.submit_and_wait(1, POLL.as_millis() as usize);
+        cq.sync();
+        for cqe in &mut cq {
+            if let Ok(((user_data, reply), mut cqe)) = routes.complete(cqe) {
+                cqe.0.UserData = user_data;
+                let _ = reply.send(cqe);
+            }
+        }
+        if submitted.is_err() {
+            break;
+        }
+    }
+    if routes.is_empty() {
+        return;
+    }
+    // The ring is unusable. Dropping it cancels what is still in flight and waits for it, so
+    // that the buffers are left alone by the time everything is reported as aborted.
+    drop(ring);
+    for (_, (user_data, reply)) in mem::take(&mut routes).into_iter() {
+        let _ = reply.send(cqueue::Entry::synthetic(user_data, OPERATION_ABORTED, 0));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::opcode;
+    use crate::testfile::TestFile;
+    use crate::windows::{
+        NT_IORING_BUFFERREF, NT_IORING_HANDLEREF, _NT_IORING_OP_FLAGS_NT_IORING_OP_FLAG_NONE,
+    };
+    use crate::{fs, IoRing};
+    use std::ptr;
+
+    fn read(file: u64, buf: &mut [u8]) -> squeue::Entry {
+        opcode::Read::new(
+            ptr::null_mut(),
+            NT_IORING_HANDLEREF { Handle: file },
+            NT_IORING_BUFFERREF {
+                Address: buf.as_mut_ptr() as _,
+            },
+            buf.len() as u32,
+            0,
+            _NT_IORING_OP_FLAGS_NT_IORING_OP_FLAG_NONE,
+        )
+        .build()
+    }
+
+    #[test]
+    fn affinity_keeps_a_file_on_one_ring() {
+        let pool = RingPool::new(4, Builder::new(8).emulated(true)).unwrap();
+        let mut buf = [0u8; 1];
+        let first = pool.route(unsafe { &*read(42, &mut buf).0 });
+        for _ in 0..8 {
+            assert_eq!(pool.route(unsafe { &*read(42, &mut buf).0 }), first);
+        }
+
+        let pool = pool.routing(Routing::RoundRobin);
+        let rings: Vec<_> = (0..8)
+            .map(|_| pool.route(unsafe { &*read(42, &mut buf).0 }))
+            .collect();
+        assert_eq!(rings, [0, 1, 2, 3, 0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn completions_come_back_with_their_user_data() {
+        let paths: Vec<_> = (0..3u8)
+            .map(|i| TestFile::new(&format!("pool-{}", i), &[i; 4]))
+            .collect();
+        let files: Vec<_> = paths
+            .iter()
+            .map(|path| std::fs::File::open(path.path()).unwrap())
+            .collect();
+
+        let pool = RingPool::new(2, Builder::new(4).emulated(true)).unwrap();
+        let (sender, receiver) = mpsc::channel();
+        let mut bufs = [[0u8; 4]; 12];
+        for (i, buf) in bufs.iter_mut().enumerate() {
+            let file = fs::raw_handle(&files[i % 3]) as u64;
+            let entry = read(file, buf).user_data(100 + i as u64);
+            unsafe { pool.submit(&entry, &sender).unwrap() };
+        }
+
+        let mut completed: Vec<_> = receiver
+            .iter()
+            .take(12)
+            .map(|cqe| (cqe.user_data(), cqe.into_result().unwrap()))
+            .collect();
+        completed.sort_unstable();
+        assert_eq!(completed, (100..112).map(|i| (i, 4)).collect::<Vec<_>>());
+        drop(pool);
+        for (i, buf) in bufs.iter().enumerate() {
+            assert_eq!(buf, &[(i % 3) as u8; 4]);
+        }
+    }
+
+    #[test]
+    fn entries_built_in_a_ring_are_refused() {
+        let ring = IoRing::emulated(4).unwrap();
+        let (sender, _receiver) = mpsc::channel();
+        let pool = RingPool::new(1, Builder::new(4).emulated(true)).unwrap();
+        let nop = opcode::Nop::new(ring.info.0).build();
+        let err = unsafe { pool.submit(&nop, &sender) }.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+}
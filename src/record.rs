@@ -0,0 +1,740 @@
+//! Recording of the operations of a ring, and their replay.
+//!
+//! A [`Recorder`], set with [`IoRing::set_recorder`], writes a line of JSON for every entry
+//! pushed to the ring and every completion reaped from it, stamped with the microseconds since
+//! the recording started:
+//!
+//! ```text
+//! {"t":12,"op":"sqe","user_data":1,"raw":"01000000…","entry":"read user_data=1 file=0x3 buffer=0x55d0… offset=0 length=4"}
+//! {"t":85,"op":"cqe","user_data":1,"result":0,"information":4,"entry":"user_data=1 information=4"}
+//! ```
+//!
+//! `raw` holds the bytes of the entry, from which it is rebuilt, while `entry` is only there to
+//! be read by people. An operation that never completes shows up as an `sqe` line without a
+//! matching `cqe` line, which is what to look for when a ring hangs.
+//!
+//! A [`Replay`] pushes the recorded entries again, in the same order, on an
+//! [emulated](IoRing::emulated) ring or a real one, and compares the completions with the
+//! recorded ones. The buffers of the recording are gone by then, so reads and writes get
+//! buffers of their own, zeroed. Writes are only replayed when asked to with
+//! [`Replay::writes`], and then should be replayed on copies of the files.
+//!
+//! # Examples
+//!
+//! ```
+//! use ioring_rs::record::{Recorder, Replay};
+//! use ioring_rs::{opcode, IoRing};
+//! use std::sync::{Arc, Mutex};
+//!
+//! # fn main() -> std::io::Result<()> {
+//! #[derive(Clone, Default)]
+//! struct Trace(Arc<Mutex<Vec<u8>>>);
+//!
+//! impl std::io::Write for Trace {
+//!     fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+//!         self.0.lock().unwrap().write(buf)
+//!     }
+//!     fn flush(&mut self) -> std::io::Result<()> {
+//!         Ok(())
+//!     }
+//! }
+//!
+//! let trace = Trace::default();
+//! let mut ring = IoRing::emulated(8)?;
+//! ring.set_recorder(Some(Recorder::new(trace.clone())));
+//! let nop = opcode::Nop::new(ring.info.0).information(7).build().user_data(1);
+//! unsafe { ring.submission().push(&nop).expect("submission queue is full") };
+//! ring.submit_and_wait(1)?;
+//! assert_eq!(ring.completion().count(), 1);
+//! ring.set_recorder(None).unwrap().finish()?;
+//!
+//! let trace = trace.0.lock().unwrap();
+//! let report = Replay::read(&trace[..])?.run(&mut IoRing::emulated(8)?)?;
+//! assert_eq!(report.replayed, 1);
+//! assert!(report.is_ok());
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt::{self, Debug, Formatter};
+use std::io::{self, BufRead, LineWriter, Write};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+use std::{mem, ptr, slice};
+
+use crate::cqueue;
+use crate::squeue::{self, Sqe};
+use crate::windows::{
+    HANDLE, NT_IORING_BUFFERREF, NT_IORING_HANDLEREF, _IORING_OP_CODE_IORING_OP_CANCEL,
+    _IORING_OP_CODE_IORING_OP_FLUSH, _IORING_OP_CODE_IORING_OP_NOP, _IORING_OP_CODE_IORING_OP_READ,
+    _IORING_OP_CODE_IORING_OP_WRITE, _NT_IORING_OP_FLAGS,
+    _NT_IORING_OP_FLAGS_NT_IORING_OP_FLAG_NONE,
+    _NT_IORING_OP_FLAGS_NT_IORING_OP_FLAG_REGISTERED_FILE, _NT_IORING_SQE,
+};
+use crate::{IoRing, Shared};
+
+const SQE_SIZE: usize = mem::size_of::<_NT_IORING_SQE>();
+
+/// Writes the operations of a ring as lines of JSON, see the [module](self) documentation.
+///
+/// Every line is flushed as soon as it is written, so that the trace of a process that hangs
+/// or crashes is complete.
+pub struct Recorder {
+    out: LineWriter<Box<dyn Write + Send>>,
+    start: Instant,
+    /// The first error writing the trace, after which nothing more is written.
+    error: Option<io::Error>,
+}
+
+impl Recorder {
+    /// Write the trace to `out`, with the time of each event counted from now.
+    pub fn new(out: impl Write + Send + 'static) -> Recorder {
+        Recorder {
+            out: LineWriter::new(Box::new(out)),
+            start: Instant::now(),
+            error: None,
+        }
+    }
+
+    /// Flush the trace, and report the first error writing it, if any.
+    pub fn finish(mut self) -> io::Result<()> {
+        match self.error.take() {
+            Some(err) => Err(err),
+            None => self.out.flush(),
+        }
+    }
+
+    fn write(&mut self, line: fmt::Arguments<'_>) {
+        if self.error.is_none() {
+            let micros = self.start.elapsed().as_micros();
+            if let Err(err) = writeln!(self.out, "{{\"t\":{},{}}}", micros, line) {
+                self.error = Some(err);
+            }
+        }
+    }
+
+    fn pushed(&mut self, sqe: &_NT_IORING_SQE) {
+        let raw = unsafe { slice::from_raw_parts(sqe as *const _ as *const u8, SQE_SIZE) };
+        let raw: String = raw.iter().map(|byte| format!("{:02x}", byte)).collect();
+        self.write(format_args!(
+            "\"op\":\"sqe\",\"user_data\":{},\"raw\":\"{}\",\"entry\":\"{}\"",
+            sqe.UserData,
+            raw,
+            escape(&Sqe(sqe, None).to_string())
+        ));
+    }
+
+    fn reaped(&mut self, cqe: &cqueue::Entry) {
+        self.write(format_args!(
+            "\"op\":\"cqe\",\"user_data\":{},\"result\":{},\"information\":{},\"entry\":\"{}\"",
+            cqe.user_data(),
+            cqe.result(),
+            cqe.information(),
+            escape(&cqe.to_string())
+        ));
+    }
+}
+
+impl Debug for Recorder {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Recorder")
+            .field("start", &self.start)
+            .field("error", &self.error)
+            .finish()
+    }
+}
+
+/// Record an entry pushed to the ring, if it is being recorded.
+pub(crate) fn pushed(shared: &Shared, sqe: &_NT_IORING_SQE) {
+    if cqueue::is_hidden(sqe.UserData) {
+        return;
+    }
+    if let Some(recorder) = shared.recorder.lock().unwrap().as_mut() {
+        recorder.pushed(sqe);
+    }
+}
+
+/// Record a completion reaped from the ring, if it is being recorded.
+pub(crate) fn reaped(shared: &Shared, cqe: &cqueue::Entry) {
+    if let Some(recorder) = shared.recorder.lock().unwrap().as_mut() {
+        recorder.reaped(cqe);
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// A line of a trace.
+#[derive(Clone, Copy)]
+#[non_exhaustive]
+pub enum Event {
+    /// An entry was pushed.
+    Pushed { at: Duration, sqe: _NT_IORING_SQE },
+    /// A completion was reaped.
+    Reaped {
+        at: Duration,
+        user_data: u64,
+        result: i32,
+        information: u64,
+    },
+}
+
+impl Event {
+    /// Parse a line written by a [`Recorder`].
+    pub fn parse(line: &str) -> io::Result<Event> {
+        let at = Duration::from_micros(number(line, "t")?);
+        match field(line, "op") {
+            Some("sqe") => {
+                let hex = field(line, "raw").ok_or_else(|| invalid("raw"))?;
+                if hex.len() != 2 * SQE_SIZE || !hex.is_ascii() {
+                    return Err(invalid("raw"));
+                }
+                let mut raw = [0u8; SQE_SIZE];
+                for (byte, digits) in raw.iter_mut().zip(hex.as_bytes().chunks(2)) {
+                    let digits = std::str::from_utf8(digits).unwrap();
+                    *byte = u8::from_str_radix(digits, 16).map_err(|_| invalid("raw"))?;
+                }
+                let sqe = unsafe { ptr::read_unaligned(raw.as_ptr() as *const _NT_IORING_SQE) };
+                Ok(Event::Pushed { at, sqe })
+            }
+            Some("cqe") => Ok(Event::Reaped {
+                at,
+                user_data: number(line, "user_data")?,
+                result: number(line, "result")?,
+                information: number(line, "information")?,
+            }),
+            _ => Err(invalid("op")),
+        }
+    }
+
+    /// When the event happened, since the recording started.
+    pub fn at(&self) -> Duration {
+        match *self {
+            Event::Pushed { at, .. } | Event::Reaped { at, .. } => at,
+        }
+    }
+}
+
+impl Debug for Event {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Event::Pushed { at, sqe } => f
+                .debug_struct("Pushed")
+                .field("at", at)
+                .field("sqe", &Sqe(sqe, None))
+                .finish(),
+            Event::Reaped {
+                at,
+                user_data,
+                result,
+                information,
+            } => f
+                .debug_struct("Reaped")
+                .field("at", at)
+                .field("user_data", user_data)
+                .field("result", result)
+                .field("information", information)
+                .finish(),
+        }
+    }
+}
+
+/// The value of `key` in a line of a trace, without its quotes.
+fn field<'a>(line: &'a str, key: &str) -> Option<&'a str> {
+    // The human readable `entry` comes last, and may contain anything.
+    let line = line.split(",\"entry\":").next()?;
+    let start = line.find(&format!("\"{}\":", key))? + key.len() + 3;
+    let value = &line[start..];
+    let end = value.find([',', '}']).unwrap_or(value.len());
+    Some(value[..end].trim_matches('"'))
+}
+
+fn number<T: FromStr>(line: &str, key: &'static str) -> io::Result<T> {
+    field(line, key)
+        .and_then(|value| value.parse().ok())
+        .ok_or_else(|| invalid(key))
+}
+
+fn invalid(key: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("missing or invalid `{}` in trace", key),
+    )
+}
+
+/// Pushes recorded operations again and compares their completions, see the
+/// [module](self) documentation.
+///
+/// Files are given by the handle they had when they were recorded, and have to be mapped with
+/// [`file`](Self::file) to be replayed. Operations on files that are not mapped, on
+/// registered files or buffers, and the registrations themselves are skipped, and so are writes
+/// unless [`writes`](Self::writes) are enabled.
+#[derive(Debug)]
+pub struct Replay {
+    events: Vec<Event>,
+    files: HashMap<u64, u64>,
+    timeout: Duration,
+    writes: bool,
+}
+
+/// The outcome of a [`Replay`].
+#[derive(Clone, Debug, Default)]
+#[non_exhaustive]
+pub struct Report {
+    /// The number of operations pushed.
+    pub replayed: usize,
+    /// The `user_data` of the operations that could not be replayed.
+    pub skipped: Vec<u64>,
+    /// The operations that did not complete as recorded.
+    pub mismatches: Vec<Mismatch>,
+}
+
+impl Report {
+    /// Whether every operation replayed completed as recorded.
+    pub fn is_ok(&self) -> bool {
+        self.mismatches.is_empty()
+    }
+}
+
+/// An operation that did not complete as recorded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Mismatch {
+    /// The `user_data` the operation was recorded with.
+    pub user_data: u64,
+    /// The recorded result and information, or `None` if it never completed.
+    pub recorded: Option<(i32, u64)>,
+    /// The result and information of the replay, or `None` if it did not complete in time.
+    pub replayed: Option<(i32, u64)>,
+}
+
+/// The state of a replay in progress.
+#[derive(Default)]
+struct Progress {
+    /// How many operations are in flight with each `user_data`.
+    in_flight: HashMap<u64, usize>,
+    /// Completions reaped but not matched with the recording yet.
+    reaped: HashMap<u64, VecDeque<(i32, u64)>>,
+    /// Operations given up on, whose completion may still come.
+    late: HashSet<u64>,
+    buffers: Vec<Vec<u8>>,
+}
+
+impl Replay {
+    /// Replay `events`, usually [read](Self::read) from a trace, on the ring given to
+    /// [`run`](Self::run).
+    pub fn new(events: Vec<Event>) -> Replay {
+        Replay {
+            events,
+            files: HashMap::new(),
+            timeout: Duration::from_secs(5),
+            writes: false,
+        }
+    }
+
+    /// Read the trace written by a [`Recorder`].
+    pub fn read(trace: impl BufRead) -> io::Result<Replay> {
+        let mut events = Vec::new();
+        for line in trace.lines() {
+            let line = line?;
+            if !line.trim().is_empty() {
+                events.push(Event::parse(&line)?);
+            }
+        }
+        Ok(Replay::new(events))
+    }
+
+    /// The events of the trace.
+    pub fn events(&self) -> &[Event] {
+        &self.events
+    }
+
+    /// Replay the operations on the file that had the handle `recorded` on `handle`.
+    pub fn file(mut self, recorded: u64, handle: HANDLE) -> Replay {
+        self.files.insert(recorded, handle as u64);
+        self
+    }
+
+    /// Replay writes as well, which are skipped by default. Their data is gone, so they write
+    /// zeroes over the files: only enable this on copies of them.
+    pub fn writes(mut self, writes: bool) -> Replay {
+        self.writes = writes;
+        self
+    }
+
+    /// Set how long to wait for each completion, 5 seconds by default. Operations that take
+    /// longer are reported as not completed.
+    pub fn timeout(mut self, timeout: Duration) -> Replay {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Replay the trace on `ring`, whose queues should be empty.
+    ///
+    /// Each operation is pushed once the completions recorded before it have come in, so that
+    /// operations that depended on each other keep their order. If operations are still in
+    /// flight at the end, their buffers are leaked rather than freed under the kernel.
+    pub fn run(&self, ring: &mut IoRing) -> io::Result<Report> {
+        let mut report = Report::default();
+        let mut progress = Progress::default();
+        let res = self.drive(ring, &mut report, &mut progress);
+        if progress.in_flight.values().any(|&count| count > 0) {
+            mem::forget(mem::take(&mut progress.buffers));
+        }
+        res.map(|()| report)
+    }
+
+    fn drive(
+        &self,
+        ring: &mut IoRing,
+        report: &mut Report,
+        progress: &mut Progress,
+    ) -> io::Result<()> {
+        // The completions the recording is still waiting for.
+        let mut recorded: HashMap<u64, usize> = HashMap::new();
+        for event in &self.events {
+            match *event {
+                Event::Pushed { mut sqe, .. } => {
+                    let user_data = sqe.UserData;
+                    if !self.prepare(&mut sqe, &mut progress.buffers) {
+                        report.skipped.push(user_data);
+                        continue;
+                    }
+                    self.push(ring, &squeue::Entry::detached(sqe, None), progress)?;
+                    *progress.in_flight.entry(user_data).or_default() += 1;
+                    *recorded.entry(user_data).or_default() += 1;
+                    report.replayed += 1;
+                }
+                Event::Reaped {
+                    user_data,
+                    result,
+                    information,
+                    ..
+                } => {
+                    match recorded.get_mut(&user_data) {
+                        Some(count) if *count > 0 => *count -= 1,
+                        // Skipped, or produced in user space.
+                        _ => continue,
+                    }
+                    let replayed = self.wait_for(ring, user_data, progress)?;
+                    let recorded = Some((result, information));
+                    if replayed != recorded {
+                        report.mismatches.push(Mismatch {
+                            user_data,
+                            recorded,
+                            replayed,
+                        });
+                    }
+                }
+            }
+        }
+
+        // What did not complete in the recording may complete now.
+        let deadline = Instant::now() + self.timeout;
+        while progress.in_flight.values().any(|&count| count > 0) {
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                break;
+            }
+            ring.submitter()
+                .submit_and_wait(1, left.as_millis().max(1) as usize)?;
+            progress.reap(ring);
+        }
+        for (user_data, completions) in progress.reaped.drain() {
+            for replayed in completions {
+                report.mismatches.push(Mismatch {
+                    user_data,
+                    recorded: None,
+                    replayed: Some(replayed),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Point `sqe` at the mapped file and a buffer of its own, and return whether it can be
+    /// replayed.
+    fn prepare(&self, sqe: &mut _NT_IORING_SQE, buffers: &mut Vec<Vec<u8>>) -> bool {
+        let mut buffer = |buffer: &mut NT_IORING_BUFFERREF, length: u32| {
+            let mut replayed = vec![0u8; length as usize];
+            buffer.Address = replayed.as_mut_ptr() as u64;
+            buffers.push(replayed);
+        };
+        unsafe {
+            let op = &mut sqe.__bindgen_anon_1;
+            match sqe.OpCode {
+                _IORING_OP_CODE_IORING_OP_NOP => true,
+                _IORING_OP_CODE_IORING_OP_READ => {
+                    let read = &mut op.Read;
+                    if read.CommonOpFlags != _NT_IORING_OP_FLAGS_NT_IORING_OP_FLAG_NONE
+                        || !self.map(&mut read.File, read.CommonOpFlags)
+                    {
+                        return false;
+                    }
+                    buffer(&mut read.Buffer, read.Length);
+                    true
+                }
+                _IORING_OP_CODE_IORING_OP_WRITE => {
+                    let write = &mut op.Write;
+                    if !self.writes
+                        || write.CommonOpFlags != _NT_IORING_OP_FLAGS_NT_IORING_OP_FLAG_NONE
+                        || !self.map(&mut write.File, write.CommonOpFlags)
+                    {
+                        return false;
+                    }
+                    buffer(&mut write.Buffer, write.Length);
+                    true
+                }
+                _IORING_OP_CODE_IORING_OP_FLUSH => {
+                    let flush = &mut op.Flush;
+                    self.map(&mut flush.File, flush.CommonOpFlags)
+                }
+                _IORING_OP_CODE_IORING_OP_CANCEL => {
+                    let cancel = &mut op.Cancel;
+                    self.map(&mut cancel.File, cancel.CommonOpFlags)
+                }
+                _ => false,
+            }
+        }
+    }
+
+    fn map(&self, file: &mut NT_IORING_HANDLEREF, flags: _NT_IORING_OP_FLAGS) -> bool {
+        if flags & _NT_IORING_OP_FLAGS_NT_IORING_OP_FLAG_REGISTERED_FILE != 0 {
+            return false;
+        }
+        match self.files.get(unsafe { &file.Handle }) {
+            Some(&handle) => {
+                file.Handle = handle;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn push(
+        &self,
+        ring: &mut IoRing,
+        entry: &squeue::Entry,
+        progress: &mut Progress,
+    ) -> io::Result<()> {
+        let deadline = Instant::now() + self.timeout;
+        while unsafe { ring.submission().push(entry) }.is_err() {
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "the submission queue stayed full",
+                ));
+            }
+            ring.submitter()
+                .submit_and_wait(1, left.as_millis().max(1) as usize)?;
+            progress.reap(ring);
+        }
+        Ok(())
+    }
+
+    /// Wait for the next completion of `user_data`.
+    fn wait_for(
+        &self,
+        ring: &mut IoRing,
+        user_data: u64,
+        progress: &mut Progress,
+    ) -> io::Result<Option<(i32, u64)>> {
+        let deadline = Instant::now() + self.timeout;
+        loop {
+            progress.reap(ring);
+            if let Some(completion) = progress.take(user_data) {
+                return Ok(Some(completion));
+            }
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                progress.late.insert(user_data);
+                return Ok(None);
+            }
+            ring.submitter()
+                .submit_and_wait(1, left.as_millis().max(1) as usize)?;
+        }
+    }
+}
+
+impl Progress {
+    fn reap(&mut self, ring: &mut IoRing) {
+        for cqe in ring.completion() {
+            let user_data = cqe.user_data();
+            match self.in_flight.get_mut(&user_data) {
+                Some(count) if *count > 0 => *count -= 1,
+                _ => continue,
+            }
+            if self.late.remove(&user_data) {
+                continue;
+            }
+            let completion = (cqe.result(), cqe.information() as u64);
+            self.reaped
+                .entry(user_data)
+                .or_default()
+                .push_back(completion);
+        }
+    }
+
+    fn take(&mut self, user_data: u64) -> Option<(i32, u64)> {
+        let completions = self.reaped.get_mut(&user_data)?;
+        let completion = completions.pop_front();
+        if completions.is_empty() {
+            self.reaped.remove(&user_data);
+        }
+        completion
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testfile::TestFile;
+    use crate::windows::{NT_IORING_HANDLEREF, _NT_IORING_OP_FLAGS_NT_IORING_OP_FLAG_NONE};
+    use crate::{fs, opcode};
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Clone, Default)]
+    struct Trace(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Trace {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn read(ring: &IoRing, file: u64, buf: &mut [u8]) -> squeue::Entry {
+        opcode::Read::new(
+            ring.info.0,
+            NT_IORING_HANDLEREF { Handle: file },
+            NT_IORING_BUFFERREF {
+                Address: buf.as_mut_ptr() as _,
+            },
+            buf.len() as u32,
+            0,
+            _NT_IORING_OP_FLAGS_NT_IORING_OP_FLAG_NONE,
+        )
+        .build()
+    }
+
+    #[test]
+    fn recorded_reads_are_replayed() {
+        let path = TestFile::new("record", b"recorded");
+        let file = std::fs::File::open(path.path()).unwrap();
+        let handle = fs::raw_handle(&file);
+
+        let trace = Trace::default();
+        let mut ring = IoRing::emulated(8).unwrap();
+        ring.set_recorder(Some(Recorder::new(trace.clone())));
+        let mut buf = [0u8; 8];
+        let entry = read(&ring, handle as u64, &mut buf).user_data(1);
+        unsafe { ring.submission().push(&entry).unwrap() };
+        let entry = read(&ring, 0x1234, &mut buf).user_data(2);
+        unsafe { ring.submission().push(&entry).unwrap() };
+        ring.submit().unwrap();
+        assert_eq!(ring.completion().count(), 2);
+        ring.set_recorder(None).unwrap().finish().unwrap();
+
+        let trace = trace.0.lock().unwrap().clone();
+        let text = String::from_utf8(trace.clone()).unwrap();
+        let lines: Vec<_> = text.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert!(lines[0].contains("\"entry\":\"read user_data=1 file="));
+        assert!(lines[2].ends_with("\"entry\":\"user_data=1 information=8\"}"));
+
+        let replay = Replay::read(&trace[..]).unwrap();
+        assert!(matches!(
+            replay.events()[0],
+            Event::Pushed { sqe, .. } if sqe.UserData == 1
+        ));
+        let replay = replay.file(handle as u64, handle);
+        let report = replay.run(&mut IoRing::emulated(8).unwrap()).unwrap();
+        assert_eq!((report.replayed, &report.skipped[..]), (1, &[2][..]));
+        assert!(report.is_ok(), "{:?}", report);
+
+        std::fs::write(path.path(), b"shorter").unwrap();
+        let report = replay.run(&mut IoRing::emulated(8).unwrap()).unwrap();
+        let recorded = Some((0, 8));
+        let replayed = Some((0, 7));
+        assert_eq!(
+            report.mismatches,
+            [Mismatch {
+                user_data: 1,
+                recorded,
+                replayed
+            }]
+        );
+    }
+
+    #[test]
+    fn writes_are_only_replayed_when_enabled() {
+        let path = TestFile::new("record-write", b"original");
+        let file = std::fs::OpenOptions::new()
+            .write(true)
+            .open(path.path())
+            .unwrap();
+        let handle = fs::raw_handle(&file);
+
+        let trace = Trace::default();
+        let mut ring = IoRing::emulated(8).unwrap();
+        ring.set_recorder(Some(Recorder::new(trace.clone())));
+        let data = *b"recorded";
+        let entry = opcode::Write::new(
+            ring.info.0,
+            NT_IORING_HANDLEREF {
+                Handle: handle as _,
+            },
+            NT_IORING_BUFFERREF {
+                Address: data.as_ptr() as _,
+            },
+            data.len() as u32,
+            0,
+            _NT_IORING_OP_FLAGS_NT_IORING_OP_FLAG_NONE,
+        )
+        .build()
+        .user_data(1);
+        unsafe { ring.submission().push(&entry).unwrap() };
+        ring.submit().unwrap();
+        assert_eq!(ring.completion().count(), 1);
+        ring.set_recorder(None).unwrap().finish().unwrap();
+
+        let trace = trace.0.lock().unwrap().clone();
+        let replay = Replay::read(&trace[..])
+            .unwrap()
+            .file(handle as u64, handle);
+        let report = replay.run(&mut IoRing::emulated(8).unwrap()).unwrap();
+        assert_eq!((report.replayed, &report.skipped[..]), (0, &[1][..]));
+        assert_eq!(std::fs::read(path.path()).unwrap(), b"recorded");
+
+        let replay = replay.writes(true);
+        let report = replay.run(&mut IoRing::emulated(8).unwrap()).unwrap();
+        assert_eq!((report.replayed, report.skipped.len()), (1, 0));
+        assert!(report.is_ok(), "{:?}", report);
+        assert_eq!(std::fs::read(path.path()).unwrap(), [0; 8]);
+    }
+
+    #[test]
+    fn malformed_lines_are_rejected() {
+        let err = Event::parse("{\"t\":1,\"op\":\"sqe\",\"raw\":\"00\"}").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(Event::parse("{\"t\":1,\"op\":\"xyz\"}").is_err());
+        let line = "{\"t\":3,\"op\":\"cqe\",\"user_data\":5,\"result\":-1,\"information\":2,\
+                    \"entry\":\"x,\\\"result\\\":7}\"}";
+        match Event::parse(line).unwrap() {
+            Event::Reaped {
+                at,
+                user_data,
+                result,
+                information,
+            } => assert_eq!(
+                (at, user_data, result, information),
+                (Duration::from_micros(3), 5, -1, 2)
+            ),
+            event => panic!("unexpected {:?}", event),
+        }
+    }
+}
@@ -0,0 +1,515 @@
+//! The head and tail indices of the submission and completion queues.
+//!
+//! Both queues are ring buffers with a power of two number of slots, shared by one producer
+//! and one consumer. The indices are free running `u32` counters that are masked to find a
+//! slot: the producer owns the tail, the consumer owns the head, and the `tail - head` slots in
+//! between are filled. The crate produces the submission queue and the kernel consumes it, and
+//! it is the other way around for the completion queue.
+//!
+//! The orderings pair up as follows:
+//!
+//! - the producer fills a slot, then publishes it by storing the tail with `Release`, and the
+//!   consumer loads the tail with `Acquire` before it reads the slots below it;
+//! - the consumer reads a slot, then gives it back by storing the head with `Release`, and the
+//!   producer loads the head with `Acquire` before it overwrites the slots below it.
+//!
+//! An index is only ever stored by its owner, who can load it `Relaxed`. Everyone else loads
+//! it with `Acquire`.
+//!
+//! The entries of the submission queue are built in place, so the crate reserves slots ahead of
+//! the tail it publishes: `win_ring_get_sqe` hands out slots past the `sqe_tail` of the ring, and
+//! only once they are filled does `win_ring_sq_flush` move the tail up to it, when an entry is
+//! pushed or the queue is submitted.
+
+use std::ptr;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use crate::windows::win_ring;
+
+/// An atomic `u32`, so that the indices can be model checked with loom.
+pub(crate) trait Atomic {
+    fn load(&self, order: Ordering) -> u32;
+    fn store(&self, value: u32, order: Ordering);
+}
+
+impl Atomic for AtomicU32 {
+    #[inline]
+    fn load(&self, order: Ordering) -> u32 {
+        AtomicU32::load(self, order)
+    }
+
+    #[inline]
+    fn store(&self, value: u32, order: Ordering) {
+        AtomicU32::store(self, value, order)
+    }
+}
+
+#[cfg(loom)]
+impl Atomic for loom::sync::atomic::AtomicU32 {
+    fn load(&self, order: Ordering) -> u32 {
+        loom::sync::atomic::AtomicU32::load(self, order)
+    }
+
+    fn store(&self, value: u32, order: Ordering) {
+        loom::sync::atomic::AtomicU32::store(self, value, order)
+    }
+}
+
+/// The indices of a ring buffer of `size` slots.
+pub(crate) struct Indices<'a, A: Atomic = AtomicU32> {
+    head: &'a A,
+    tail: &'a A,
+    size: u32,
+}
+
+impl<'a> Indices<'a> {
+    /// The indices of the submission queue of `ring`.
+    ///
+    /// # Safety
+    ///
+    /// `ring` must point to a live ring, which outlives the indices.
+    #[inline]
+    pub(crate) unsafe fn submission(ring: *const win_ring) -> Self {
+        let sq = (*ring).info.__bindgen_anon_1.SubmissionQueue;
+        Self::from_raw(
+            ptr::addr_of!((*sq).Head),
+            ptr::addr_of!((*sq).Tail),
+            (*ring).info.SubmissionQueueSize,
+        )
+    }
+
+    /// The indices of the completion queue of `ring`.
+    ///
+    /// # Safety
+    ///
+    /// `ring` must point to a live ring, which outlives the indices.
+    #[inline]
+    pub(crate) unsafe fn completion(ring: *const win_ring) -> Self {
+        let cq = (*ring).info.__bindgen_anon_2.CompletionQueue;
+        Self::from_raw(
+            ptr::addr_of!((*cq).Head),
+            ptr::addr_of!((*cq).Tail),
+            (*ring).info.CompletionQueueSize,
+        )
+    }
+
+    /// # Safety
+    ///
+    /// `head` and `tail` must be valid and aligned for as long as the indices live, and only be
+    /// accessed atomically meanwhile.
+    #[inline]
+    unsafe fn from_raw(head: *const u32, tail: *const u32, size: u32) -> Self {
+        // `AtomicU32` has the same layout as `u32`.
+        Self::new(&*head.cast(), &*tail.cast(), size)
+    }
+}
+
+impl<'a, A: Atomic> Indices<'a, A> {
+    #[inline]
+    pub(crate) fn new(head: &'a A, tail: &'a A, size: u32) -> Self {
+        debug_assert!(size.is_power_of_two());
+        Self { head, tail, size }
+    }
+
+    /// The slot of an index.
+    #[inline]
+    pub(crate) fn slot(&self, index: u32) -> usize {
+        (index & (self.size - 1)) as usize
+    }
+
+    /// The head, as seen by the producer or by an observer.
+    #[inline]
+    pub(crate) fn head(&self) -> u32 {
+        self.head.load(Ordering::Acquire)
+    }
+
+    /// The tail, as seen by the consumer or by an observer.
+    #[inline]
+    pub(crate) fn tail(&self) -> u32 {
+        self.tail.load(Ordering::Acquire)
+    }
+
+    /// The number of filled slots.
+    #[inline]
+    pub(crate) fn len(&self) -> u32 {
+        self.tail().wrapping_sub(self.head())
+    }
+
+    /// The number of free slots, for a producer that publishes each slot as it fills it.
+    #[inline]
+    pub(crate) fn room(&self) -> u32 {
+        let tail = self.tail.load(Ordering::Relaxed);
+        self.size - tail.wrapping_sub(self.head())
+    }
+
+    /// Fill the next slot with `write` and publish it, for a producer that fills one slot at a
+    /// time. Returns `false` when every slot is taken.
+    #[inline]
+    pub(crate) fn push(&self, write: impl FnOnce(usize)) -> bool {
+        if self.room() == 0 {
+            return false;
+        }
+        let tail = self.tail.load(Ordering::Relaxed);
+        write(self.slot(tail));
+        self.publish(tail.wrapping_add(1));
+        true
+    }
+
+    /// Hand out the slot at `reserved` and move `reserved` past it, for a producer that fills
+    /// slots before publishing them: `reserved` is its own tail, which runs ahead of the
+    /// published one. The slot is the producer's to fill until it [publishes](Self::publish)
+    /// `reserved`. Returns `None` when every slot is taken.
+    // On Windows, libwinring reserves the slots in C.
+    #[cfg_attr(all(windows, not(loom)), allow(dead_code))]
+    #[inline]
+    pub(crate) fn reserve(&self, reserved: &mut u32) -> Option<usize> {
+        if reserved.wrapping_sub(self.head()) == self.size {
+            return None;
+        }
+        let slot = self.slot(*reserved);
+        *reserved = reserved.wrapping_add(1);
+        Some(slot)
+    }
+
+    /// Move the tail, for the producer: the slots up to `tail` must have been filled.
+    #[inline]
+    pub(crate) fn publish(&self, tail: u32) {
+        self.tail.store(tail, Ordering::Release);
+    }
+
+    /// Read the slot at the head with `read` and give it back, for the consumer.
+    #[inline]
+    pub(crate) fn pop<T>(&self, read: impl FnOnce(usize) -> T) -> Option<T> {
+        let head = self.head.load(Ordering::Relaxed);
+        if head == self.tail() {
+            return None;
+        }
+        let value = read(self.slot(head));
+        self.release(head.wrapping_add(1));
+        Some(value)
+    }
+
+    /// Move the head, for the consumer: the slots up to `head` must have been read.
+    #[inline]
+    pub(crate) fn release(&self, head: u32) {
+        self.head.store(head, Ordering::Release);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+    use std::sync::atomic::AtomicU32;
+
+    use proptest::prelude::*;
+
+    use super::Indices;
+    use crate::windows::{win_ring_cq_ready, win_ring_sq_ready};
+    use crate::{opcode, IoRing};
+
+    #[derive(Clone, Debug)]
+    enum Op {
+        Push(u8),
+        Submit,
+        Reap(u8),
+    }
+
+    fn ops() -> impl Strategy<Value = Vec<Op>> {
+        let op = prop_oneof![
+            (1..=16u8).prop_map(Op::Push),
+            Just(Op::Submit),
+            (1..=16u8).prop_map(Op::Reap),
+        ];
+        prop::collection::vec(op, 1..1000)
+    }
+
+    /// An emulated ring whose indices all start at `start`.
+    fn ring_at(entries: u32, start: u32) -> IoRing {
+        let ring = IoRing::emulated(entries).unwrap();
+        unsafe {
+            for indices in [
+                Indices::submission(ring.info.0),
+                Indices::completion(ring.info.0),
+            ] {
+                indices.release(start);
+                indices.publish(start);
+            }
+            (*ring.info.0).sqe_tail = start;
+        }
+        ring
+    }
+
+    proptest! {
+        #[test]
+        fn indices_wrap_around(
+            size in (0..8u32).prop_map(|bits| 1 << bits),
+            start in (0..512u32).prop_map(|before| u32::MAX - before),
+            steps in prop::collection::vec((any::<bool>(), 1..=16u32), 1..2000),
+        ) {
+            let (head, tail) = (AtomicU32::new(start), AtomicU32::new(start));
+            let indices = Indices::new(&head, &tail, size);
+            let mut slots = vec![0u64; size as usize];
+            let mut queue = VecDeque::new();
+            let (mut pushed, mut popped) = (0, 0);
+            for (push, count) in steps {
+                for _ in 0..count {
+                    if push {
+                        let room = indices.room() as usize;
+                        let done = indices.push(|slot| slots[slot] = pushed);
+                        prop_assert_eq!(done, room > 0);
+                        if done {
+                            queue.push_back(pushed);
+                            pushed += 1;
+                        }
+                    } else {
+                        let value = indices.pop(|slot| slots[slot]);
+                        prop_assert_eq!(value, queue.pop_front());
+                        popped += value.is_some() as u64;
+                    }
+                    prop_assert_eq!(indices.len() as usize, queue.len());
+                    prop_assert_eq!(indices.len() + indices.room(), size);
+                }
+            }
+            prop_assert_eq!(pushed - popped, queue.len() as u64);
+        }
+    }
+
+    proptest! {
+        /// Every entry pushed is reaped exactly once and in order, however the indices wrap.
+        #[test]
+        fn entries_survive_wraparound(
+            entries in 1..=64u32,
+            start in (0..1024u32).prop_map(|before| u32::MAX - before),
+            ops in ops(),
+        ) {
+            let mut ring = ring_at(entries, start);
+            let ptr = ring.info.0;
+            let (mut pushed, mut reaped) = (0u64, 0u64);
+            for op in ops {
+                match op {
+                    Op::Push(count) => {
+                        for _ in 0..count {
+                            let mut sq = ring.submission();
+                            let full = sq.is_full();
+                            let nop = opcode::Nop::new(ptr).build().user_data(pushed);
+                            prop_assert_eq!(unsafe { sq.push(&nop) }.is_err(), full);
+                            pushed += !full as u64;
+                        }
+                    }
+                    Op::Submit => {
+                        ring.submit().unwrap();
+                    }
+                    Op::Reap(count) => {
+                        for entry in ring.completion().take(count as usize) {
+                            prop_assert_eq!(entry.user_data(), reaped);
+                            reaped += 1;
+                        }
+                    }
+                }
+                let sq = ring.submission();
+                let sq_len = sq.len();
+                prop_assert_eq!(sq_len, unsafe { win_ring_sq_ready(ptr) } as usize);
+                prop_assert!(sq_len <= sq.capacity());
+                prop_assert_eq!(sq.is_empty(), sq_len == 0);
+                prop_assert_eq!(sq.is_full(), sq_len == sq.capacity());
+                let cq = ring.completion();
+                let cq_len = cq.len();
+                prop_assert_eq!(cq_len, unsafe { win_ring_cq_ready(ptr) } as usize);
+                prop_assert!(cq_len <= cq.capacity());
+                prop_assert_eq!(cq.is_empty(), cq_len == 0);
+                prop_assert_eq!(cq.is_full(), cq_len == cq.capacity());
+                // Nops complete as soon as they are submitted, so nothing else is in flight.
+                prop_assert_eq!(pushed, reaped + (sq_len + cq_len) as u64);
+            }
+            while reaped < pushed {
+                ring.submit().unwrap();
+                for entry in ring.completion() {
+                    prop_assert_eq!(entry.user_data(), reaped);
+                    reaped += 1;
+                }
+            }
+        }
+    }
+}
+
+/// Model checks of the indices against a simulated kernel, with
+/// `RUSTFLAGS="--cfg loom" cargo test --release --lib ring`.
+#[cfg(all(test, loom))]
+mod model {
+    use loom::cell::UnsafeCell;
+    use loom::sync::atomic::AtomicU32;
+    use loom::sync::Arc;
+    use loom::thread;
+
+    use super::Indices;
+
+    struct Queue {
+        head: AtomicU32,
+        tail: AtomicU32,
+        slots: Vec<UnsafeCell<u64>>,
+    }
+
+    // The slots are only accessed as the indices allow, which is what loom checks.
+    unsafe impl Sync for Queue {}
+
+    impl Queue {
+        fn new(size: usize) -> Arc<Self> {
+            Arc::new(Self {
+                head: AtomicU32::new(0),
+                tail: AtomicU32::new(0),
+                slots: (0..size).map(|_| UnsafeCell::new(0)).collect(),
+            })
+        }
+
+        fn indices(&self) -> Indices<'_, AtomicU32> {
+            Indices::new(&self.head, &self.tail, self.slots.len() as u32)
+        }
+
+        /// Like the emulator posting a completion.
+        fn push(&self, value: u64) {
+            let indices = self.indices();
+            while !indices.push(|slot| self.slots[slot].with_mut(|p| unsafe { *p = value })) {
+                thread::yield_now();
+            }
+        }
+
+        fn pop(&self) -> u64 {
+            let indices = self.indices();
+            loop {
+                let value = indices.pop(|slot| self.slots[slot].with(|p| unsafe { *p }));
+                match value {
+                    Some(value) => return value,
+                    None => thread::yield_now(),
+                }
+            }
+        }
+    }
+
+    /// The crate's side of the submission queue: `win_ring_get_sqe` reserves a slot, the
+    /// opcode builders fill it, and `win_ring_sq_flush` publishes it on push or submit.
+    struct Submissions<'a> {
+        sq: &'a Queue,
+        /// The `sqe_tail` of the ring.
+        reserved: u32,
+    }
+
+    impl Submissions<'_> {
+        fn get_sqe(&mut self) -> usize {
+            let indices = self.sq.indices();
+            loop {
+                match indices.reserve(&mut self.reserved) {
+                    Some(slot) => return slot,
+                    None => thread::yield_now(),
+                }
+            }
+        }
+
+        fn fill(&self, slot: usize, user_data: u64) {
+            self.sq.slots[slot].with_mut(|p| unsafe { *p = user_data });
+        }
+
+        fn flush(&self) {
+            self.sq.indices().publish(self.reserved);
+        }
+
+        /// Build an entry in place and push it.
+        fn push(&mut self, user_data: u64) {
+            let slot = self.get_sqe();
+            self.fill(slot, user_data);
+            self.flush();
+        }
+    }
+
+    #[test]
+    fn kernel_takes_pushed_entries_in_order() {
+        loom::model(|| {
+            let sq = Queue::new(2);
+            let kernel = {
+                let sq = sq.clone();
+                thread::spawn(move || (0..3).map(|_| sq.pop()).collect::<Vec<_>>())
+            };
+            let mut submissions = Submissions {
+                sq: &sq,
+                reserved: 0,
+            };
+            for user_data in 1..=3 {
+                submissions.push(user_data);
+            }
+            assert_eq!(kernel.join().unwrap(), [1, 2, 3]);
+        });
+    }
+
+    #[test]
+    fn reserved_entries_are_hidden_until_flushed() {
+        loom::model(|| {
+            let sq = Queue::new(2);
+            let kernel = {
+                let sq = sq.clone();
+                thread::spawn(move || (0..2).map(|_| sq.pop()).collect::<Vec<_>>())
+            };
+            // Both slots are handed out before either is filled, and the kernel may look at the
+            // queue at any point meanwhile: loom fails on any read of a slot being filled.
+            let mut submissions = Submissions {
+                sq: &sq,
+                reserved: 0,
+            };
+            let first = submissions.get_sqe();
+            let second = submissions.get_sqe();
+            submissions.fill(second, 2);
+            submissions.fill(first, 1);
+            submissions.flush();
+            assert_eq!(kernel.join().unwrap(), [1, 2]);
+        });
+    }
+
+    #[test]
+    fn completions_are_reaped_in_batches() {
+        loom::model(|| {
+            let cq = Queue::new(2);
+            let kernel = {
+                let cq = cq.clone();
+                thread::spawn(move || (1..=3).for_each(|user_data| cq.push(user_data)))
+            };
+            // Like `CompletionQueue`: read up to the tail, then give the slots back at once.
+            let indices = cq.indices();
+            let mut reaped = Vec::new();
+            let mut head = 0;
+            while reaped.len() < 3 {
+                let tail = indices.tail();
+                while head != tail {
+                    reaped.push(cq.slots[indices.slot(head)].with(|p| unsafe { *p }));
+                    head = head.wrapping_add(1);
+                }
+                indices.release(head);
+                thread::yield_now();
+            }
+            kernel.join().unwrap();
+            assert_eq!(reaped, [1, 2, 3]);
+        });
+    }
+
+    #[test]
+    fn submissions_complete_through_both_queues() {
+        loom::model(|| {
+            let (sq, cq) = (Queue::new(1), Queue::new(1));
+            let kernel = {
+                let (sq, cq) = (sq.clone(), cq.clone());
+                thread::spawn(move || {
+                    for _ in 0..2 {
+                        let user_data = sq.pop();
+                        cq.push(user_data);
+                    }
+                })
+            };
+            let mut submissions = Submissions {
+                sq: &sq,
+                reserved: 0,
+            };
+            submissions.push(1);
+            submissions.push(2);
+            assert_eq!(cq.pop(), 1);
+            assert_eq!(cq.pop(), 2);
+            kernel.join().unwrap();
+        });
+    }
+}
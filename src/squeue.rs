@@ -1,10 +1,31 @@
+use std::collections::VecDeque;
 use std::error::Error;
+use std::ffi::c_void;
 use std::fmt::{self, Debug, Display, Formatter};
+use std::time::{Duration, Instant};
+use std::{io, mem, ptr};
 
+use crate::cqueue;
+use crate::record;
+use crate::ring::Indices;
+use crate::stats;
+use crate::submit::Submitter;
+use crate::timer::Timer;
+use crate::trace;
 use crate::windows::{
-    win_ring, win_ring_sqe_set_data64, win_ring_sqe_set_flags, _NT_IORING_SQE,
-    _NT_IORING_SQE_FLAGS, _NT_IORING_SUBMISSION_QUEUE,
+    win_ring, win_ring_get_sqe, win_ring_prep_nop, win_ring_sq_flush, win_ring_sqe_set_data64,
+    win_ring_sqe_set_flags, FILE_FLUSH_MODE, FILE_FLUSH_MODE_FILE_FLUSH_DATA,
+    FILE_FLUSH_MODE_FILE_FLUSH_DEFAULT, FILE_FLUSH_MODE_FILE_FLUSH_MIN_METADATA,
+    FILE_FLUSH_MODE_FILE_FLUSH_NO_SYNC, FILE_WRITE_FLAGS, HANDLE, IORING_BUFFER_INFO,
+    NT_IORING_BUFFERREF, NT_IORING_HANDLEREF, _IORING_OP_CODE_IORING_OP_CANCEL,
+    _IORING_OP_CODE_IORING_OP_FLUSH, _IORING_OP_CODE_IORING_OP_NOP, _IORING_OP_CODE_IORING_OP_READ,
+    _IORING_OP_CODE_IORING_OP_REGISTER_BUFFERS, _IORING_OP_CODE_IORING_OP_REGISTER_FILES,
+    _IORING_OP_CODE_IORING_OP_WRITE, _NT_IORING_OP_FLAGS,
+    _NT_IORING_OP_FLAGS_NT_IORING_OP_FLAG_REGISTERED_BUFFER,
+    _NT_IORING_OP_FLAGS_NT_IORING_OP_FLAG_REGISTERED_FILE, _NT_IORING_SQE, _NT_IORING_SQE_FLAGS,
+    _NT_IORING_SQE_FLAGS_NT_IORING_SQE_FLAG_DRAIN_PRECEDING_OPS, _NT_IORING_SUBMISSION_QUEUE,
 };
+use crate::Shared;
 
 pub struct SubmissionQueue<'a> {
     head: u32,
@@ -16,44 +37,266 @@ unsafe impl Sync for SubmissionQueue<'_> {}
 
 /// An entry in the submission queue, representing a request for an I/O operation.
 ///
-/// These can be created via the opcodes in [`opcode`](crate::opcode).
-#[repr(transparent)]
-#[derive(Clone)]
-pub struct Entry(pub(crate) *mut _NT_IORING_SQE);
+/// These can be created via the opcodes in [`opcode`](crate::opcode). An entry is built in place
+/// in the submission queue; if the queue is full, or the opcode is given a null ring, it is built
+/// on the heap instead, and only enters the queue, or the ring's
+/// [backlog](crate::IoRing::set_backlog), when it is [pushed](SubmissionQueue::push).
+pub struct Entry(
+    pub(crate) *mut _NT_IORING_SQE,
+    pub(crate) Option<Duration>,
+    /// The entry itself while it is not in the submission queue.
+    pub(crate) Option<Box<_NT_IORING_SQE>>,
+);
 
-/// An error pushing to the submission queue due to it being full.
+impl Clone for Entry {
+    fn clone(&self) -> Entry {
+        match &self.2 {
+            Some(detached) => Entry::detached(**detached, self.1),
+            None => Entry(self.0, self.1, None),
+        }
+    }
+}
+
+/// The smallest `user_data` the crate reserves for the operations it submits on its own behalf,
+/// whose completions are not reported. Every value from it up to `u64::MAX` is reserved, see
+/// [`Entry::user_data`].
+pub const RESERVED_USER_DATA: u64 = u64::MAX - 0xff;
+
+/// `user_data` given to an entry built in place whose own `user_data` was refused, so that the
+/// slot it took completes unreported.
+pub(crate) const REFUSED_USER_DATA: u64 = u64::MAX - 4;
+
+/// An error pushing to the submission queue.
 #[derive(Debug, Clone, PartialEq, Eq)]
 #[non_exhaustive]
-pub struct PushError;
+pub enum PushError {
+    /// The submission queue is full, and the backlog is disabled.
+    Full,
+    /// The entry's `user_data` is one the crate reserves, see [`RESERVED_USER_DATA`].
+    ReservedUserData(u64),
+}
 
 impl Display for PushError {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        f.write_str("submission queue is full")
+        match self {
+            PushError::Full => f.write_str("submission queue is full"),
+            PushError::ReservedUserData(user_data) => {
+                write!(f, "user_data {:#x} is reserved by the crate", user_data)
+            }
+        }
     }
 }
 
 impl Error for PushError {}
+
+/// Entries waiting in user space for room in the submission queue, see
+/// [`IoRing::set_backlog`](crate::IoRing::set_backlog).
+#[derive(Default)]
+pub(crate) struct Backlog {
+    pub(crate) enabled: bool,
+    pub(crate) entries: VecDeque<_NT_IORING_SQE>,
+    peak: usize,
+    total: u64,
+}
+
+/// Metrics on the backlog of a ring, see [`IoRing::backlog_stats`](crate::IoRing::backlog_stats).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct BacklogStats {
+    /// The number of entries waiting in the backlog.
+    pub depth: usize,
+    /// The largest number of entries that have been waiting at once.
+    pub peak: usize,
+    /// The number of entries that have gone through the backlog.
+    pub total: u64,
+}
+
+impl Backlog {
+    pub(crate) fn push(&mut self, sqe: _NT_IORING_SQE) {
+        self.entries.push_back(sqe);
+        self.peak = self.peak.max(self.entries.len());
+        self.total += 1;
+    }
+
+    pub(crate) fn stats(&self) -> BacklogStats {
+        BacklogStats {
+            depth: self.entries.len(),
+            peak: self.peak,
+            total: self.total,
+        }
+    }
+
+    /// Move as many waiting entries as fit into the submission queue of `ring`, and return
+    /// whether the backlog is empty.
+    pub(crate) unsafe fn flush(&mut self, ring: *const win_ring) -> bool {
+        while let Some(sqe) = self.entries.front() {
+            let slot = win_ring_get_sqe(ring);
+            if slot.is_null() {
+                break;
+            }
+            *slot = *sqe;
+            self.entries.pop_front();
+        }
+        win_ring_sq_flush(ring.cast_mut());
+        self.entries.is_empty()
+    }
+}
+
+/// Start the timeout of a queued `entry`, if it has one, and return its handle.
+pub(crate) fn arm(shared: &Shared, entry: &Entry) -> OpHandle {
+    trace::pushed(unsafe { &*entry.0 });
+    record::pushed(shared, unsafe { &*entry.0 });
+    let handle = OpHandle::new(unsafe { &*entry.0 });
+    track(shared, handle.target);
+    if let (Some(timeout), Some((file, flags))) = (entry.1, handle.target) {
+        let timer = Timer {
+            user_data: handle.user_data,
+            file,
+            flags,
+        };
+        shared
+            .timers
+            .lock()
+            .unwrap()
+            .insert(Instant::now() + timeout, timer);
+    }
+    handle
+}
+
+/// Remember the file of an operation, for [`ShutdownMode::Cancel`](crate::ShutdownMode::Cancel).
+fn track(shared: &Shared, target: Option<(NT_IORING_HANDLEREF, _NT_IORING_OP_FLAGS)>) {
+    if let Some((file, flags)) = target {
+        let registered = flags & _NT_IORING_OP_FLAGS_NT_IORING_OP_FLAG_REGISTERED_FILE;
+        let handle = unsafe { file.Handle };
+        shared.targets.lock().unwrap().insert((handle, registered));
+    }
+}
+
+/// Put `entry` into the submission queue of `ring` if it was built outside of it, or into the
+/// backlog if the queue is still full and the backlog is enabled, and publish the queue to the
+/// kernel.
+pub(crate) unsafe fn enqueue(
+    ring: *const win_ring,
+    shared: &Shared,
+    entry: &Entry,
+) -> Result<(), PushError> {
+    let detached = match &entry.2 {
+        Some(detached) => detached,
+        None => {
+            // Built in the slot `claim` reserved for it.
+            win_ring_sq_flush(ring.cast_mut());
+            return Ok(());
+        }
+    };
+    let mut backlog = shared.backlog.lock().unwrap();
+    // Entries already waiting go first.
+    if backlog.entries.is_empty() {
+        let slot = win_ring_get_sqe(ring);
+        if !slot.is_null() {
+            *slot = **detached;
+            win_ring_sq_flush(ring.cast_mut());
+            return Ok(());
+        }
+    }
+    if !backlog.enabled {
+        return Err(PushError::Full);
+    }
+    backlog.push(**detached);
+    Ok(())
+}
+
+/// Refuse `entry` if `user_data`, its own or that of a link chained to it, is
+/// [reserved](RESERVED_USER_DATA). The slot of an entry built in place is submitted anyway, so
+/// it is turned into a NOP that is not reported.
+unsafe fn check_user_data(entry: &Entry, user_data: u64) -> Result<(), PushError> {
+    if user_data < RESERVED_USER_DATA {
+        return Ok(());
+    }
+    if entry.2.is_none() {
+        win_ring_prep_nop(entry.0);
+        win_ring_sqe_set_data64(entry.0, REFUSED_USER_DATA);
+    }
+    Err(PushError::ReservedUserData(user_data))
+}
+
+/// A handle to an operation that has been [pushed](SubmissionQueue::push), used to cancel it.
+///
+/// Cancelling does not make the operation disappear: it still produces its completion, with
+/// `HRESULT_FROM_WIN32(ERROR_OPERATION_ABORTED)` as its result unless it completed first. Its
+/// buffers must stay valid until that completion has been reaped.
+#[derive(Clone, Copy)]
+pub struct OpHandle {
+    user_data: u64,
+    target: Option<(NT_IORING_HANDLEREF, _NT_IORING_OP_FLAGS)>,
+}
+
+impl OpHandle {
+    pub(crate) fn new(sqe: &_NT_IORING_SQE) -> OpHandle {
+        OpHandle {
+            user_data: sqe.UserData,
+            target: target(sqe),
+        }
+    }
+
+    /// The user data of the operation.
+    #[inline]
+    pub fn user_data(&self) -> u64 {
+        self.user_data
+    }
+
+    /// Queue an [`opcode::Cancel`](crate::opcode::Cancel) for the operation, to be sent with the
+    /// next submit of the ring that `submitter` belongs to, which must be the ring it was pushed
+    /// to. The completion of the cancel itself is not reported.
+    ///
+    /// Operations that do not refer to a file, such as a nop, cannot be cancelled and nothing is
+    /// queued for them. The operation's `user_data` must be unique among the operations in flight
+    /// on its file, and must not be `0`, which would cancel all of them.
+    pub fn cancel(&self, submitter: &Submitter<'_>) -> io::Result<()> {
+        let (file, flags) = match self.target {
+            Some(target) => target,
+            None => return Ok(()),
+        };
+        if self.user_data == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "an operation with user_data 0 cannot be cancelled on its own",
+            ));
+        }
+        submitter.queue_cancel(file, flags, self.user_data)
+    }
+}
+
+impl Debug for OpHandle {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OpHandle")
+            .field("user_data", &self.user_data)
+            .finish()
+    }
+}
 pub struct Inner {
-    ring_mask: u32,
     info: *mut win_ring,
     pub sqes: *mut _NT_IORING_SUBMISSION_QUEUE,
+    shared: *const Shared,
 }
 impl Inner {
-    pub unsafe fn new(p: *mut win_ring) -> Self {
-        let ring_mask = (*p).info.SubmissionQueueRingMask;
+    pub(crate) unsafe fn new(p: *mut win_ring, shared: *const Shared) -> Self {
         let sqes = (*p).info.__bindgen_anon_1.SubmissionQueue;
         Self {
-            ring_mask,
             sqes,
             info: p,
+            shared,
         }
     }
 
+    /// # Safety
+    ///
+    /// No other [`SubmissionQueue`]s may exist when calling this function.
     #[inline]
     pub unsafe fn borrow_shared(&self) -> SubmissionQueue<'_> {
+        let indices = Indices::submission(self.info);
         SubmissionQueue {
-            head: self.sqes.as_ref().unwrap().Head,
-            tail: self.sqes.as_ref().unwrap().Tail,
+            head: indices.head(),
+            tail: indices.tail(),
             queue: (self as *const Self as *mut Self).as_mut().unwrap(),
         }
     }
@@ -72,10 +315,9 @@ impl SubmissionQueue<'_> {
     /// consumed some entries in the meantime.
     #[inline]
     pub fn sync(&mut self) {
-        unsafe {
-            self.tail = self.queue.sqes.as_ref().unwrap().Head;
-            self.head = self.queue.sqes.as_ref().unwrap().Tail;
-        }
+        let indices = unsafe { Indices::submission(self.queue.info) };
+        self.head = indices.head();
+        self.tail = indices.tail();
     }
 
     /// Get the total number of entries in the submission queue ring buffer.
@@ -103,28 +345,219 @@ impl SubmissionQueue<'_> {
         self.len() == self.capacity()
     }
 
-    /// Attempts to push an [`Entry`] into the queue.
-    /// If the queue is full, an error is returned.
+    /// Attempts to push an [`Entry`] into the queue, returning an [`OpHandle`] to cancel it.
+    /// If the queue is full, the entry goes to the ring's [backlog](crate::IoRing::set_backlog)
+    /// if it is enabled, and an error is returned otherwise.
+    ///
+    /// An entry whose `user_data` is [reserved](RESERVED_USER_DATA) is refused with
+    /// [`PushError::ReservedUserData`]. If it was built in the submission queue, its slot is
+    /// turned into a NOP whose completion is not reported.
     ///
     /// # Safety
     ///
     /// Developers must ensure that parameters of the [`Entry`] (such as buffer) are valid and will
     /// be valid for the entire duration of the operation, otherwise it may cause memory problems.
     #[inline]
-    pub unsafe fn push(&mut self, Entry(_entry): &Entry) -> Result<(), PushError> {
+    pub unsafe fn push(&mut self, entry: &Entry) -> Result<OpHandle, PushError> {
+        check_user_data(entry, (*entry.0).UserData)?;
+        enqueue(self.queue.info, &*self.queue.shared, entry)?;
+        let handle = arm(&*self.queue.shared, entry);
         self.sync();
-        if !self.is_full() {
-            // let sqe = win_ring_get_sqe(self.queue.info);
-            // *sqe = **entry;
-            self.sync();
-            Ok(())
-        } else {
-            Err(PushError)
+        Ok(handle)
+    }
+
+    /// Attempts to push an [`Entry`] that must not start before every operation pushed ahead of
+    /// it has completed, by adding
+    /// [`NT_IORING_SQE_FLAG_DRAIN_PRECEDING_OPS`](crate::windows::_NT_IORING_SQE_FLAGS_NT_IORING_SQE_FLAG_DRAIN_PRECEDING_OPS)
+    /// to its flags. Operations pushed after it may still overtake it.
+    /// If the queue is full, an error is returned.
+    ///
+    /// To only learn when the earlier operations are done, push an
+    /// [`opcode::Fence`](crate::opcode::Fence) instead.
+    ///
+    /// # Safety
+    ///
+    /// Developers must ensure that parameters of the [`Entry`] (such as buffer) are valid and will
+    /// be valid for the entire duration of the operation, otherwise it may cause memory problems.
+    #[inline]
+    pub unsafe fn push_barrier(&mut self, entry: &Entry) -> Result<OpHandle, PushError> {
+        (*entry.0).Flags |= _NT_IORING_SQE_FLAGS_NT_IORING_SQE_FLAG_DRAIN_PRECEDING_OPS;
+        self.push(entry)
+    }
+
+    /// Attempts to push a [`Chain`] into the queue. Only its first entry is queued now, the
+    /// others are queued as their predecessors complete. The returned [`OpHandle`] cancels the
+    /// first entry, which aborts the rest of the chain.
+    /// If the queue is full, an error is returned.
+    ///
+    /// # Safety
+    ///
+    /// Developers must ensure that parameters of every [`Entry`] in the chain (such as buffer) are
+    /// valid and will be valid for the entire duration of the chain, otherwise it may cause memory
+    /// problems.
+    pub unsafe fn push_chain(&mut self, chain: Chain) -> Result<OpHandle, PushError> {
+        let Chain { first, links } = chain;
+        for link in &links {
+            check_user_data(&first, link.user_data)?;
+        }
+        let handle = self.push(&first)?;
+        if !links.is_empty() {
+            (*self.queue.shared)
+                .chains
+                .lock()
+                .unwrap()
+                .insert((*first.0).UserData, links);
         }
+        Ok(handle)
+    }
+}
+
+/// A sequence of operations where each one is queued only once the previous one has completed
+/// successfully.
+///
+/// Windows IoRing has no equivalent of io_uring's `IOSQE_IO_LINK`, so the chain is driven from
+/// user space: when the completion of a link is reaped from the
+/// [`CompletionQueue`](crate::cqueue::CompletionQueue), the next link is built from that
+/// completion and queued, to be sent to the kernel by the next submit. Every link still produces
+/// its own completion.
+///
+/// If a link fails, the rest of the chain is not queued; each remaining link instead completes
+/// with `HRESULT_FROM_WIN32(ERROR_OPERATION_ABORTED)`.
+///
+/// The `user_data` of every link, including the first, must be unique among the operations in
+/// flight on the ring.
+///
+/// # Examples
+///
+/// Write out what was read, with the byte count of the read:
+///
+/// ```no_run
+/// use ioring_rs::windows::{
+///     NT_IORING_BUFFERREF, NT_IORING_HANDLEREF, _NT_IORING_OP_FLAGS_NT_IORING_OP_FLAG_NONE,
+/// };
+/// use ioring_rs::{fs::raw_handle, opcode, squeue::Chain, IoRing};
+///
+/// # fn main() -> std::io::Result<()> {
+/// let mut ring = IoRing::new(32)?;
+/// let src = std::fs::File::open("foo.txt")?;
+/// let dst = std::fs::File::create("bar.txt")?;
+/// let mut buf = vec![0u8; 4096];
+///
+/// let ptr = ring.info.0;
+/// let flags = _NT_IORING_OP_FLAGS_NT_IORING_OP_FLAG_NONE;
+/// let buffer = NT_IORING_BUFFERREF { Address: buf.as_mut_ptr() as _ };
+/// let src = NT_IORING_HANDLEREF { Handle: raw_handle(&src) as _ };
+/// let dst = NT_IORING_HANDLEREF { Handle: raw_handle(&dst) as _ };
+///
+/// let read = opcode::Read::new(ptr, src, buffer, 4096, 0, flags).build().user_data(1);
+/// let chain = Chain::new(read).then(2, move |read| {
+///     let len = read.information() as u32;
+///     opcode::Write::new(std::ptr::null_mut(), dst, buffer, len, 0, flags).build()
+/// });
+/// unsafe { ring.submission().push_chain(chain).expect("submission queue is full") };
+///
+/// ring.submit_and_wait(1)?;
+/// let read = ring.completion().next().expect("completion queue is empty");
+/// assert_eq!(read.user_data(), 1);
+///
+/// // Reaping the read queued the write.
+/// ring.submit_and_wait(1)?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct Chain {
+    first: Entry,
+    links: VecDeque<Link>,
+}
+
+pub(crate) struct Link {
+    user_data: u64,
+    build: Box<dyn FnOnce(&cqueue::Entry) -> Entry + Send>,
+}
+
+impl Chain {
+    /// Start a chain with an already built `first` entry.
+    pub fn new(first: Entry) -> Chain {
+        Chain {
+            first,
+            links: VecDeque::new(),
+        }
+    }
+
+    /// Append a link to the chain. `build` is called with the completion of the previous link
+    /// and returns the next entry, whose user data is then set to `user_data`.
+    ///
+    /// `build` runs on whichever thread reaps that completion, which is why it must be `Send`.
+    /// It can build its entry with a null ring instead of capturing the ring's pointer.
+    pub fn then<F>(mut self, user_data: u64, build: F) -> Chain
+    where
+        F: FnOnce(&cqueue::Entry) -> Entry + Send + 'static,
+    {
+        self.links.push_back(Link {
+            user_data,
+            build: Box::new(build),
+        });
+        self
+    }
+}
+
+/// Queue the next link of the chain waiting on `cqe`, if there is one.
+pub(crate) fn advance_chain(ring: *const win_ring, shared: &Shared, cqe: &cqueue::Entry) {
+    let mut links = match shared.chains.lock().unwrap().remove(&cqe.user_data()) {
+        Some(links) => links,
+        None => return,
+    };
+    let mut aborted = Vec::new();
+    if cqe.result() >= 0 {
+        let link = links.pop_front().unwrap();
+        let entry = (link.build)(cqe).user_data(link.user_data);
+        if unsafe { enqueue(ring, shared, &entry) }.is_ok() {
+            trace::pushed(unsafe { &*entry.0 });
+            record::pushed(shared, unsafe { &*entry.0 });
+            track(shared, target(unsafe { &*entry.0 }));
+            if !links.is_empty() {
+                shared.chains.lock().unwrap().insert(link.user_data, links);
+            }
+            return;
+        }
+        // The submission queue is full and there is no backlog, so this link fails as well.
+        aborted.push(link.user_data);
+    }
+    aborted.extend(links.iter().map(|link| link.user_data));
+
+    let mut synthetic = shared.synthetic.lock().unwrap();
+    for user_data in aborted {
+        synthetic.push_back(cqueue::Entry::synthetic(
+            user_data,
+            cqueue::OPERATION_ABORTED,
+            0,
+        ));
     }
 }
 
 impl Entry {
+    /// Build the next entry in the submission queue of `ring`, or on the heap if it is full or
+    /// `ring` is null. A slot of the queue is only reserved: the kernel does not see the entry
+    /// until it is [pushed](SubmissionQueue::push) or the queue is submitted.
+    pub(crate) unsafe fn claim(ring: *const win_ring) -> Entry {
+        let sqe = if ring.is_null() {
+            ptr::null_mut()
+        } else {
+            win_ring_get_sqe(ring)
+        };
+        if sqe.is_null() {
+            Entry::detached(mem::zeroed(), None)
+        } else {
+            Entry(sqe, None, None)
+        }
+    }
+
+    /// An entry that lives outside of the submission queue.
+    pub(crate) fn detached(sqe: _NT_IORING_SQE, timeout: Option<Duration>) -> Entry {
+        let mut detached = Box::new(sqe);
+        Entry(&mut *detached, timeout, Some(detached))
+    }
+
     /// Set the submission event's [flags](Flags).
     #[inline]
     pub fn flags(self, flags: _NT_IORING_SQE_FLAGS) -> Entry {
@@ -134,8 +567,27 @@ impl Entry {
         self
     }
 
+    /// Give the operation `timeout` to complete, counted from when the entry is
+    /// [pushed](SubmissionQueue::push). Past that, the ring cancels it, and its completion
+    /// reports [`ErrorKind::TimedOut`](std::io::ErrorKind::TimedOut) from
+    /// [`into_result`](crate::cqueue::Entry::into_result) instead of the abort.
+    ///
+    /// IoRing has no timeout opcode, so the deadline is only checked while the ring is being
+    /// submitted to or waited on. Only operations on a file (reads, writes and flushes) can time
+    /// out; the `user_data` must be set before the entry is pushed and be unique among the
+    /// operations in flight.
+    #[inline]
+    pub fn timeout(mut self, timeout: Duration) -> Entry {
+        self.1 = Some(timeout);
+        self
+    }
+
     /// Set the user data. This is an application-supplied value that will be passed straight
     /// through into the [completion queue entry](crate::cqueue::Entry::user_data).
+    ///
+    /// The values from [`RESERVED_USER_DATA`] up to `u64::MAX` are reserved for the operations
+    /// the crate submits itself, whose completions are not reported. Pushing an entry with one
+    /// of them fails with [`PushError::ReservedUserData`].
     #[inline]
     pub fn user_data(self, user_data: u64) -> Entry {
         unsafe {
@@ -144,40 +596,712 @@ impl Entry {
         self
     }
 
-    /// Set the personality of this event. You can obtain a personality using
-    /// [`Submitter::register_personality`](crate::Submitter::register_personality).
-    ///
-    /// Requires the `unstable` feature.
-    #[cfg(feature = "unstable")]
-    pub fn personality(mut self, personality: u16) -> Entry {
-        self.0.personality = personality;
-        self
+    /// Decode the entry back into the operation it performs.
+    #[inline]
+    pub fn operation(&self) -> Operation {
+        Operation::decode(unsafe { &*self.0 })
     }
 }
 
-impl Debug for Entry {
-    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+/// The file an operation targets, and the flags telling how it is referred to, if any.
+pub(crate) fn target(sqe: &_NT_IORING_SQE) -> Option<(NT_IORING_HANDLEREF, _NT_IORING_OP_FLAGS)> {
+    unsafe {
+        let op = &sqe.__bindgen_anon_1;
+        match sqe.OpCode {
+            _IORING_OP_CODE_IORING_OP_READ => Some((op.Read.File, op.Read.CommonOpFlags)),
+            _IORING_OP_CODE_IORING_OP_WRITE => Some((op.Write.File, op.Write.CommonOpFlags)),
+            _IORING_OP_CODE_IORING_OP_FLUSH => Some((op.Flush.File, op.Flush.CommonOpFlags)),
+            _IORING_OP_CODE_IORING_OP_CANCEL => Some((op.Cancel.File, op.Cancel.CommonOpFlags)),
+            _ => None,
+        }
+    }
+}
+
+/// An entry decoded into the operation it performs, see [`Entry::operation`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Operation {
+    /// A [`Nop`](crate::opcode::Nop) or a [`Fence`](crate::opcode::Fence), with the result and
+    /// information it completes with on an emulated ring.
+    Nop {
+        result: i32,
+        information: u64,
+    },
+    Read {
+        file: File,
+        buffer: Buffer,
+        offset: u64,
+        length: u32,
+    },
+    Write {
+        file: File,
+        buffer: Buffer,
+        offset: u64,
+        length: u32,
+        flags: FILE_WRITE_FLAGS,
+    },
+    Flush {
+        file: File,
+        mode: FILE_FLUSH_MODE,
+    },
+    Cancel {
+        file: File,
+        cancel_id: u64,
+    },
+    RegisterFiles {
+        handles: *const HANDLE,
+        count: u32,
+    },
+    RegisterBuffers {
+        buffers: *const IORING_BUFFER_INFO,
+        count: u32,
+    },
+    /// An opcode this crate has no builder for.
+    Unknown(i32),
+}
+
+/// The file of an operation, as its entry refers to it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum File {
+    Handle(HANDLE),
+    /// The index of a file registered with the ring.
+    Registered(u32),
+}
+
+/// The buffer of an operation, as its entry refers to it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Buffer {
+    Address(*mut c_void),
+    /// An offset into a buffer registered with the ring.
+    Registered {
+        index: u32,
+        offset: u32,
+    },
+}
+
+impl Operation {
+    /// Decode a raw entry, such as one taken from the submission queue or a
+    /// [recording](crate::record).
+    pub fn decode(sqe: &_NT_IORING_SQE) -> Operation {
+        let file = |file: NT_IORING_HANDLEREF, flags: _NT_IORING_OP_FLAGS| unsafe {
+            if flags & _NT_IORING_OP_FLAGS_NT_IORING_OP_FLAG_REGISTERED_FILE != 0 {
+                File::Registered(file.HandleIndex as u32)
+            } else {
+                File::Handle(file.Handle as HANDLE)
+            }
+        };
+        let buffer = |buffer: NT_IORING_BUFFERREF, flags: _NT_IORING_OP_FLAGS| unsafe {
+            if flags & _NT_IORING_OP_FLAGS_NT_IORING_OP_FLAG_REGISTERED_BUFFER != 0 {
+                let fixed = buffer.FixedBuffer;
+                Buffer::Registered {
+                    index: fixed.BufferIndex,
+                    offset: fixed.Offset,
+                }
+            } else {
+                Buffer::Address(buffer.Address as *mut c_void)
+            }
+        };
         unsafe {
-            f.debug_struct("Entry")
-                .field("op_code", &(*self.0).OpCode)
-                .field("flags", &(*self.0).Flags)
-                .field("user_data", &(*self.0).UserData)
-                .finish()
+            let op = &sqe.__bindgen_anon_1;
+            match sqe.OpCode {
+                _IORING_OP_CODE_IORING_OP_NOP => Operation::Nop {
+                    result: op.ReservedMaxSizePadding.Argument1 as i32,
+                    information: op.ReservedMaxSizePadding.Argument2,
+                },
+                _IORING_OP_CODE_IORING_OP_READ => Operation::Read {
+                    file: file(op.Read.File, op.Read.CommonOpFlags),
+                    buffer: buffer(op.Read.Buffer, op.Read.CommonOpFlags),
+                    offset: op.Read.Offset,
+                    length: op.Read.Length,
+                },
+                _IORING_OP_CODE_IORING_OP_WRITE => Operation::Write {
+                    file: file(op.Write.File, op.Write.CommonOpFlags),
+                    buffer: buffer(op.Write.Buffer, op.Write.CommonOpFlags),
+                    offset: op.Write.Offset,
+                    length: op.Write.Length,
+                    flags: op.Write.Flags,
+                },
+                _IORING_OP_CODE_IORING_OP_FLUSH => Operation::Flush {
+                    file: file(op.Flush.File, op.Flush.CommonOpFlags),
+                    mode: op.Flush.FlushMode,
+                },
+                _IORING_OP_CODE_IORING_OP_CANCEL => Operation::Cancel {
+                    file: file(op.Cancel.File, op.Cancel.CommonOpFlags),
+                    cancel_id: op.Cancel.CancelId,
+                },
+                _IORING_OP_CODE_IORING_OP_REGISTER_FILES => Operation::RegisterFiles {
+                    handles: op.RegisterFiles.__bindgen_anon_1.Handles,
+                    count: op.RegisterFiles.Count,
+                },
+                _IORING_OP_CODE_IORING_OP_REGISTER_BUFFERS => Operation::RegisterBuffers {
+                    buffers: op.RegisterBuffers.__bindgen_anon_1.Buffers,
+                    count: op.RegisterBuffers.Count,
+                },
+                op_code => Operation::Unknown(op_code),
+            }
+        }
+    }
+
+    /// The opcode of the operation, which is the `CODE` of the builder it came from.
+    pub fn code(&self) -> i32 {
+        match *self {
+            Operation::Nop { .. } => _IORING_OP_CODE_IORING_OP_NOP,
+            Operation::Read { .. } => _IORING_OP_CODE_IORING_OP_READ,
+            Operation::Write { .. } => _IORING_OP_CODE_IORING_OP_WRITE,
+            Operation::Flush { .. } => _IORING_OP_CODE_IORING_OP_FLUSH,
+            Operation::Cancel { .. } => _IORING_OP_CODE_IORING_OP_CANCEL,
+            Operation::RegisterFiles { .. } => _IORING_OP_CODE_IORING_OP_REGISTER_FILES,
+            Operation::RegisterBuffers { .. } => _IORING_OP_CODE_IORING_OP_REGISTER_BUFFERS,
+            Operation::Unknown(op_code) => op_code,
         }
     }
 }
 
-impl Debug for SubmissionQueue<'_> {
+impl Display for File {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        let mut d = f.debug_list();
-        let mut pos = self.head;
-        while pos != self.tail {
-            let entry: &Entry = unsafe {
-                &*(self.queue.sqes.add((pos & self.queue.ring_mask) as usize) as *const Entry)
+        match *self {
+            File::Handle(handle) => write!(f, "{:#x}", handle as usize),
+            File::Registered(index) => write!(f, "#{}", index),
+        }
+    }
+}
+
+impl Display for Buffer {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match *self {
+            Buffer::Address(address) => write!(f, "{:#x}", address as usize),
+            Buffer::Registered { index, offset } => write!(f, "#{}+{}", index, offset),
+        }
+    }
+}
+
+/// Call `field` with the name and value of each operand of `sqe` that means something for its
+/// opcode.
+fn operands(sqe: &_NT_IORING_SQE, mut field: impl FnMut(&'static str, &dyn Display)) {
+    match Operation::decode(sqe) {
+        Operation::Nop {
+            result,
+            information,
+        } => {
+            if result != 0 {
+                field("result", &result);
+            }
+            if information != 0 {
+                field("information", &information);
+            }
+        }
+        Operation::Read {
+            file,
+            buffer,
+            offset,
+            length,
+        }
+        | Operation::Write {
+            file,
+            buffer,
+            offset,
+            length,
+            ..
+        } => {
+            field("file", &file);
+            field("buffer", &buffer);
+            field("offset", &offset);
+            field("length", &length);
+        }
+        Operation::Flush { file, mode } => {
+            field("file", &file);
+            let mode = match mode {
+                FILE_FLUSH_MODE_FILE_FLUSH_DEFAULT => "default",
+                FILE_FLUSH_MODE_FILE_FLUSH_DATA => "data",
+                FILE_FLUSH_MODE_FILE_FLUSH_MIN_METADATA => "min_metadata",
+                FILE_FLUSH_MODE_FILE_FLUSH_NO_SYNC => "no_sync",
+                _ => "unknown",
             };
-            d.entry(&entry);
-            pos = pos.wrapping_add(1);
+            field("mode", &mode);
+        }
+        Operation::Cancel { file, cancel_id } => {
+            field("file", &file);
+            field("cancel_id", &cancel_id);
+        }
+        Operation::RegisterFiles { count, .. } | Operation::RegisterBuffers { count, .. } => {
+            field("count", &count);
+        }
+        Operation::Unknown(_) => {}
+    }
+}
+
+/// Show an entry as its opcode, `user_data`, flags and operands.
+pub(crate) struct Sqe<'a>(pub(crate) &'a _NT_IORING_SQE, pub(crate) Option<Duration>);
+
+impl Debug for Sqe<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let Sqe(sqe, timeout) = *self;
+        let mut d = f.debug_struct("Entry");
+        d.field("opcode", &format_args!("{}", stats::name(sqe.OpCode)))
+            .field("user_data", &sqe.UserData);
+        if sqe.Flags & _NT_IORING_SQE_FLAGS_NT_IORING_SQE_FLAG_DRAIN_PRECEDING_OPS != 0 {
+            d.field("drain_preceding_ops", &true);
+        }
+        operands(sqe, |name, value| {
+            d.field(name, &format_args!("{}", value));
+        });
+        if let Some(timeout) = timeout {
+            d.field("timeout", &timeout);
         }
         d.finish()
     }
 }
+
+impl Display for Sqe<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let Sqe(sqe, timeout) = *self;
+        write!(f, "{} user_data={}", stats::name(sqe.OpCode), sqe.UserData)?;
+        if sqe.Flags & _NT_IORING_SQE_FLAGS_NT_IORING_SQE_FLAG_DRAIN_PRECEDING_OPS != 0 {
+            f.write_str(" drain_preceding_ops")?;
+        }
+        let mut res = Ok(());
+        operands(sqe, |name, value| {
+            res = res.and_then(|()| write!(f, " {}={}", name, value));
+        });
+        res?;
+        if let Some(timeout) = timeout {
+            write!(f, " timeout={:?}", timeout)?;
+        }
+        Ok(())
+    }
+}
+
+/// Shows the opcode, `user_data`, flags and the operands that matter for the opcode, such as the
+/// file, buffer, offset and length of a [`Read`](crate::opcode::Read).
+impl Debug for Entry {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        Debug::fmt(&Sqe(unsafe { &*self.0 }, self.1), f)
+    }
+}
+
+/// Shows the entry on one line, such as `read user_data=1 file=0x1c buffer=0x2a offset=0
+/// length=4`. Registered files and buffers are shown by index, as `#0`, with the offset into a
+/// registered buffer as `#0+16`.
+impl Display for Entry {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        Display::fmt(&Sqe(unsafe { &*self.0 }, self.1), f)
+    }
+}
+
+/// Shows the entries in the submission queue that the kernel has not taken yet, followed by
+/// those waiting in the backlog.
+impl Debug for SubmissionQueue<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let mut entries = f.debug_list();
+        unsafe {
+            let indices = Indices::submission(self.queue.info);
+            let sqes = (*self.queue.sqes).Entries.as_ptr();
+            let mut pos = indices.head();
+            while pos != indices.tail() {
+                let sqe = &*sqes.add(indices.slot(pos));
+                entries.entry(&Sqe(sqe, None));
+                pos = pos.wrapping_add(1);
+            }
+            let backlog = (*self.queue.shared).backlog.lock().unwrap();
+            entries.entries(backlog.entries.iter().map(|sqe| Sqe(sqe, None)));
+        }
+        entries.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Chain, PushError};
+    use crate::emulate::CompletionOrder;
+    use crate::testfile::TestFile;
+    use crate::windows::{
+        FILE_FLUSH_MODE_FILE_FLUSH_DEFAULT, NT_IORING_BUFFERREF, NT_IORING_HANDLEREF,
+        _NT_IORING_OP_FLAGS_NT_IORING_OP_FLAG_NONE,
+    };
+    use crate::{cqueue, fs, opcode, IoRing};
+    use std::time::{Duration, Instant};
+    use std::{io, ptr};
+
+    fn completed(ring: &mut IoRing) -> Vec<u64> {
+        ring.completion().map(|cqe| cqe.user_data()).collect()
+    }
+
+    #[test]
+    fn fence_completes_after_preceding_operations() {
+        let mut ring = IoRing::emulated(8).unwrap();
+        ring.emulator()
+            .unwrap()
+            .set_completion_order(CompletionOrder::Reversed);
+        let ptr = ring.info.0;
+        for user_data in 1..=3 {
+            let nop = opcode::Nop::new(ptr).build().user_data(user_data);
+            unsafe { ring.submission().push(&nop).unwrap() };
+        }
+        let fence = opcode::Fence::new(ptr).build().user_data(4);
+        unsafe { ring.submission().push(&fence).unwrap() };
+        let nop = opcode::Nop::new(ptr).build().user_data(5);
+        unsafe { ring.submission().push(&nop).unwrap() };
+
+        ring.submit().unwrap();
+        // The later nop may overtake the fence, but nothing before the fence can.
+        assert_eq!(completed(&mut ring), [5, 3, 2, 1, 4]);
+    }
+
+    #[test]
+    fn barrier_waits_for_stalled_operation() {
+        let path = TestFile::new("barrier", b"barrier");
+        let file = std::fs::File::open(path.path()).unwrap();
+        let handle = fs::raw_handle(&file);
+
+        let mut ring = IoRing::emulated(8).unwrap();
+        ring.emulator().unwrap().stall(handle);
+        let ptr = ring.info.0;
+        let mut buf = [0u8; 7];
+        let read = opcode::Read::new(
+            ptr,
+            NT_IORING_HANDLEREF {
+                Handle: handle as _,
+            },
+            NT_IORING_BUFFERREF {
+                Address: buf.as_mut_ptr() as _,
+            },
+            buf.len() as u32,
+            0,
+            _NT_IORING_OP_FLAGS_NT_IORING_OP_FLAG_NONE,
+        )
+        .build()
+        .user_data(1);
+        unsafe { ring.submission().push(&read).unwrap() };
+        let nop = opcode::Nop::new(ptr).build().user_data(2);
+        unsafe { ring.submission().push_barrier(&nop).unwrap() };
+
+        ring.submit().unwrap();
+        assert!(completed(&mut ring).is_empty());
+        assert_eq!(ring.emulator().unwrap().in_flight(), 2);
+
+        ring.emulator().unwrap().resume(handle);
+        ring.submit().unwrap();
+        let cqes: Vec<_> = ring.completion().collect();
+        assert_eq!(cqes.len(), 2);
+        assert_eq!(cqes[0].user_data(), 1);
+        assert_eq!(cqes[0].into_result().unwrap(), 7);
+        assert_eq!(cqes[1].user_data(), 2);
+        assert_eq!(&buf, b"barrier");
+    }
+
+    #[test]
+    fn stalled_operation_times_out() {
+        let path = TestFile::new("timeout", b"timeout");
+        let file = std::fs::File::open(path.path()).unwrap();
+        let handle = fs::raw_handle(&file);
+
+        let mut ring = IoRing::emulated(8).unwrap();
+        ring.emulator().unwrap().stall(handle);
+        let ptr = ring.info.0;
+        let mut buf = [0u8; 7];
+        let read = opcode::Read::new(
+            ptr,
+            NT_IORING_HANDLEREF {
+                Handle: handle as _,
+            },
+            NT_IORING_BUFFERREF {
+                Address: buf.as_mut_ptr() as _,
+            },
+            buf.len() as u32,
+            0,
+            _NT_IORING_OP_FLAGS_NT_IORING_OP_FLAG_NONE,
+        )
+        .build()
+        .user_data(1)
+        .timeout(Duration::from_millis(20));
+        unsafe { ring.submission().push(&read).unwrap() };
+        let nop = opcode::Nop::new(ptr)
+            .build()
+            .user_data(2)
+            .timeout(Duration::from_millis(20));
+        unsafe { ring.submission().push(&nop).unwrap() };
+
+        let start = Instant::now();
+        ring.submit_and_wait(2).unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(20));
+        let cqes: Vec<_> = ring.completion().collect();
+        assert_eq!(cqes.len(), 2);
+        assert_eq!(cqes[0].user_data(), 2);
+        assert_eq!(cqes[0].into_result().unwrap(), 0);
+        assert_eq!(cqes[1].user_data(), 1);
+        let err = cqes[1].into_result().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        assert_eq!(ring.emulator().unwrap().in_flight(), 0);
+    }
+
+    #[test]
+    fn cancelled_operation_still_completes() {
+        let path = TestFile::new("cancel", b"cancel");
+        let file = std::fs::File::open(path.path()).unwrap();
+        let handle = fs::raw_handle(&file);
+
+        let mut ring = IoRing::emulated(8).unwrap();
+        ring.emulator().unwrap().stall(handle);
+        let ptr = ring.info.0;
+        let mut bufs = [[0u8; 6]; 3];
+        let mut ops = Vec::new();
+        for (user_data, buf) in (1..).zip(bufs.iter_mut()) {
+            let read = opcode::Read::new(
+                ptr,
+                NT_IORING_HANDLEREF {
+                    Handle: handle as _,
+                },
+                NT_IORING_BUFFERREF {
+                    Address: buf.as_mut_ptr() as _,
+                },
+                buf.len() as u32,
+                0,
+                _NT_IORING_OP_FLAGS_NT_IORING_OP_FLAG_NONE,
+            )
+            .build()
+            .user_data(user_data);
+            ops.push(unsafe { ring.submission().push(&read).unwrap() });
+        }
+        ring.submit().unwrap();
+        assert!(completed(&mut ring).is_empty());
+
+        ops[1].cancel(&ring.submitter()).unwrap();
+        ring.submit().unwrap();
+        let cqes: Vec<_> = ring.completion().collect();
+        assert_eq!(cqes.len(), 1);
+        assert_eq!(cqes[0].user_data(), 2);
+        assert_eq!(cqes[0].result(), cqueue::OPERATION_ABORTED);
+
+        ring.cancel_all_for(handle).unwrap();
+        ring.submit().unwrap();
+        assert_eq!(completed(&mut ring), [1, 3]);
+        assert_eq!(ring.emulator().unwrap().in_flight(), 0);
+
+        // Both operations are gone, so this cancel finds nothing, and is not reported either.
+        ops[0].cancel(&ring.submitter()).unwrap();
+        ring.submit().unwrap();
+        assert!(completed(&mut ring).is_empty());
+    }
+
+    #[test]
+    fn cancel_refuses_zero_user_data() {
+        let mut ring = IoRing::emulated(8).unwrap();
+        let flush = opcode::Flush::new(
+            ring.info.0,
+            NT_IORING_HANDLEREF { Handle: 0 as _ },
+            FILE_FLUSH_MODE_FILE_FLUSH_DEFAULT,
+            _NT_IORING_OP_FLAGS_NT_IORING_OP_FLAG_NONE,
+        )
+        .build();
+        let op = unsafe { ring.submission().push(&flush).unwrap() };
+        let err = op.cancel(&ring.submitter()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn reserved_user_data_is_refused() {
+        let mut ring = IoRing::emulated(8).unwrap();
+        let ptr = ring.info.0;
+        let reserved = u64::MAX - 3;
+
+        let nop = opcode::Nop::new(ptr).build().user_data(reserved);
+        assert_eq!(
+            unsafe { ring.submission().push(&nop) }.unwrap_err(),
+            PushError::ReservedUserData(reserved)
+        );
+        let link = opcode::Nop::new(ptr).build().user_data(1);
+        let chain = Chain::new(link).then(u64::MAX, |_| opcode::Nop::new(ptr::null_mut()).build());
+        assert_eq!(
+            unsafe { ring.submission().push_chain(chain) }.unwrap_err(),
+            PushError::ReservedUserData(u64::MAX)
+        );
+        let nop = opcode::Nop::new(ptr::null_mut())
+            .build()
+            .user_data(reserved);
+        let err = unsafe { ring.shared_submitter().push(&nop) }.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        let err = ring.notifier().notify(reserved, 0).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+        // The slots the refused entries were built in complete without being reported.
+        let nop = opcode::Nop::new(ptr).build().user_data(2);
+        unsafe { ring.submission().push(&nop).unwrap() };
+        ring.submit().unwrap();
+        assert_eq!(completed(&mut ring), [2]);
+    }
+
+    #[test]
+    fn backlog_holds_entries_until_submit() {
+        let mut ring = IoRing::emulated(4).unwrap();
+        let ptr = ring.info.0;
+        for user_data in 1..=4 {
+            let nop = opcode::Nop::new(ptr).build().user_data(user_data);
+            unsafe { ring.submission().push(&nop).unwrap() };
+        }
+        let nop = opcode::Nop::new(ptr).build().user_data(5);
+        assert_eq!(
+            unsafe { ring.submission().push(&nop) }.unwrap_err(),
+            PushError::Full
+        );
+
+        ring.set_backlog(true);
+        for user_data in 5..=7 {
+            let nop = opcode::Nop::new(ptr).build().user_data(user_data);
+            unsafe { ring.submission().push(&nop).unwrap() };
+        }
+        let stats = ring.backlog_stats();
+        assert_eq!((stats.depth, stats.peak, stats.total), (3, 3, 3));
+        assert!(ring.submission().is_full());
+
+        ring.submit().unwrap();
+        assert_eq!(completed(&mut ring), [1, 2, 3, 4, 5, 6, 7]);
+        let stats = ring.backlog_stats();
+        assert_eq!((stats.depth, stats.peak, stats.total), (0, 3, 3));
+    }
+
+    #[test]
+    fn entries_are_decoded() {
+        use crate::windows::{
+            _IORING_REGISTERED_BUFFER, _NT_IORING_OP_FLAGS_NT_IORING_OP_FLAG_REGISTERED_BUFFER,
+            _NT_IORING_OP_FLAGS_NT_IORING_OP_FLAG_REGISTERED_FILE,
+        };
+
+        let registered = _NT_IORING_OP_FLAGS_NT_IORING_OP_FLAG_REGISTERED_FILE
+            | _NT_IORING_OP_FLAGS_NT_IORING_OP_FLAG_REGISTERED_BUFFER;
+        let fixed = _IORING_REGISTERED_BUFFER {
+            BufferIndex: 2,
+            Offset: 16,
+        };
+        let write = opcode::Write::new(
+            std::ptr::null_mut(),
+            NT_IORING_HANDLEREF { HandleIndex: 1 },
+            NT_IORING_BUFFERREF { FixedBuffer: fixed },
+            8,
+            4096,
+            registered,
+        )
+        .build()
+        .user_data(7);
+        assert_eq!(
+            write.to_string(),
+            "write user_data=7 file=#1 buffer=#2+16 offset=4096 length=8"
+        );
+
+        let flush = opcode::Flush::new(
+            std::ptr::null_mut(),
+            NT_IORING_HANDLEREF { Handle: 0x1c },
+            FILE_FLUSH_MODE_FILE_FLUSH_DEFAULT,
+            _NT_IORING_OP_FLAGS_NT_IORING_OP_FLAG_NONE,
+        )
+        .build()
+        .user_data(8)
+        .timeout(Duration::from_millis(5));
+        assert_eq!(
+            format!("{:?}", flush),
+            "Entry { opcode: flush, user_data: 8, file: 0x1c, mode: default, timeout: 5ms }"
+        );
+
+        let cancel = opcode::Cancel::new(
+            std::ptr::null_mut(),
+            NT_IORING_HANDLEREF { Handle: 0x1c },
+            _NT_IORING_OP_FLAGS_NT_IORING_OP_FLAG_NONE,
+        )
+        .cancel_id(7)
+        .build()
+        .user_data(9);
+        assert_eq!(
+            cancel.to_string(),
+            "cancel user_data=9 file=0x1c cancel_id=7"
+        );
+    }
+
+    #[test]
+    fn builders_encode_their_opcode() {
+        use super::{Buffer, File, Operation};
+        use crate::windows::{
+            HANDLE, IORING_BUFFER_INFO, _NT_IORING_REG_BUFFERS_FLAGS, _NT_IORING_REG_FILES_FLAGS,
+        };
+        use std::ffi::c_void;
+        use std::mem;
+
+        let handles = [0x1c as HANDLE];
+        let files = opcode::RegisterFiles::new(
+            std::ptr::null(),
+            handles.as_ptr(),
+            1,
+            unsafe { mem::zeroed::<_NT_IORING_REG_FILES_FLAGS>() },
+            _NT_IORING_OP_FLAGS_NT_IORING_OP_FLAG_NONE,
+        )
+        .build();
+        assert_eq!(
+            files.operation(),
+            Operation::RegisterFiles {
+                handles: handles.as_ptr(),
+                count: 1
+            }
+        );
+        assert_eq!(files.operation().code(), opcode::RegisterFiles::CODE);
+
+        let buffers: [IORING_BUFFER_INFO; 2] = unsafe { mem::zeroed() };
+        let register = opcode::RegisterBuffers::new(
+            std::ptr::null(),
+            buffers.as_ptr(),
+            2,
+            unsafe { mem::zeroed::<_NT_IORING_REG_BUFFERS_FLAGS>() },
+            _NT_IORING_OP_FLAGS_NT_IORING_OP_FLAG_NONE,
+        )
+        .build();
+        assert_eq!(
+            register.operation(),
+            Operation::RegisterBuffers {
+                buffers: buffers.as_ptr(),
+                count: 2
+            }
+        );
+        assert_eq!(register.operation().code(), opcode::RegisterBuffers::CODE);
+
+        let read = opcode::Read::new(
+            std::ptr::null_mut(),
+            NT_IORING_HANDLEREF { Handle: 0x1c },
+            NT_IORING_BUFFERREF { Address: 0x1000 },
+            8,
+            4096,
+            _NT_IORING_OP_FLAGS_NT_IORING_OP_FLAG_NONE,
+        )
+        .build();
+        assert_eq!(
+            read.operation(),
+            Operation::Read {
+                file: File::Handle(0x1c as HANDLE),
+                buffer: Buffer::Address(0x1000 as *mut c_void),
+                offset: 4096,
+                length: 8,
+            }
+        );
+        assert_eq!(read.operation().code(), opcode::Read::CODE);
+        let fence = opcode::Fence::new(std::ptr::null_mut()).build();
+        assert_eq!(fence.operation().code(), opcode::Fence::CODE);
+    }
+
+    #[test]
+    fn submission_queue_shows_pending_entries() {
+        let mut ring = IoRing::emulated(2).unwrap();
+        ring.set_backlog(true);
+        let ptr = ring.info.0;
+        for user_data in 1..=3 {
+            let nop = opcode::Nop::new(ptr).build().user_data(user_data);
+            unsafe { ring.submission().push(&nop).unwrap() };
+        }
+        let fence = opcode::Fence::new(ptr).build().user_data(4);
+        unsafe { ring.submission().push_barrier(&fence).unwrap() };
+        assert_eq!(
+            format!("{:?}", ring.submission()),
+            "[Entry { opcode: nop, user_data: 1 }, Entry { opcode: nop, user_data: 2 }, \
+             Entry { opcode: nop, user_data: 3 }, \
+             Entry { opcode: nop, user_data: 4, drain_preceding_ops: true }]"
+        );
+
+        ring.submit().unwrap();
+        assert_eq!(format!("{:?}", ring.submission()), "[]");
+    }
+}
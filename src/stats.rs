@@ -0,0 +1,286 @@
+//! Counters and latency histograms of a ring, see [`IoRing::stats`](crate::IoRing::stats).
+//!
+//! Entries are counted when the kernel takes them from the submission queue, and their
+//! completions when they are reaped from the completion queue. The completions that the crate
+//! keeps to itself, such as those of the cancels behind timeouts, are left out.
+//!
+//! With the `metrics` feature, the same events are also reported through the
+//! [`metrics`](https://docs.rs/metrics) facade as they happen:
+//!
+//! - `ioring_submitted`, `ioring_completed` and `ioring_failed`, counters labeled with the
+//!   `opcode`,
+//! - `ioring_latency_seconds`, a histogram labeled with the `opcode`,
+//! - `ioring_submit_calls`, a counter.
+
+use std::collections::{BTreeMap, HashMap};
+use std::time::{Duration, Instant};
+
+use crate::cqueue;
+use crate::windows::{
+    _IORING_OP_CODE_IORING_OP_CANCEL, _IORING_OP_CODE_IORING_OP_FLUSH,
+    _IORING_OP_CODE_IORING_OP_NOP, _IORING_OP_CODE_IORING_OP_READ,
+    _IORING_OP_CODE_IORING_OP_REGISTER_BUFFERS, _IORING_OP_CODE_IORING_OP_REGISTER_FILES,
+    _IORING_OP_CODE_IORING_OP_WRITE, _NT_IORING_SQE,
+};
+
+const BUCKETS: usize = 32;
+
+/// A snapshot of the statistics of a ring.
+#[derive(Clone, Debug, Default)]
+#[non_exhaustive]
+pub struct Stats {
+    /// The statistics of each opcode that has been submitted, keyed by its `CODE`, such as
+    /// [`opcode::Read::CODE`](crate::opcode::Read::CODE).
+    pub ops: BTreeMap<i32, OpStats>,
+    /// The number of entries in the submission queue.
+    pub sq_len: usize,
+    /// The number of entries the submission queue can hold.
+    pub sq_capacity: usize,
+    /// The number of completions in the completion queue, not reaped yet.
+    pub cq_len: usize,
+    /// The number of completions the completion queue can hold.
+    pub cq_capacity: usize,
+    /// The number of times the ring was entered to submit or wait.
+    pub submit_calls: u64,
+    /// The number of entries the kernel has taken, over all opcodes.
+    pub submitted: u64,
+    batches: u64,
+}
+
+impl Stats {
+    /// The average number of entries taken per call that submitted any.
+    pub fn average_batch(&self) -> f64 {
+        match self.batches {
+            0 => 0.0,
+            batches => self.submitted as f64 / batches as f64,
+        }
+    }
+}
+
+/// The statistics of one opcode.
+#[derive(Clone, Debug, Default)]
+#[non_exhaustive]
+pub struct OpStats {
+    /// The number of entries taken by the kernel.
+    pub submitted: u64,
+    /// The number of completions reaped, successful or not.
+    pub completed: u64,
+    /// The number of completions with an error result.
+    pub failed: u64,
+    /// The time from submission to completion.
+    ///
+    /// Only the first operation of several in flight with the same `user_data` is timed, and
+    /// the time includes however long the completion waited to be reaped.
+    pub latency: Histogram,
+}
+
+/// A histogram of durations with a bucket per power of two microseconds.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Histogram {
+    buckets: [u64; BUCKETS],
+    count: u64,
+    sum: Duration,
+}
+
+impl Histogram {
+    /// Add `duration` to its bucket and to the sum the mean is taken from.
+    pub fn record(&mut self, duration: Duration) {
+        let micros = duration.as_micros().max(1);
+        let bucket = (micros.ilog2() as usize).min(BUCKETS - 1);
+        self.buckets[bucket] += 1;
+        self.count += 1;
+        self.sum += duration;
+    }
+
+    /// The number of durations recorded.
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// The average of the durations recorded, or `None` if there are none.
+    pub fn mean(&self) -> Option<Duration> {
+        (self.count > 0)
+            .then(|| Duration::from_nanos((self.sum.as_nanos() / self.count as u128) as u64))
+    }
+
+    /// An upper bound of the `q` quantile, with `q` between `0.0` and `1.0`: the end of the
+    /// bucket it falls in.
+    pub fn quantile(&self, q: f64) -> Option<Duration> {
+        if self.count == 0 {
+            return None;
+        }
+        let rank = ((q.clamp(0.0, 1.0) * self.count as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (bucket, &count) in self.buckets.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                return Some(Duration::from_micros(2 << bucket));
+            }
+        }
+        unreachable!()
+    }
+
+    /// The number of durations in each bucket: bucket `i` counts those from `2^i` up to
+    /// `2^(i+1)` microseconds, the first one everything below 2 microseconds as well, and the
+    /// last one everything above.
+    pub fn buckets(&self) -> &[u64] {
+        &self.buckets
+    }
+}
+
+/// An operation in flight, by `user_data`.
+struct Pending {
+    op_code: i32,
+    submitted: Instant,
+    /// How many more operations with the same `user_data` are in flight.
+    others: u32,
+}
+
+/// The statistics being collected for a ring.
+#[derive(Default)]
+pub(crate) struct Recorder {
+    ops: BTreeMap<i32, OpStats>,
+    pending: HashMap<u64, Pending>,
+    submit_calls: u64,
+    submitted: u64,
+    batches: u64,
+}
+
+impl Recorder {
+    pub(crate) fn entered(&mut self) {
+        self.submit_calls += 1;
+        #[cfg(feature = "metrics")]
+        metrics::counter!("ioring_submit_calls").increment(1);
+    }
+
+    /// Count the entries taken by the kernel in one call.
+    pub(crate) fn submitted<'a>(&mut self, sqes: impl IntoIterator<Item = &'a _NT_IORING_SQE>) {
+        let now = Instant::now();
+        let before = self.submitted;
+        for sqe in sqes {
+            if cqueue::is_hidden(sqe.UserData) {
+                continue;
+            }
+            self.submitted += 1;
+            self.ops.entry(sqe.OpCode).or_default().submitted += 1;
+            #[cfg(feature = "metrics")]
+            metrics::counter!("ioring_submitted", "opcode" => name(sqe.OpCode)).increment(1);
+            self.pending
+                .entry(sqe.UserData)
+                .and_modify(|pending| pending.others += 1)
+                .or_insert(Pending {
+                    op_code: sqe.OpCode,
+                    submitted: now,
+                    others: 0,
+                });
+        }
+        if self.submitted != before {
+            self.batches += 1;
+        }
+    }
+
+    /// Count a completion reaped from the completion queue.
+    pub(crate) fn completed(&mut self, cqe: &cqueue::Entry) {
+        let pending = match self.pending.get_mut(&cqe.user_data()) {
+            Some(pending) => pending,
+            // A completion produced in user space.
+            None => return,
+        };
+        let (op_code, submitted) = (pending.op_code, pending.submitted);
+        if pending.others == 0 {
+            self.pending.remove(&cqe.user_data());
+        } else {
+            pending.others -= 1;
+        }
+        let latency = submitted.elapsed();
+        let op = self.ops.entry(op_code).or_default();
+        op.completed += 1;
+        op.failed += (cqe.result() < 0) as u64;
+        op.latency.record(latency);
+        #[cfg(feature = "metrics")]
+        {
+            let opcode = name(op_code);
+            metrics::counter!("ioring_completed", "opcode" => opcode).increment(1);
+            if cqe.result() < 0 {
+                metrics::counter!("ioring_failed", "opcode" => opcode).increment(1);
+            }
+            metrics::histogram!("ioring_latency_seconds", "opcode" => opcode).record(latency);
+        }
+    }
+
+    pub(crate) fn snapshot(&self) -> Stats {
+        Stats {
+            ops: self.ops.clone(),
+            submit_calls: self.submit_calls,
+            submitted: self.submitted,
+            batches: self.batches,
+            ..Stats::default()
+        }
+    }
+}
+
+/// The name of an opcode, as used for the `opcode` label of metrics.
+pub fn name(op_code: i32) -> &'static str {
+    match op_code {
+        _IORING_OP_CODE_IORING_OP_NOP => "nop",
+        _IORING_OP_CODE_IORING_OP_READ => "read",
+        _IORING_OP_CODE_IORING_OP_WRITE => "write",
+        _IORING_OP_CODE_IORING_OP_FLUSH => "flush",
+        _IORING_OP_CODE_IORING_OP_CANCEL => "cancel",
+        _IORING_OP_CODE_IORING_OP_REGISTER_FILES => "register_files",
+        _IORING_OP_CODE_IORING_OP_REGISTER_BUFFERS => "register_buffers",
+        _ => "unknown",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cqueue::OPERATION_ABORTED;
+    use crate::{opcode, IoRing};
+
+    #[test]
+    fn histogram_buckets_by_powers_of_two() {
+        let mut histogram = Histogram::default();
+        assert_eq!(histogram.quantile(0.5), None);
+        for micros in [1, 3, 3, 100] {
+            histogram.record(Duration::from_micros(micros));
+        }
+        assert_eq!(histogram.count(), 4);
+        assert_eq!(&histogram.buckets()[..7], &[1, 2, 0, 0, 0, 0, 1]);
+        assert_eq!(histogram.mean(), Some(Duration::from_nanos(26_750)));
+        assert_eq!(histogram.quantile(0.5), Some(Duration::from_micros(4)));
+        assert_eq!(histogram.quantile(1.0), Some(Duration::from_micros(128)));
+    }
+
+    #[test]
+    fn operations_are_counted_by_opcode() {
+        let mut ring = IoRing::emulated(8).unwrap();
+        for (user_data, result) in [(1, 0), (2, OPERATION_ABORTED), (3, 0)] {
+            let nop = opcode::Nop::new(ring.info.0)
+                .result(result)
+                .build()
+                .user_data(user_data);
+            unsafe { ring.submission().push(&nop).unwrap() };
+        }
+        let stats = ring.stats();
+        assert_eq!((stats.sq_len, stats.sq_capacity), (3, 8));
+        assert_eq!(stats.submitted, 0);
+
+        ring.submit().unwrap();
+        let stats = ring.stats();
+        assert_eq!((stats.sq_len, stats.cq_len), (0, 3));
+        assert_eq!(stats.submitted, 3);
+        assert_eq!(stats.average_batch(), 3.0);
+        assert!(stats.submit_calls >= 1);
+        assert_eq!(stats.ops[&opcode::Nop::CODE].completed, 0);
+
+        assert_eq!(ring.completion().count(), 3);
+        let stats = ring.stats();
+        let nop = &stats.ops[&opcode::Nop::CODE];
+        assert_eq!((nop.submitted, nop.completed, nop.failed), (3, 3, 1));
+        assert_eq!(nop.latency.count(), 3);
+        assert_eq!(stats.cq_len, 0);
+        assert_eq!(name(opcode::Nop::CODE), "nop");
+    }
+}
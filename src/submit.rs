@@ -1,60 +1,304 @@
 #![allow(clippy::uninit_assumed_init)]
-use std::{io, iter, os::windows::prelude::RawHandle, sync::atomic};
+use std::sync::{mpsc, Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+use std::{io, sync::atomic, thread};
 
-use crate::cqueue::CompletionQueue;
+use crate::cqueue::{self, CompletionQueue};
 use crate::opcode;
-use crate::windows::{win_ring_get_sqe, win_ring_sqe};
+use crate::ring::Indices;
+use crate::squeue;
+use crate::timer::CANCEL_USER_DATA;
+use crate::trace;
+use crate::windows::{win_ring_get_sqe, win_ring_sq_flush, win_ring_sqe};
 use crate::{
     windows::{
-        win_ring_sq_space_left, NtSubmitIoRing, IORING_BUFFER_INFO,
-        _NT_IORING_CREATE_REQUIRED_FLAGS_NT_IORING_CREATE_REQUIRED_FLAG_NONE,
-        _NT_IORING_OP_FLAGS_NT_IORING_OP_FLAG_NONE, _NT_IORING_REG_BUFFERS_FLAGS,
-        _NT_IORING_REG_FILES_FLAGS,
+        win_ring_cq_ready, win_ring_sq_space_left, NtSubmitIoRing, HANDLE, IORING_BUFFER_INFO,
+        NT_IORING_HANDLEREF, _NT_IORING_CREATE_REQUIRED_FLAGS_NT_IORING_CREATE_REQUIRED_FLAG_NONE,
+        _NT_IORING_OP_FLAGS, _NT_IORING_OP_FLAGS_NT_IORING_OP_FLAG_NONE,
+        _NT_IORING_OP_FLAGS_NT_IORING_OP_FLAG_REGISTERED_FILE, _NT_IORING_REG_BUFFERS_FLAGS,
+        _NT_IORING_REG_FILES_FLAGS, _NT_IORING_SQE,
     },
-    Info,
+    Info, Shared,
 };
 
 const BS: usize = 32 * 1024;
 
+/// How often a wait checks for completions posted behind the kernel's back: those of a
+/// [`Notifier`](crate::cqueue::Notifier), and on an emulated ring those of a
+/// [`wake`](Submitter::wake).
+const POLL_MS: u64 = 1;
+
+/// `user_data` of the completions of [`Submitter::wake`], which are not reported.
+pub(crate) const WAKE_USER_DATA: u64 = u64::MAX - 3;
+
+/// The lock on [`Shared::waiting`], held by a thread while it submits.
+type Waiting<'a> = Option<MutexGuard<'a, Option<u32>>>;
+
+/// A handle that worker threads use to queue operations on a ring without access to the ring
+/// itself. It can be cloned and shared freely.
+///
+/// Entries go through a lock-free staging queue, which the thread owning the ring moves into the
+/// submission queue each time it submits. The entries have to be built outside of the submission
+/// queue, by giving their opcode a null ring:
+///
+/// ```no_run
+/// use ioring_rs::{opcode, IoRing};
+///
+/// # fn main() -> std::io::Result<()> {
+/// let ring = IoRing::new(32)?;
+/// let submitter = ring.shared_submitter();
+/// std::thread::spawn(move || {
+///     let nop = opcode::Nop::new(std::ptr::null_mut()).build().user_data(1);
+///     unsafe { submitter.push(&nop) }.expect("the ring has been dropped");
+/// })
+/// .join()
+/// .unwrap();
+///
+/// ring.submit_and_wait(1)?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct SharedSubmitter {
+    sender: mpsc::Sender<Staged>,
+}
+
+/// An entry waiting in the staging queue, and its timeout.
+struct Staged(_NT_IORING_SQE, Option<Duration>);
+
+// The entry's buffers are the business of whoever pushed it, as with `SubmissionQueue::push`.
+unsafe impl Send for Staged {}
+
+/// The staging queue behind every [`SharedSubmitter`] of a ring.
+pub(crate) struct Staging {
+    sender: mpsc::Sender<Staged>,
+    /// Only locked by the thread submitting to the ring.
+    receiver: Mutex<mpsc::Receiver<Staged>>,
+}
+
+impl Default for Staging {
+    fn default() -> Self {
+        let (sender, receiver) = mpsc::channel();
+        Staging {
+            sender,
+            receiver: Mutex::new(receiver),
+        }
+    }
+}
+
+impl Staging {
+    pub(crate) fn submitter(&self) -> SharedSubmitter {
+        SharedSubmitter {
+            sender: self.sender.clone(),
+        }
+    }
+}
+
+impl SharedSubmitter {
+    /// Queue `entry` to be moved into the submission queue by the next submit on the ring,
+    /// from any thread. It goes to the ring's [backlog](crate::IoRing::set_backlog) if the
+    /// submission queue is full then, even if the backlog is disabled.
+    ///
+    /// The returned [`OpHandle`](squeue::OpHandle) can be used to cancel the operation once it
+    /// has been moved. A [timeout](squeue::Entry::timeout) only starts at that point too.
+    ///
+    /// This fails if the entry was built in the submission queue rather than with a null ring,
+    /// with [`InvalidInput`](io::ErrorKind::InvalidInput) as well if its `user_data` is
+    /// [reserved](squeue::RESERVED_USER_DATA), wrapping a
+    /// [`PushError::ReservedUserData`](squeue::PushError::ReservedUserData), and if the ring has
+    /// been dropped.
+    ///
+    /// # Safety
+    ///
+    /// Developers must ensure that parameters of the entry (such as buffer) are valid and will
+    /// be valid for the entire duration of the operation, otherwise it may cause memory problems.
+    pub unsafe fn push(&self, entry: &squeue::Entry) -> io::Result<squeue::OpHandle> {
+        let sqe = match &entry.2 {
+            Some(detached) => **detached,
+            None => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "entries for a SharedSubmitter must be built with a null ring",
+                ))
+            }
+        };
+        if sqe.UserData >= squeue::RESERVED_USER_DATA {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                squeue::PushError::ReservedUserData(sqe.UserData),
+            ));
+        }
+        let handle = squeue::OpHandle::new(&sqe);
+        self.sender
+            .send(Staged(sqe, entry.1))
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "the ring has been dropped"))?;
+        Ok(handle)
+    }
+}
+
 pub struct Submitter<'a> {
-    pub(crate) fd: &'a RawHandle,
+    pub(crate) fd: &'a HANDLE,
     pub(crate) info: &'a Info,
-    pub(crate) sq_head: *const atomic::AtomicU32,
-    pub(crate) sq_tail: *const atomic::AtomicU32,
+    pub(crate) shared: &'a Shared,
+    pub(crate) sq: Indices<'a>,
 }
 
 impl<'a> Submitter<'a> {
-    pub fn new(
-        fd: &'a RawHandle,
+    pub(crate) fn new(
+        fd: &'a HANDLE,
         info: &'a Info,
-        sq_head: *const atomic::AtomicU32,
-        sq_tail: *const atomic::AtomicU32,
+        shared: &'a Shared,
+        sq: Indices<'a>,
     ) -> Submitter<'a> {
         Submitter {
             fd,
             info,
-            sq_head,
-            sq_tail,
+            shared,
+            sq,
         }
     }
     #[inline]
     fn sq_len(&self) -> usize {
-        unsafe {
-            let head = (*self.sq_head).load(atomic::Ordering::Acquire);
-            let tail = (*self.sq_tail).load(atomic::Ordering::Acquire);
-
-            tail.wrapping_sub(head) as usize
-        }
+        self.sq.len() as usize
     }
     /// Submit all queued submission queue events to the kernel.
     #[inline]
     pub fn submit(&self) -> io::Result<usize> {
-        self.submit_and_wait(1, std::u32::MAX as usize)
+        self.submit_and_wait(1, u32::MAX as usize)
     }
 
     /// Submit all queued submission queue events to the kernel and wait for at least `want`
     /// completion events to complete.
+    ///
+    /// While operations pushed with a [timeout](crate::squeue::Entry::timeout) are in flight,
+    /// the wait is cut short at their deadlines to cancel them, and resumed afterwards.
+    ///
+    /// An [emulated](crate::IoRing::emulated) ring only waits for those deadlines: every other
+    /// operation that can complete has completed when it is submitted.
+    ///
+    /// Completions posted by a [`Notifier`](crate::cqueue::Notifier) count towards
+    /// `number_of_entries`. While the ring has notifiers, the wait checks for them every
+    /// millisecond, on an emulated ring too as long as `want` is finite.
+    ///
+    /// Another thread can cut the wait short with [`wake`](Self::wake).
     pub fn submit_and_wait(&self, number_of_entries: u32, want: usize) -> io::Result<usize> {
+        let _span = trace::submit(number_of_entries, want);
+        let infinite = want as u64 >= u32::MAX as u64;
+        let mut left = want as u64;
+        let mut lock = Some(self.shared.waiting.lock().unwrap());
+        loop {
+            let deadline = self.expire_timers();
+            self.drain_staging();
+            self.flush_backlog(&mut lock)?;
+            // Completions posted by notifiers count as well.
+            let wait = number_of_entries.saturating_sub(self.notified());
+            let mut timeout = left;
+            if let Some(deadline) = deadline.filter(|_| wait > 0) {
+                let left = deadline.saturating_duration_since(Instant::now());
+                timeout = timeout.min((left.as_micros() as u64).div_ceil(1000));
+            }
+            // Without a timeout, an emulated ring does not wait for anything but deadlines.
+            let emulated_forever = infinite && self.shared.emulator.is_some();
+            let poll = wait > 0 && !emulated_forever && Arc::weak_count(&self.shared.synthetic) > 0;
+            if poll {
+                timeout = timeout.min(POLL_MS);
+            }
+            let started = Instant::now();
+            self.enter(&mut lock, wait, timeout)?;
+            let done = self.cq_ready() + self.notified() >= number_of_entries;
+            if poll && !done {
+                // The kernel does not wait when nothing it can complete is in flight.
+                let slice = Duration::from_millis(timeout);
+                thread::sleep(slice.saturating_sub(started.elapsed()));
+            }
+            if timeout == left || done {
+                if self.is_idle() {
+                    self.shared.targets.lock().unwrap().clear();
+                }
+                return Ok(self.sq_len());
+            }
+            if !infinite {
+                left -= timeout;
+            }
+        }
+    }
+
+    /// Hand over as many queued entries as there is room for in the completion queue, and wait
+    /// up to `timeout` for `number_of_entries` completions.
+    ///
+    /// The entries left over stay in the submission queue for the next submit, once completions
+    /// have been reaped. This keeps the kernel from ever having more completions to post than the
+    /// completion queue can hold.
+    ///
+    /// `lock` is released while waiting, so that [`wake`](Self::wake) can get in.
+    fn enter(
+        &self,
+        lock: &mut Waiting<'a>,
+        number_of_entries: u32,
+        timeout: u64,
+    ) -> io::Result<()> {
+        // Entries built in place are only published now, unless they were pushed.
+        unsafe { win_ring_sq_flush(self.info.0) };
+        let (head, tail) = (self.sq.head(), self.sq.tail());
+        let queued = tail.wrapping_sub(head);
+        let held = queued.saturating_sub(self.cq_room());
+        if held > 0 {
+            // Hide the entries that do not fit until their turn comes.
+            self.sq.publish(tail.wrapping_sub(held));
+            self.shared
+                .overflow
+                .fetch_add(held as u64, atomic::Ordering::Relaxed);
+        }
+        // Do not wait for more completions than there can be.
+        let waitable = self.in_flight() + queued - held;
+        let number_of_entries = number_of_entries.min(waitable);
+        let res = if number_of_entries > 0 && timeout > 0 {
+            let mut waiting = lock.take().unwrap();
+            *waiting = Some(held);
+            drop(waiting);
+            let _span = trace::wait(queued - held, number_of_entries, timeout);
+            let res = self.enter_unchecked(number_of_entries, timeout);
+            let mut waiting = self.shared.waiting.lock().unwrap();
+            *waiting = None;
+            *lock = Some(waiting);
+            res
+        } else {
+            self.enter_unchecked(number_of_entries, timeout)
+        };
+        // This also counts the entries of a `wake` that got in while waiting.
+        let consumed = self.sq.head().wrapping_sub(head);
+        let entries = unsafe {
+            (*(*self.info.0).info.__bindgen_anon_1.SubmissionQueue)
+                .Entries
+                .as_ptr()
+        };
+        let sqes =
+            (0..consumed).map(|i| unsafe { &*entries.add(self.sq.slot(head.wrapping_add(i))) });
+        self.shared.stats.lock().unwrap().submitted(sqes);
+        self.sq.publish(self.sq.tail().wrapping_add(held));
+        res
+    }
+
+    fn enter_unchecked(&self, number_of_entries: u32, mut timeout: u64) -> io::Result<()> {
+        self.shared.stats.lock().unwrap().entered();
+        if let Some(emulator) = &self.shared.emulator {
+            emulator.lock().unwrap().submit();
+            // Only completions posted in user space, by a `wake` or a notifier, come in behind
+            // the emulator's back.
+            if timeout < u32::MAX as u64 {
+                let deadline = Instant::now() + Duration::from_millis(timeout);
+                let notified = self.notified();
+                while self.cq_ready() + self.notified().saturating_sub(notified) < number_of_entries
+                {
+                    let left = deadline.saturating_duration_since(Instant::now());
+                    if left.is_zero() {
+                        break;
+                    }
+                    thread::sleep(left.min(Duration::from_millis(POLL_MS)));
+                }
+            }
+            return Ok(());
+        }
         let res = unsafe {
             NtSubmitIoRing(
                 *self.fd,
@@ -63,24 +307,199 @@ impl<'a> Submitter<'a> {
                 if number_of_entries == 0 {
                     &mut { std::mem::zeroed() }
                 } else {
-                    &mut (want as u64)
+                    &mut timeout
                 },
             )
         };
-        if res == 0 {
-            Ok(self.sq_len())
+        // `STATUS_TIMEOUT` is a success status.
+        if res >= 0 {
+            Ok(())
         } else {
-            Err(io::Error::from_raw_os_error(res as i32))
+            Err(io::Error::from_raw_os_error(res))
         }
     }
-    /// Get the sqe ring
-    pub fn get_sqe(&self) -> io::Result<*mut win_ring_sqe> {
-        if !self.sq_space_left() > 0 {
+
+    /// Whether nothing is in flight, nor waiting to be submitted.
+    pub(crate) fn is_idle(&self) -> bool {
+        self.in_flight() == 0
+            && self.sq_len() == 0
+            && self.shared.backlog.lock().unwrap().entries.is_empty()
+            && self.shared.chains.lock().unwrap().is_empty()
+    }
+
+    /// Drop every entry that has not been handed over to the kernel yet, with the rest of the
+    /// chains in flight. None of them completes.
+    pub(crate) fn abandon(&self) {
+        self.drain_staging();
+        self.shared.backlog.lock().unwrap().entries.clear();
+        self.shared.chains.lock().unwrap().clear();
+        let head = self.sq.head();
+        unsafe { (*self.info.0).sqe_tail = head };
+        self.sq.publish(head);
+    }
+
+    /// Move the entries of every [`SharedSubmitter`] into the submission queue, or the backlog
+    /// when it is full.
+    fn drain_staging(&self) {
+        let receiver = self.shared.staging.receiver.lock().unwrap();
+        for Staged(sqe, timeout) in receiver.try_iter() {
+            let entry = squeue::Entry::detached(sqe, timeout);
+            if unsafe { squeue::enqueue(self.info.0, self.shared, &entry) }.is_err() {
+                self.shared.backlog.lock().unwrap().push(sqe);
+            }
+            squeue::arm(self.shared, &entry);
+        }
+    }
+
+    /// Move the [backlog](crate::IoRing::set_backlog) into the submission queue, handing the
+    /// queue over to make room for as long as it takes.
+    fn flush_backlog(&self, lock: &mut Waiting<'a>) -> io::Result<()> {
+        loop {
+            if unsafe { self.shared.backlog.lock().unwrap().flush(self.info.0) } {
+                return Ok(());
+            }
+            let queued = self.sq_len();
+            self.enter(lock, 0, 0)?;
+            if self.sq_len() == queued {
+                // Nothing was taken, leave the rest for the next submit.
+                return Ok(());
+            }
+        }
+    }
+
+    /// Queue a cancel for every operation whose timeout has passed, and return the next deadline.
+    fn expire_timers(&self) -> Option<Instant> {
+        let mut timers = self.shared.timers.lock().unwrap();
+        if timers.is_empty() {
+            return None;
+        }
+        for timer in timers.expire(Instant::now()) {
+            if self
+                .queue_cancel(timer.file, timer.flags, timer.user_data)
+                .is_err()
+            {
+                // The submission queue is full, try again once it has been submitted.
+                timers.expired.remove(&timer.user_data);
+                timers.insert(Instant::now(), timer);
+            }
+        }
+        timers.next_deadline()
+    }
+
+    /// Wake up the thread waiting in [`submit_and_wait`](Self::submit_and_wait), if any, or
+    /// else cut the next wait short. Unlike the rest of the submitter, this can be called from
+    /// other threads than the ring's owner.
+    ///
+    /// The wake is an unreported completion. It is posted in user space, like those of a
+    /// [`Notifier`](crate::cqueue::Notifier), unless a thread is blocked in the kernel: then it
+    /// takes a NOP, which is only built while that thread waits, when the owner cannot be
+    /// building an entry itself. The NOP needs room in both queues: if the completion queue is
+    /// full, or the submission queue is, this fails with
+    /// [`WouldBlock`](io::ErrorKind::WouldBlock). A thread waiting on a full completion queue
+    /// has completions to reap anyway.
+    pub fn wake(&self) -> io::Result<()> {
+        let waiting = self.shared.waiting.lock().unwrap();
+        let held = match *waiting {
+            Some(held) if self.shared.emulator.is_none() => held,
+            _ => {
+                let wake = cqueue::Entry::synthetic(WAKE_USER_DATA, 0, 0);
+                self.shared.synthetic.lock().unwrap().push_back(wake);
+                return Ok(());
+            }
+        };
+        // While a thread waits with entries held back, the tail is not where it seems to be.
+        if held > 0 || self.cq_room() == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::WouldBlock,
+                "the completion queue is full",
+            ));
+        }
+        if self.sq_space_left() == 0 {
             return Err(io::Error::new(
-                io::ErrorKind::Other,
-                "No space left in sqe ring",
+                io::ErrorKind::WouldBlock,
+                "the submission queue is full",
             ));
         }
+        // The waiting thread published everything reserved before it entered the kernel, and
+        // only reserves again once it is back and has taken `waiting`.
+        opcode::Nop::new(self.info.0)
+            .build()
+            .user_data(WAKE_USER_DATA);
+        unsafe { win_ring_sq_flush(self.info.0) };
+        // The waiting thread accounts for what is consumed once it is back.
+        self.enter_unchecked(0, 0)
+    }
+
+    /// Cancel every operation in flight on `handle`, as a raw handle rather than an index in the
+    /// registered file table. The cancel is queued to be sent with the next submit, and its own
+    /// completion is not reported; each cancelled operation still completes with
+    /// `HRESULT_FROM_WIN32(ERROR_OPERATION_ABORTED)`.
+    ///
+    /// To cancel a single operation, use the [`OpHandle`](crate::squeue::OpHandle) returned when
+    /// it was pushed.
+    pub fn cancel_all_for(&self, handle: HANDLE) -> io::Result<()> {
+        let file = NT_IORING_HANDLEREF {
+            Handle: handle as _,
+        };
+        self.queue_cancel(file, _NT_IORING_OP_FLAGS_NT_IORING_OP_FLAG_NONE, 0)
+    }
+
+    /// Queue a cancel of the operations on `file` whose user data is `cancel_id`, or all of them
+    /// if it is `0`, with a `user_data` that keeps its completion from being reported.
+    pub(crate) fn queue_cancel(
+        &self,
+        file: NT_IORING_HANDLEREF,
+        flags: _NT_IORING_OP_FLAGS,
+        cancel_id: u64,
+    ) -> io::Result<()> {
+        let cancel = opcode::Cancel::new(
+            self.info.0,
+            file,
+            flags & _NT_IORING_OP_FLAGS_NT_IORING_OP_FLAG_REGISTERED_FILE,
+        )
+        .cancel_id(cancel_id)
+        .build()
+        .user_data(CANCEL_USER_DATA);
+        unsafe { squeue::enqueue(self.info.0, self.shared, &cancel) }.map_err(io::Error::other)
+    }
+
+    /// The number of operations submitted whose completion has not been reaped yet.
+    ///
+    /// Entries submitted through the raw `win_ring` functions are counted too.
+    pub fn in_flight(&self) -> u32 {
+        // Every entry the kernel takes, through the raw `win_ring` functions too, completes
+        // once. The completion queue's head is loaded first, so that it does not count the
+        // completions of entries taken after the submission queue's head was loaded.
+        let reaped = unsafe { Indices::completion(self.info.0) }.head();
+        self.sq.head().wrapping_sub(reaped)
+    }
+
+    /// The number of completions that can still be posted without overflowing the completion
+    /// queue.
+    fn cq_room(&self) -> u32 {
+        let size = unsafe { (*self.info.0).info.CompletionQueueSize };
+        size.saturating_sub(self.in_flight())
+    }
+
+    /// The number of completions produced in user space, or set aside from the completion queue,
+    /// that have not been reaped.
+    pub(crate) fn notified(&self) -> u32 {
+        let deferred = self.shared.deferred.lock().unwrap().len();
+        (self.shared.synthetic.lock().unwrap().len() + deferred) as u32
+    }
+
+    #[inline]
+    fn cq_ready(&self) -> u32 {
+        unsafe { win_ring_cq_ready(self.info.0) }
+    }
+    /// Get the sqe ring
+    ///
+    /// The entry is reserved, not submitted: the kernel sees it once it has been filled and the
+    /// queue is submitted.
+    pub fn get_sqe(&self) -> io::Result<*mut win_ring_sqe> {
+        if self.sq_space_left() == 0 {
+            return Err(io::Error::other("No space left in sqe ring"));
+        }
         let sqe = unsafe { win_ring_get_sqe(self.info.0) };
         Ok(sqe)
     }
@@ -93,8 +512,8 @@ impl<'a> Submitter<'a> {
     /// [`ReadFixed`](crate::opcode::ReadFixed) and [`WriteFixed`](crate::opcode::WriteFixed)
     /// operations.
     /// This function is replica of register_files_bufs()
-    pub fn register_files_bufs(&self, infd: RawHandle, outfd: RawHandle) -> io::Result<()> {
-        let _fds = vec![infd, outfd];
+    pub fn register_files_bufs(&self, infd: HANDLE, outfd: HANDLE) -> io::Result<()> {
+        let _fds = [infd, outfd];
         let _ = opcode::RegisterFiles::new(
             self.info.0,
             _fds.as_ptr() as _,
@@ -131,75 +550,119 @@ impl<'a> Submitter<'a> {
         }
         Ok(())
     }
+}
 
-    /// Register in-memory user buffers for I/O with the kernel. You can use these buffers with the
-    /// [`ReadFixed`](crate::opcode::ReadFixed) and [`WriteFixed`](crate::opcode::WriteFixed)
-    /// operations.
-    /// This function is replica of register_files_bufs()
-    pub fn register_read_write(&self, infd: RawHandle, outfd: RawHandle) -> io::Result<()> {
-        let _fds = vec![infd, outfd];
-        let _ = opcode::RegisterFiles::new(
-            self.info.0,
-            _fds.as_ptr() as _,
-            2,
-            _NT_IORING_REG_FILES_FLAGS {
-                Required: 0,
-                Advisory: 0,
-            },
-            _NT_IORING_OP_FLAGS_NT_IORING_OP_FLAG_NONE,
-        );
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+    use std::{io, ptr, thread};
 
-        static STATIC_BUFFER: [u8; BS] = [0u8; BS];
+    use crate::testfile::TestFile;
+    use crate::windows::{
+        NT_IORING_BUFFERREF, NT_IORING_HANDLEREF, _NT_IORING_OP_FLAGS_NT_IORING_OP_FLAG_NONE,
+    };
+    use crate::{fs, opcode, IoRing};
 
-        let buf_info = IORING_BUFFER_INFO {
-            Address: &STATIC_BUFFER as *const _ as _,
-            Length: BS as u32,
-        };
+    #[test]
+    fn shared_submitters_feed_the_ring_from_many_threads() {
+        let mut ring = IoRing::emulated(8).unwrap();
+        let workers: Vec<_> = (0..4)
+            .map(|worker| {
+                let submitter = ring.shared_submitter();
+                thread::spawn(move || {
+                    for i in 0..8 {
+                        let nop = opcode::Nop::new(ptr::null_mut())
+                            .build()
+                            .user_data(worker * 8 + i);
+                        unsafe { submitter.push(&nop).unwrap() };
+                    }
+                })
+            })
+            .collect();
+        for worker in workers {
+            worker.join().unwrap();
+        }
 
-        let _ = opcode::RegisterBuffers::new(
-            self.info.0,
-            &buf_info,
-            1,
-            _NT_IORING_REG_BUFFERS_FLAGS {
-                Required: 0,
-                Advisory: 0,
-            },
-            _NT_IORING_OP_FLAGS_NT_IORING_OP_FLAG_NONE,
-        );
-        unsafe {
-            CompletionQueue::<'_>::clear_cqes(
-                self.info.0 as *const _ as *mut _,
-                "submit register_buffer",
-            )?;
+        // The completion queue only has room for 16 of the 32 operations at a time.
+        let mut completed = BTreeSet::new();
+        for _ in 0..4 {
+            ring.submit().unwrap();
+            completed.extend(ring.completion().map(|cqe| cqe.user_data()));
         }
-        todo!()
+        assert_eq!(completed, (0..32).collect());
+        assert_eq!(ring.in_flight(), 0);
     }
 
-    /// Register in-memory user buffers for I/O with the kernel. You can use these buffers with the
-    /// This function is replica of queue_read_write_pair()
-    pub fn queue_read_write_pair(&self, offset: u64, size: usize) -> io::Result<()> {
-        let sqe = self.get_sqe()?;
-        todo!()
-    }
-
-    /// Copy the file of series of infd to outfd
-    /// This function is replica of copy_file()
-    pub fn copy_file(&self, size: u64) -> io::Result<()> {
-        for offset in (0..size - (BS as u64)).step_by(BS) {
-            unsafe {
-                self.queue_read_write_pair(offset, BS)?;
-                if self.sq_space_left() < 2 {
-                    CompletionQueue::clear_cqes(self.info.0, "read_write")?;
-                }
-            }
-        }
-        let offset: u64 = size - size % BS as u64;
-        if offset != size {
-            self.queue_read_write_pair(offset, (size - offset) as usize)?;
-            unsafe {
-                CompletionQueue::clear_cqes(self.info.0, "read_write")?;
+    #[test]
+    fn shared_submitter_refuses_entries_built_in_the_queue() {
+        let ring = IoRing::emulated(8).unwrap();
+        let nop = opcode::Nop::new(ring.info.0).build();
+        let err = unsafe { ring.shared_submitter().push(&nop) }.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+        let submitter = ring.shared_submitter();
+        drop(ring);
+        let nop = opcode::Nop::new(ptr::null_mut()).build();
+        let err = unsafe { submitter.push(&nop) }.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::BrokenPipe);
+    }
+
+    #[test]
+    fn wake_cuts_a_wait_short() {
+        let path = TestFile::new("wake", b"wake");
+        let file = std::fs::File::open(path.path()).unwrap();
+        let handle = fs::raw_handle(&file);
+
+        let mut ring = IoRing::emulated(8).unwrap();
+        ring.emulator().unwrap().stall(handle);
+        let mut buf = [0u8; 4];
+        let read = opcode::Read::new(
+            ring.info.0,
+            NT_IORING_HANDLEREF {
+                Handle: handle as _,
+            },
+            NT_IORING_BUFFERREF {
+                Address: buf.as_mut_ptr() as _,
+            },
+            buf.len() as u32,
+            0,
+            _NT_IORING_OP_FLAGS_NT_IORING_OP_FLAG_NONE,
+        )
+        .build()
+        .user_data(1);
+        unsafe { ring.submission().push(&read).unwrap() };
+
+        let ring = Arc::new(ring);
+        let waiter = thread::spawn({
+            let ring = ring.clone();
+            move || {
+                let start = Instant::now();
+                ring.submitter().submit_and_wait(1, 10_000).unwrap();
+                start.elapsed()
             }
-        }
-        Ok(())
+        });
+        thread::sleep(Duration::from_millis(20));
+        ring.wake().unwrap();
+        assert!(waiter.join().unwrap() < Duration::from_secs(5));
+
+        let mut ring = Arc::into_inner(ring).unwrap();
+        // The wake has completed, unreported.
+        assert_eq!(ring.completion().count(), 0);
+        assert_eq!(ring.in_flight(), 1);
+
+        // With nobody waiting, the next wait is cut short, and the queue is left alone.
+        ring.wake().unwrap();
+        assert_eq!(ring.submission().len(), 0);
+        let start = Instant::now();
+        ring.submitter().submit_and_wait(1, 10_000).unwrap();
+        assert!(start.elapsed() < Duration::from_secs(5));
+        assert_eq!(ring.completion().count(), 0);
+        ring.emulator().unwrap().resume(handle);
+        ring.submit().unwrap();
+        let cqes: Vec<_> = ring.completion().map(|cqe| cqe.user_data()).collect();
+        assert_eq!(cqes, [1]);
+        assert_eq!(&buf, b"wake");
     }
 }
@@ -0,0 +1,30 @@
+//! Files the tests read and write, made in the temporary directory.
+
+use std::path::{Path, PathBuf};
+
+/// A file named after the test and the process, so that concurrent test runs do not share it,
+/// and removed when dropped.
+///
+/// Declare it before the handles opened on it: Windows refuses to delete a file that is still
+/// open.
+pub(crate) struct TestFile(PathBuf);
+
+impl TestFile {
+    /// Create `ioring-rs-<name>-<pid>` holding `contents`.
+    pub(crate) fn new(name: &str, contents: &[u8]) -> TestFile {
+        let name = format!("ioring-rs-{}-{}", name, std::process::id());
+        let path = std::env::temp_dir().join(name);
+        std::fs::write(&path, contents).unwrap();
+        TestFile(path)
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TestFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
@@ -0,0 +1,186 @@
+//! The timer wheel behind [`Entry::timeout`](crate::squeue::Entry::timeout).
+//!
+//! IoRing has no timeout opcode, so deadlines are kept in user space. When one passes, the
+//! submitter queues an [`opcode::Cancel`](crate::opcode::Cancel) for the operation, and the
+//! completion queue reports the resulting abort as a timeout.
+
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+
+use crate::windows::{NT_IORING_HANDLEREF, _NT_IORING_OP_FLAGS};
+
+/// `user_data` of the cancels queued for expired timers. Their completions are not reported.
+pub(crate) const CANCEL_USER_DATA: u64 = u64::MAX - 2;
+
+const TICK: Duration = Duration::from_millis(1);
+const SLOTS: u64 = 256;
+
+/// An operation waiting on its deadline, with what is needed to cancel it.
+#[derive(Clone, Copy)]
+pub(crate) struct Timer {
+    pub(crate) user_data: u64,
+    pub(crate) file: NT_IORING_HANDLEREF,
+    pub(crate) flags: _NT_IORING_OP_FLAGS,
+}
+
+/// A hashed timer wheel with a resolution of one millisecond. A timer lands in the slot of its
+/// deadline tick modulo the number of slots, and only fires when the wheel has reached its tick,
+/// so deadlines further away than one revolution simply stay put for a while.
+pub(crate) struct TimerWheel {
+    start: Instant,
+    /// The first tick that has not been expired yet.
+    current: u64,
+    slots: Vec<Vec<(u64, Timer)>>,
+    /// The tick of every armed timer, by `user_data`.
+    ticks: HashMap<u64, u64>,
+    /// Operations whose timer fired and whose completion has not been reaped yet.
+    pub(crate) expired: HashSet<u64>,
+}
+
+impl Default for TimerWheel {
+    fn default() -> Self {
+        TimerWheel::new(Instant::now())
+    }
+}
+
+impl TimerWheel {
+    fn new(start: Instant) -> TimerWheel {
+        TimerWheel {
+            start,
+            current: 0,
+            slots: (0..SLOTS).map(|_| Vec::new()).collect(),
+            ticks: HashMap::new(),
+            expired: HashSet::new(),
+        }
+    }
+
+    /// The last tick that has started by `instant`.
+    fn tick_of(&self, instant: Instant) -> u64 {
+        (instant.saturating_duration_since(self.start).as_nanos() / TICK.as_nanos()) as u64
+    }
+
+    /// Arm `timer` to fire once `deadline` has passed, replacing any timer with the same
+    /// `user_data`.
+    pub(crate) fn insert(&mut self, deadline: Instant, timer: Timer) {
+        self.remove(timer.user_data);
+        // Round up, so that a timer never fires before its deadline.
+        let elapsed = deadline.saturating_duration_since(self.start).as_nanos();
+        let tick = elapsed.div_ceil(TICK.as_nanos()) as u64;
+        let tick = tick.max(self.current);
+        self.slots[(tick % SLOTS) as usize].push((tick, timer));
+        self.ticks.insert(timer.user_data, tick);
+    }
+
+    /// Disarm the timer of `user_data`, returning whether there was one.
+    pub(crate) fn remove(&mut self, user_data: u64) -> bool {
+        match self.ticks.remove(&user_data) {
+            Some(tick) => {
+                self.slots[(tick % SLOTS) as usize]
+                    .retain(|(_, timer)| timer.user_data != user_data);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.ticks.is_empty()
+    }
+
+    /// The earliest deadline among the armed timers, rounded up to the wheel's resolution.
+    pub(crate) fn next_deadline(&self) -> Option<Instant> {
+        let tick = *self.ticks.values().min()?;
+        Some(self.start + Duration::from_nanos(tick * TICK.as_nanos() as u64))
+    }
+
+    /// Take out every timer whose deadline has passed by `now`, and remember their operations as
+    /// [`expired`](Self::expired).
+    pub(crate) fn expire(&mut self, now: Instant) -> Vec<Timer> {
+        let now = self.tick_of(now);
+        let mut fired = Vec::new();
+        if now < self.current {
+            return fired;
+        }
+        for tick in self.current..=now.min(self.current + SLOTS - 1) {
+            let slot = &mut self.slots[(tick % SLOTS) as usize];
+            let mut i = 0;
+            while i < slot.len() {
+                if slot[i].0 <= now {
+                    fired.push(slot.swap_remove(i).1);
+                } else {
+                    i += 1;
+                }
+            }
+        }
+        self.current = now + 1;
+        for timer in &fired {
+            self.ticks.remove(&timer.user_data);
+            self.expired.insert(timer.user_data);
+        }
+        fired
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn timer(user_data: u64) -> Timer {
+        Timer {
+            user_data,
+            file: NT_IORING_HANDLEREF { Handle: 0 },
+            flags: 0,
+        }
+    }
+
+    fn fired(wheel: &mut TimerWheel, now: Instant) -> Vec<u64> {
+        let mut fired: Vec<_> = wheel.expire(now).iter().map(|t| t.user_data).collect();
+        fired.sort_unstable();
+        fired
+    }
+
+    #[test]
+    fn fires_only_after_deadline() {
+        let start = Instant::now();
+        let mut wheel = TimerWheel::new(start);
+        wheel.insert(start + Duration::from_millis(5), timer(1));
+        wheel.insert(start + Duration::from_millis(10), timer(2));
+        assert_eq!(
+            wheel.next_deadline(),
+            Some(start + Duration::from_millis(5))
+        );
+
+        assert!(fired(&mut wheel, start + Duration::from_micros(4900)).is_empty());
+        assert_eq!(fired(&mut wheel, start + Duration::from_millis(7)), [1]);
+        assert_eq!(fired(&mut wheel, start + Duration::from_millis(10)), [2]);
+        assert!(wheel.is_empty());
+        assert!(wheel.expired.contains(&1) && wheel.expired.contains(&2));
+    }
+
+    #[test]
+    fn deadlines_beyond_one_revolution() {
+        let start = Instant::now();
+        let mut wheel = TimerWheel::new(start);
+        let far = Duration::from_millis(SLOTS * 3 + 7);
+        wheel.insert(start + far, timer(1));
+        wheel.insert(start + Duration::from_millis(7), timer(2));
+
+        assert_eq!(
+            fired(&mut wheel, start + Duration::from_millis(SLOTS + 7)),
+            [2]
+        );
+        assert!(fired(&mut wheel, start + far - TICK).is_empty());
+        assert_eq!(fired(&mut wheel, start + far * 2), [1]);
+    }
+
+    #[test]
+    fn removed_timers_do_not_fire() {
+        let start = Instant::now();
+        let mut wheel = TimerWheel::new(start);
+        wheel.insert(start + Duration::from_millis(3), timer(1));
+        wheel.insert(start + Duration::from_millis(3), timer(2));
+        assert!(wheel.remove(1));
+        assert!(!wheel.remove(1));
+        assert_eq!(fired(&mut wheel, start + Duration::from_millis(3)), [2]);
+    }
+}
@@ -0,0 +1,158 @@
+//! Instrumentation through the [`tracing`](https://docs.rs/tracing) crate, with the `tracing`
+//! feature. Without it, every function here is empty and compiles away.
+//!
+//! Submits and waits get `debug` spans, while entries pushed and completions reaped are
+//! `trace` events, all under the `ioring_rs::trace` target.
+
+use crate::cqueue;
+use crate::windows::_NT_IORING_SQE;
+#[cfg(feature = "tracing")]
+use crate::windows::{_IORING_OP_CODE_IORING_OP_READ, _IORING_OP_CODE_IORING_OP_WRITE};
+#[cfg(feature = "tracing")]
+use crate::{squeue::target, stats};
+
+/// Keeps a span entered until it is dropped.
+#[cfg(feature = "tracing")]
+pub(crate) type Guard = tracing::span::EnteredSpan;
+#[cfg(not(feature = "tracing"))]
+pub(crate) struct Guard;
+
+/// Enter the span of a call to
+/// [`Submitter::submit_and_wait`](crate::submit::Submitter::submit_and_wait).
+#[inline(always)]
+pub(crate) fn submit(want: u32, timeout: usize) -> Guard {
+    #[cfg(feature = "tracing")]
+    return tracing::debug_span!("submit", want, timeout).entered();
+    #[cfg(not(feature = "tracing"))]
+    {
+        let _ = (want, timeout);
+        Guard
+    }
+}
+
+/// Enter the span of one wait in the kernel, for `want` completions or `timeout` milliseconds.
+#[inline(always)]
+pub(crate) fn wait(submit: u32, want: u32, timeout: u64) -> Guard {
+    #[cfg(feature = "tracing")]
+    return tracing::debug_span!("wait", submit, want, timeout).entered();
+    #[cfg(not(feature = "tracing"))]
+    {
+        let _ = (submit, want, timeout);
+        Guard
+    }
+}
+
+/// Report an entry pushed to the ring, whether into the submission queue or the backlog.
+#[inline(always)]
+pub(crate) fn pushed(sqe: &_NT_IORING_SQE) {
+    #[cfg(feature = "tracing")]
+    {
+        let handle = target(sqe).map(|(file, _)| unsafe { file.Handle });
+        let length = unsafe {
+            match sqe.OpCode {
+                _IORING_OP_CODE_IORING_OP_READ => Some(sqe.__bindgen_anon_1.Read.Length),
+                _IORING_OP_CODE_IORING_OP_WRITE => Some(sqe.__bindgen_anon_1.Write.Length),
+                _ => None,
+            }
+        };
+        tracing::trace!(
+            opcode = stats::name(sqe.OpCode),
+            user_data = sqe.UserData,
+            handle,
+            length,
+            "pushed"
+        );
+    }
+    #[cfg(not(feature = "tracing"))]
+    let _ = sqe;
+}
+
+/// Report a completion reaped from the ring.
+#[inline(always)]
+pub(crate) fn reaped(cqe: &cqueue::Entry) {
+    #[cfg(feature = "tracing")]
+    tracing::trace!(
+        user_data = cqe.user_data(),
+        result = cqe.result(),
+        information = cqe.information(),
+        "reaped"
+    );
+    #[cfg(not(feature = "tracing"))]
+    let _ = cqe;
+}
+
+/// Report a completion thrown away by
+/// [`CompletionQueue::clear_cqes`](cqueue::CompletionQueue::clear_cqes).
+#[inline(always)]
+pub(crate) fn discarded(context: &str, cqe: &cqueue::Entry) {
+    #[cfg(feature = "tracing")]
+    tracing::debug!(
+        context,
+        user_data = cqe.user_data(),
+        result = cqe.result(),
+        information = cqe.information(),
+        "discarded"
+    );
+    #[cfg(not(feature = "tracing"))]
+    let _ = (context, cqe);
+}
+
+#[cfg(all(test, feature = "tracing"))]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use tracing::span::{Attributes, Id, Record};
+    use tracing::{Event, Metadata, Subscriber};
+
+    use crate::{opcode, IoRing};
+
+    /// Collects the names of spans and the messages of events.
+    #[derive(Clone, Default)]
+    struct Names(Arc<Mutex<Vec<String>>>);
+
+    struct Message<'a>(&'a mut String);
+
+    impl tracing::field::Visit for Message<'_> {
+        fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+            if field.name() == "message" {
+                *self.0 = format!("{:?}", value);
+            }
+        }
+    }
+
+    impl Subscriber for Names {
+        fn enabled(&self, _: &Metadata<'_>) -> bool {
+            true
+        }
+        fn new_span(&self, span: &Attributes<'_>) -> Id {
+            self.0
+                .lock()
+                .unwrap()
+                .push(span.metadata().name().to_owned());
+            Id::from_u64(1)
+        }
+        fn record(&self, _: &Id, _: &Record<'_>) {}
+        fn record_follows_from(&self, _: &Id, _: &Id) {}
+        fn event(&self, event: &Event<'_>) {
+            let mut message = String::new();
+            event.record(&mut Message(&mut message));
+            self.0.lock().unwrap().push(message);
+        }
+        fn enter(&self, _: &Id) {}
+        fn exit(&self, _: &Id) {}
+    }
+
+    #[test]
+    fn pushes_and_completions_are_traced() {
+        let names = Names::default();
+        tracing::subscriber::with_default(names.clone(), || {
+            let mut ring = IoRing::emulated(4).unwrap();
+            let nop = opcode::Nop::new(ring.info.0).build().user_data(1);
+            unsafe { ring.submission().push(&nop).unwrap() };
+            ring.submit().unwrap();
+            assert_eq!(ring.completion().count(), 1);
+        });
+        let names = names.0.lock().unwrap();
+        assert_eq!(*names, ["pushed", "submit", "wait", "reaped"]);
+    }
+}
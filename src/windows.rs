@@ -6,17 +6,21 @@
     dead_code,
     clippy::missing_safety_doc
 )]
+// The `bindgen_test_layout_*` tests were generated for x64 and only run on 64-bit targets. The
+// ring ABI is checked against `ioringnt.h` on every target by the `layout` tests. Its unions and
+// `NT_IORING_OP_RESERVED` are `align(8)`: MSVC aligns `uint64_t` to 8 on x86 too, 32-bit Unix to 4.
 
 pub type HANDLE = *mut ::std::os::raw::c_void;
 pub type PHANDLE = *mut HANDLE;
-pub type ULONG = ::std::os::raw::c_ulong;
+// `long` is 32 bits wide on Windows; spelled out so the layouts also hold on LP64 hosts.
+pub type ULONG = u32;
 pub type PULONG = *mut ULONG;
 pub type USHORT = ::std::os::raw::c_ushort;
 pub type PUSHORT = *mut USHORT;
 pub type UCHAR = ::std::os::raw::c_uchar;
 pub type PUCHAR = *mut UCHAR;
 pub type PSZ = *mut ::std::os::raw::c_char;
-pub type DWORD = ::std::os::raw::c_ulong;
+pub type DWORD = u32;
 pub type BOOL = ::std::os::raw::c_int;
 pub type BYTE = ::std::os::raw::c_uchar;
 pub type WORD = ::std::os::raw::c_ushort;
@@ -30,7 +34,7 @@ pub type PINT = *mut ::std::os::raw::c_int;
 pub type LPINT = *mut ::std::os::raw::c_int;
 pub type PWORD = *mut WORD;
 pub type LPWORD = *mut WORD;
-pub type LPLONG = *mut ::std::os::raw::c_long;
+pub type LPLONG = *mut i32;
 pub type PDWORD = *mut DWORD;
 pub type LPDWORD = *mut DWORD;
 pub type LPVOID = *mut ::std::os::raw::c_void;
@@ -97,7 +101,7 @@ pub type PKAFFINITY = *mut KAFFINITY;
 pub type PVOID = *mut ::std::os::raw::c_void;
 pub type CHAR = ::std::os::raw::c_char;
 pub type SHORT = ::std::os::raw::c_short;
-pub type LONG = ::std::os::raw::c_long;
+pub type LONG = i32;
 pub type WCHAR = u16;
 pub type PWCHAR = *mut WCHAR;
 pub type LPWCH = *mut WCHAR;
@@ -129,7 +133,7 @@ pub type LPCWCHAR = *const WCHAR;
 pub type PCWCHAR = *const WCHAR;
 pub type LPCUWCHAR = *const WCHAR;
 pub type PCUWCHAR = *const WCHAR;
-pub type UCSCHAR = ::std::os::raw::c_ulong;
+pub type UCSCHAR = u32;
 pub type PUCSCHAR = *mut UCSCHAR;
 pub type PCUCSCHAR = *const UCSCHAR;
 pub type PUCSSTR = *mut UCSCHAR;
@@ -183,7 +187,7 @@ pub type PCNZTCH = PCNZCH;
 pub type PCUNZTCH = PCNZCH;
 pub type PSHORT = *mut SHORT;
 pub type PLONG = *mut LONG;
-pub type HRESULT = ::std::os::raw::c_long;
+pub type HRESULT = i32;
 pub type LONGLONG = ::std::os::raw::c_longlong;
 pub type ULONGLONG = ::std::os::raw::c_ulonglong;
 pub type PLONGLONG = *mut LONGLONG;
@@ -238,6 +242,7 @@ pub struct _LARGE_INTEGER__bindgen_ty_1 {
     pub HighPart: LONG,
 }
 #[test]
+#[cfg(target_pointer_width = "64")]
 fn bindgen_test_layout__LARGE_INTEGER__bindgen_ty_1() {
     assert_eq!(
         ::std::mem::size_of::<_LARGE_INTEGER__bindgen_ty_1>(),
@@ -291,6 +296,7 @@ pub struct _LARGE_INTEGER__bindgen_ty_2 {
     pub HighPart: LONG,
 }
 #[test]
+#[cfg(target_pointer_width = "64")]
 fn bindgen_test_layout__LARGE_INTEGER__bindgen_ty_2() {
     assert_eq!(
         ::std::mem::size_of::<_LARGE_INTEGER__bindgen_ty_2>(),
@@ -338,6 +344,7 @@ fn bindgen_test_layout__LARGE_INTEGER__bindgen_ty_2() {
     test_field_HighPart();
 }
 #[test]
+#[cfg(target_pointer_width = "64")]
 fn bindgen_test_layout__LARGE_INTEGER() {
     assert_eq!(
         ::std::mem::size_of::<_LARGE_INTEGER>(),
@@ -391,6 +398,7 @@ pub struct _LIST_ENTRY {
     pub Blink: *mut _LIST_ENTRY,
 }
 #[test]
+#[cfg(target_pointer_width = "64")]
 fn bindgen_test_layout__LIST_ENTRY() {
     assert_eq!(
         ::std::mem::size_of::<_LIST_ENTRY>(),
@@ -461,7 +469,7 @@ pub const _NT_IORING_OP_FLAGS_NT_IORING_OP_FLAG_REGISTERED_FILE: _NT_IORING_OP_F
 pub const _NT_IORING_OP_FLAGS_NT_IORING_OP_FLAG_REGISTERED_BUFFER: _NT_IORING_OP_FLAGS = 2;
 pub type _NT_IORING_OP_FLAGS = ::std::os::raw::c_int;
 pub use self::_NT_IORING_OP_FLAGS as NT_IORING_OP_FLAGS;
-#[repr(C)]
+#[repr(C, align(8))]
 #[derive(Copy, Clone)]
 pub union _NT_IORING_HANDLEREF {
     pub Handle: u64,
@@ -477,6 +485,7 @@ impl ::core::fmt::Debug for _NT_IORING_HANDLEREF {
     }
 }
 #[test]
+#[cfg(target_pointer_width = "64")]
 fn bindgen_test_layout__NT_IORING_HANDLEREF() {
     assert_eq!(
         ::std::mem::size_of::<_NT_IORING_HANDLEREF>(),
@@ -536,6 +545,7 @@ pub struct _IORING_REGISTERED_BUFFER {
     pub Offset: u32,
 }
 #[test]
+#[cfg(target_pointer_width = "64")]
 fn bindgen_test_layout__IORING_REGISTERED_BUFFER() {
     assert_eq!(
         ::std::mem::size_of::<_IORING_REGISTERED_BUFFER>(),
@@ -584,7 +594,7 @@ fn bindgen_test_layout__IORING_REGISTERED_BUFFER() {
 }
 pub type IORING_REGISTERED_BUFFER = _IORING_REGISTERED_BUFFER;
 pub type PIORING_REGISTERED_BUFFER = _IORING_REGISTERED_BUFFER;
-#[repr(C)]
+#[repr(C, align(8))]
 #[derive(Copy, Clone)]
 pub union _NT_IORING_BUFFERREF {
     pub Address: u64,
@@ -600,6 +610,7 @@ impl ::core::fmt::Debug for _NT_IORING_BUFFERREF {
     }
 }
 #[test]
+#[cfg(target_pointer_width = "64")]
 fn bindgen_test_layout__NT_IORING_BUFFERREF() {
     assert_eq!(
         ::std::mem::size_of::<_NT_IORING_BUFFERREF>(),
@@ -660,6 +671,7 @@ pub struct _NT_IORING_OP_READ {
     pub Key: u32,
 }
 #[test]
+#[cfg(target_pointer_width = "64")]
 fn bindgen_test_layout__NT_IORING_OP_READ() {
     assert_eq!(
         ::std::mem::size_of::<_NT_IORING_OP_READ>(),
@@ -808,6 +820,7 @@ pub struct _NT_IORING_REG_FILES_FLAGS {
     pub Advisory: NT_IORING_REG_FILES_ADV_FLAGS,
 }
 #[test]
+#[cfg(target_pointer_width = "64")]
 fn bindgen_test_layout__NT_IORING_REG_FILES_FLAGS() {
     assert_eq!(
         ::std::mem::size_of::<_NT_IORING_REG_FILES_FLAGS>(),
@@ -864,13 +877,14 @@ pub struct _NT_IORING_OP_REGISTER_FILES {
     pub Count: u32,
     pub __bindgen_anon_1: _NT_IORING_OP_REGISTER_FILES__bindgen_ty_1,
 }
-#[repr(C)]
+#[repr(C, align(8))]
 #[derive(Copy, Clone)]
 pub union _NT_IORING_OP_REGISTER_FILES__bindgen_ty_1 {
     pub Handles: *const HANDLE,
     pub PadX86_Handles: u64,
 }
 #[test]
+#[cfg(target_pointer_width = "64")]
 fn bindgen_test_layout__NT_IORING_OP_REGISTER_FILES__bindgen_ty_1() {
     assert_eq!(
         ::std::mem::size_of::<_NT_IORING_OP_REGISTER_FILES__bindgen_ty_1>(),
@@ -926,6 +940,7 @@ fn bindgen_test_layout__NT_IORING_OP_REGISTER_FILES__bindgen_ty_1() {
     test_field_PadX86_Handles();
 }
 #[test]
+#[cfg(target_pointer_width = "64")]
 fn bindgen_test_layout__NT_IORING_OP_REGISTER_FILES() {
     assert_eq!(
         ::std::mem::size_of::<_NT_IORING_OP_REGISTER_FILES>(),
@@ -1006,6 +1021,7 @@ pub struct _NT_IORING_REG_BUFFERS_FLAGS {
     pub Advisory: NT_IORING_REG_BUFFERS_ADV_FLAGS,
 }
 #[test]
+#[cfg(target_pointer_width = "64")]
 fn bindgen_test_layout__NT_IORING_REG_BUFFERS_FLAGS() {
     assert_eq!(
         ::std::mem::size_of::<_NT_IORING_REG_BUFFERS_FLAGS>(),
@@ -1061,6 +1077,7 @@ pub struct _IORING_BUFFER_INFO {
     pub Length: u32,
 }
 #[test]
+#[cfg(target_pointer_width = "64")]
 fn bindgen_test_layout__IORING_BUFFER_INFO() {
     assert_eq!(
         ::std::mem::size_of::<_IORING_BUFFER_INFO>(),
@@ -1117,13 +1134,14 @@ pub struct _NT_IORING_OP_REGISTER_BUFFERS {
     pub Count: u32,
     pub __bindgen_anon_1: _NT_IORING_OP_REGISTER_BUFFERS__bindgen_ty_1,
 }
-#[repr(C)]
+#[repr(C, align(8))]
 #[derive(Copy, Clone)]
 pub union _NT_IORING_OP_REGISTER_BUFFERS__bindgen_ty_1 {
     pub Buffers: *const IORING_BUFFER_INFO,
     pub PadX86_Buffers: u64,
 }
 #[test]
+#[cfg(target_pointer_width = "64")]
 fn bindgen_test_layout__NT_IORING_OP_REGISTER_BUFFERS__bindgen_ty_1() {
     assert_eq!(
         ::std::mem::size_of::<_NT_IORING_OP_REGISTER_BUFFERS__bindgen_ty_1>(),
@@ -1181,6 +1199,7 @@ fn bindgen_test_layout__NT_IORING_OP_REGISTER_BUFFERS__bindgen_ty_1() {
     test_field_PadX86_Buffers();
 }
 #[test]
+#[cfg(target_pointer_width = "64")]
 fn bindgen_test_layout__NT_IORING_OP_REGISTER_BUFFERS() {
     assert_eq!(
         ::std::mem::size_of::<_NT_IORING_OP_REGISTER_BUFFERS>(),
@@ -1254,6 +1273,7 @@ pub struct _NT_IORING_OP_CANCEL {
     pub CancelId: u64,
 }
 #[test]
+#[cfg(target_pointer_width = "64")]
 fn bindgen_test_layout__NT_IORING_OP_CANCEL() {
     assert_eq!(
         ::std::mem::size_of::<_NT_IORING_OP_CANCEL>(),
@@ -1334,6 +1354,7 @@ pub struct _NT_IORING_OP_WRITE {
     pub Key: u32,
 }
 #[test]
+#[cfg(target_pointer_width = "64")]
 fn bindgen_test_layout__NT_IORING_OP_WRITE() {
     assert_eq!(
         ::std::mem::size_of::<_NT_IORING_OP_WRITE>(),
@@ -1475,6 +1496,7 @@ pub struct _NT_IORING_OP_FLUSH {
     pub File: NT_IORING_HANDLEREF,
 }
 #[test]
+#[cfg(target_pointer_width = "64")]
 fn bindgen_test_layout__NT_IORING_OP_FLUSH() {
     assert_eq!(
         ::std::mem::size_of::<_NT_IORING_OP_FLUSH>(),
@@ -1540,7 +1562,7 @@ fn bindgen_test_layout__NT_IORING_OP_FLUSH() {
 }
 pub type NT_IORING_OP_FLUSH = _NT_IORING_OP_FLUSH;
 pub type PNT_IORING_OP_FLUSH = *mut _NT_IORING_OP_FLUSH;
-#[repr(C)]
+#[repr(C, align(8))]
 #[derive(Debug, Copy, Clone)]
 pub struct _NT_IORING_OP_RESERVED {
     pub Argument1: u64,
@@ -1551,6 +1573,7 @@ pub struct _NT_IORING_OP_RESERVED {
     pub Argument6: u64,
 }
 #[test]
+#[cfg(target_pointer_width = "64")]
 fn bindgen_test_layout__NT_IORING_OP_RESERVED() {
     assert_eq!(
         ::std::mem::size_of::<_NT_IORING_OP_RESERVED>(),
@@ -1687,6 +1710,7 @@ pub union _NT_IORING_SQE__bindgen_ty_1 {
     pub ReservedMaxSizePadding: NT_IORING_OP_RESERVED,
 }
 #[test]
+#[cfg(target_pointer_width = "64")]
 fn bindgen_test_layout__NT_IORING_SQE__bindgen_ty_1() {
     assert_eq!(
         ::std::mem::size_of::<_NT_IORING_SQE__bindgen_ty_1>(),
@@ -1819,6 +1843,7 @@ fn bindgen_test_layout__NT_IORING_SQE__bindgen_ty_1() {
     test_field_ReservedMaxSizePadding();
 }
 #[test]
+#[cfg(target_pointer_width = "64")]
 fn bindgen_test_layout__NT_IORING_SQE() {
     assert_eq!(
         ::std::mem::size_of::<_NT_IORING_SQE>(),
@@ -1905,6 +1930,7 @@ pub struct _NT_IORING_CREATE_FLAGS {
     pub Advisory: NT_IORING_CREATE_ADVISORY_FLAGS,
 }
 #[test]
+#[cfg(target_pointer_width = "64")]
 fn bindgen_test_layout__NT_IORING_CREATE_FLAGS() {
     assert_eq!(
         ::std::mem::size_of::<_NT_IORING_CREATE_FLAGS>(),
@@ -1964,6 +1990,7 @@ pub struct _NT_IORING_SUBMISSION_QUEUE {
     pub Entries: __IncompleteArrayField<NT_IORING_SQE>,
 }
 #[test]
+#[cfg(target_pointer_width = "64")]
 fn bindgen_test_layout__NT_IORING_SUBMISSION_QUEUE() {
     assert_eq!(
         ::std::mem::size_of::<_NT_IORING_SUBMISSION_QUEUE>(),
@@ -1985,7 +2012,7 @@ pub struct _NT_IORING_CQE {
     pub __bindgen_anon_1: _NT_IORING_CQE__bindgen_ty_1,
     pub Information: u64,
 }
-#[repr(C)]
+#[repr(C, align(8))]
 #[derive(Copy, Clone)]
 pub union _NT_IORING_CQE__bindgen_ty_1 {
     pub ResultCode: HRESULT,
@@ -1993,6 +2020,7 @@ pub union _NT_IORING_CQE__bindgen_ty_1 {
     pub PadX86_Pointer: u64,
 }
 #[test]
+#[cfg(target_pointer_width = "64")]
 fn bindgen_test_layout__NT_IORING_CQE__bindgen_ty_1() {
     assert_eq!(
         ::std::mem::size_of::<_NT_IORING_CQE__bindgen_ty_1>(),
@@ -2057,6 +2085,7 @@ fn bindgen_test_layout__NT_IORING_CQE__bindgen_ty_1() {
     test_field_PadX86_Pointer();
 }
 #[test]
+#[cfg(target_pointer_width = "64")]
 fn bindgen_test_layout__NT_IORING_CQE() {
     assert_eq!(
         ::std::mem::size_of::<_NT_IORING_CQE>(),
@@ -2114,6 +2143,7 @@ pub struct _NT_IORING_COMPLETION_QUEUE {
 }
 
 #[test]
+#[cfg(target_pointer_width = "64")]
 fn bindgen_test_layout__NT_IORING_COMPLETION_QUEUE() {
     assert_eq!(
         ::std::mem::size_of::<_NT_IORING_COMPLETION_QUEUE>(),
@@ -2157,7 +2187,7 @@ impl Default for _NT_IORING_INFO {
         }
     }
 }
-#[repr(C)]
+#[repr(C, align(8))]
 #[derive(Copy, Clone)]
 pub union _NT_IORING_INFO__bindgen_ty_1 {
     pub SubmissionQueue: *mut NT_IORING_SUBMISSION_QUEUE,
@@ -2169,6 +2199,7 @@ impl ::std::fmt::Debug for _NT_IORING_INFO__bindgen_ty_1 {
     }
 }
 #[test]
+#[cfg(target_pointer_width = "64")]
 fn bindgen_test_layout__NT_IORING_INFO__bindgen_ty_1() {
     assert_eq!(
         ::std::mem::size_of::<_NT_IORING_INFO__bindgen_ty_1>(),
@@ -2215,7 +2246,7 @@ fn bindgen_test_layout__NT_IORING_INFO__bindgen_ty_1() {
     }
     test_field_PadX86_SubmissionQueue();
 }
-#[repr(C)]
+#[repr(C, align(8))]
 #[derive(Copy, Clone)]
 pub union _NT_IORING_INFO__bindgen_ty_2 {
     pub CompletionQueue: *mut NT_IORING_COMPLETION_QUEUE,
@@ -2227,6 +2258,7 @@ impl ::std::fmt::Debug for _NT_IORING_INFO__bindgen_ty_2 {
     }
 }
 #[test]
+#[cfg(target_pointer_width = "64")]
 fn bindgen_test_layout__NT_IORING_INFO__bindgen_ty_2() {
     assert_eq!(
         ::std::mem::size_of::<_NT_IORING_INFO__bindgen_ty_2>(),
@@ -2274,6 +2306,7 @@ fn bindgen_test_layout__NT_IORING_INFO__bindgen_ty_2() {
     test_field_PadX86_CompletionQueue();
 }
 #[test]
+#[cfg(target_pointer_width = "64")]
 fn bindgen_test_layout__NT_IORING_INFO() {
     assert_eq!(
         ::std::mem::size_of::<_NT_IORING_INFO>(),
@@ -2399,6 +2432,7 @@ pub struct _NT_IORING_STRUCTV1 {
     pub Flags: NT_IORING_CREATE_FLAGS,
 }
 #[test]
+#[cfg(target_pointer_width = "64")]
 fn bindgen_test_layout__NT_IORING_STRUCTV1() {
     assert_eq!(
         ::std::mem::size_of::<_NT_IORING_STRUCTV1>(),
@@ -2496,6 +2530,7 @@ pub struct _NT_IORING_CAPABILITIES {
     pub CompletionQueueSize: u32,
 }
 #[test]
+#[cfg(target_pointer_width = "64")]
 fn bindgen_test_layout__NT_IORING_CAPABILITIES() {
     assert_eq!(
         ::std::mem::size_of::<_NT_IORING_CAPABILITIES>(),
@@ -2599,6 +2634,10 @@ pub const _NT_IORING_INFO_CLASS_IoRingInvalid: _NT_IORING_INFO_CLASS = 0;
 pub const _NT_IORING_INFO_CLASS_IoRingRegisterUserCompletionEventClass: _NT_IORING_INFO_CLASS = 1;
 pub type _NT_IORING_INFO_CLASS = ::std::os::raw::c_int;
 pub use self::_NT_IORING_INFO_CLASS as NT_IORING_INFO_CLASS;
+// Off Windows, the system calls and the `win_ring` helpers come from the Rust port instead.
+#[cfg(not(windows))]
+pub use crate::winring::*;
+#[cfg(windows)]
 extern "C" {
     pub fn NtSubmitIoRing(
         Handle: HANDLE,
@@ -2607,6 +2646,7 @@ extern "C" {
         Timeout: *mut u64,
     ) -> NTSTATUS;
 }
+#[cfg(windows)]
 extern "C" {
     pub fn NtCreateIoRing(
         pIoRingHandle: PHANDLE,
@@ -2616,12 +2656,14 @@ extern "C" {
         pRingInfo: PNT_IORING_INFO,
     ) -> NTSTATUS;
 }
+#[cfg(windows)]
 extern "C" {
     pub fn NtQueryIoRingCapabilities(
         CapabilitiesLength: size_t,
         Capabilities: PNT_IORING_CAPABILITIES,
     ) -> NTSTATUS;
 }
+#[cfg(windows)]
 extern "C" {
     pub fn NtSetInformationIoRing(
         Handle: HANDLE,
@@ -2639,6 +2681,7 @@ pub struct _STRING {
     pub Buffer: PCHAR,
 }
 #[test]
+#[cfg(target_pointer_width = "64")]
 fn bindgen_test_layout__STRING() {
     assert_eq!(
         ::std::mem::size_of::<_STRING>(),
@@ -2718,6 +2761,7 @@ pub struct _UNICODE_STRING {
     pub Buffer: PWSTR,
 }
 #[test]
+#[cfg(target_pointer_width = "64")]
 fn bindgen_test_layout__UNICODE_STRING() {
     assert_eq!(
         ::std::mem::size_of::<_UNICODE_STRING>(),
@@ -2792,6 +2836,7 @@ pub struct _CLIENT_ID {
     pub UniqueThread: HANDLE,
 }
 #[test]
+#[cfg(target_pointer_width = "64")]
 fn bindgen_test_layout__CLIENT_ID() {
     assert_eq!(
         ::std::mem::size_of::<_CLIENT_ID>(),
@@ -2847,6 +2892,7 @@ pub struct _PEB_LDR_DATA {
     pub InMemoryOrderModuleList: LIST_ENTRY,
 }
 #[test]
+#[cfg(target_pointer_width = "64")]
 fn bindgen_test_layout__PEB_LDR_DATA() {
     assert_eq!(
         ::std::mem::size_of::<_PEB_LDR_DATA>(),
@@ -2933,6 +2979,7 @@ pub union _LDR_DATA_TABLE_ENTRY__bindgen_ty_1 {
     pub Reserved6: PVOID,
 }
 #[test]
+#[cfg(target_pointer_width = "64")]
 fn bindgen_test_layout__LDR_DATA_TABLE_ENTRY__bindgen_ty_1() {
     assert_eq!(
         ::std::mem::size_of::<_LDR_DATA_TABLE_ENTRY__bindgen_ty_1>(),
@@ -2985,6 +3032,7 @@ fn bindgen_test_layout__LDR_DATA_TABLE_ENTRY__bindgen_ty_1() {
     test_field_Reserved6();
 }
 #[test]
+#[cfg(target_pointer_width = "64")]
 fn bindgen_test_layout__LDR_DATA_TABLE_ENTRY() {
     assert_eq!(
         ::std::mem::size_of::<_LDR_DATA_TABLE_ENTRY>(),
@@ -3161,6 +3209,7 @@ pub struct _RTL_USER_PROCESS_PARAMETERS {
     pub CommandLine: UNICODE_STRING,
 }
 #[test]
+#[cfg(target_pointer_width = "64")]
 fn bindgen_test_layout__RTL_USER_PROCESS_PARAMETERS() {
     assert_eq!(
         ::std::mem::size_of::<_RTL_USER_PROCESS_PARAMETERS>(),
@@ -3268,6 +3317,7 @@ pub struct _PEB {
     pub SessionId: ULONG,
 }
 #[test]
+#[cfg(target_pointer_width = "64")]
 fn bindgen_test_layout__PEB() {
     assert_eq!(
         ::std::mem::size_of::<_PEB>(),
@@ -3615,6 +3665,7 @@ pub struct _TEB {
     pub TlsExpansionSlots: PVOID,
 }
 #[test]
+#[cfg(target_pointer_width = "64")]
 fn bindgen_test_layout__TEB() {
     assert_eq!(
         ::std::mem::size_of::<_TEB>(),
@@ -3810,6 +3861,7 @@ pub struct _OBJECT_ATTRIBUTES {
     pub SecurityQualityOfService: PVOID,
 }
 #[test]
+#[cfg(target_pointer_width = "64")]
 fn bindgen_test_layout__OBJECT_ATTRIBUTES() {
     assert_eq!(
         ::std::mem::size_of::<_OBJECT_ATTRIBUTES>(),
@@ -3939,6 +3991,7 @@ pub union _IO_STATUS_BLOCK__bindgen_ty_1 {
     pub Pointer: PVOID,
 }
 #[test]
+#[cfg(target_pointer_width = "64")]
 fn bindgen_test_layout__IO_STATUS_BLOCK__bindgen_ty_1() {
     assert_eq!(
         ::std::mem::size_of::<_IO_STATUS_BLOCK__bindgen_ty_1>(),
@@ -3986,6 +4039,7 @@ fn bindgen_test_layout__IO_STATUS_BLOCK__bindgen_ty_1() {
     test_field_Pointer();
 }
 #[test]
+#[cfg(target_pointer_width = "64")]
 fn bindgen_test_layout__IO_STATUS_BLOCK() {
     assert_eq!(
         ::std::mem::size_of::<_IO_STATUS_BLOCK>(),
@@ -4030,6 +4084,7 @@ pub struct _PROCESS_BASIC_INFORMATION {
     pub Reserved3: PVOID,
 }
 #[test]
+#[cfg(target_pointer_width = "64")]
 fn bindgen_test_layout__PROCESS_BASIC_INFORMATION() {
     assert_eq!(
         ::std::mem::size_of::<_PROCESS_BASIC_INFORMATION>(),
@@ -4139,6 +4194,7 @@ pub struct _SYSTEM_PROCESSOR_PERFORMANCE_INFORMATION {
     pub Reserved2: ULONG,
 }
 #[test]
+#[cfg(target_pointer_width = "64")]
 fn bindgen_test_layout__SYSTEM_PROCESSOR_PERFORMANCE_INFORMATION() {
     assert_eq!(
         ::std::mem::size_of::<_SYSTEM_PROCESSOR_PERFORMANCE_INFORMATION>(),
@@ -4277,6 +4333,7 @@ pub struct _SYSTEM_PROCESS_INFORMATION {
     pub Reserved7: [LARGE_INTEGER; 6usize],
 }
 #[test]
+#[cfg(target_pointer_width = "64")]
 fn bindgen_test_layout__SYSTEM_PROCESS_INFORMATION() {
     assert_eq!(
         ::std::mem::size_of::<_SYSTEM_PROCESS_INFORMATION>(),
@@ -4696,6 +4753,7 @@ pub struct _SYSTEM_THREAD_INFORMATION {
     pub WaitReason: ULONG,
 }
 #[test]
+#[cfg(target_pointer_width = "64")]
 fn bindgen_test_layout__SYSTEM_THREAD_INFORMATION() {
     assert_eq!(
         ::std::mem::size_of::<_SYSTEM_THREAD_INFORMATION>(),
@@ -4871,6 +4929,7 @@ pub struct _SYSTEM_REGISTRY_QUOTA_INFORMATION {
     pub Reserved1: PVOID,
 }
 #[test]
+#[cfg(target_pointer_width = "64")]
 fn bindgen_test_layout__SYSTEM_REGISTRY_QUOTA_INFORMATION() {
     assert_eq!(
         ::std::mem::size_of::<_SYSTEM_REGISTRY_QUOTA_INFORMATION>(),
@@ -4950,6 +5009,7 @@ pub struct _SYSTEM_BASIC_INFORMATION {
     pub NumberOfProcessors: CCHAR,
 }
 #[test]
+#[cfg(target_pointer_width = "64")]
 fn bindgen_test_layout__SYSTEM_BASIC_INFORMATION() {
     assert_eq!(
         ::std::mem::size_of::<_SYSTEM_BASIC_INFORMATION>(),
@@ -5021,6 +5081,7 @@ pub struct _SYSTEM_TIMEOFDAY_INFORMATION {
     pub Reserved1: [BYTE; 48usize],
 }
 #[test]
+#[cfg(target_pointer_width = "64")]
 fn bindgen_test_layout__SYSTEM_TIMEOFDAY_INFORMATION() {
     assert_eq!(
         ::std::mem::size_of::<_SYSTEM_TIMEOFDAY_INFORMATION>(),
@@ -5058,6 +5119,7 @@ pub struct _SYSTEM_PERFORMANCE_INFORMATION {
     pub Reserved1: [BYTE; 312usize],
 }
 #[test]
+#[cfg(target_pointer_width = "64")]
 fn bindgen_test_layout__SYSTEM_PERFORMANCE_INFORMATION() {
     assert_eq!(
         ::std::mem::size_of::<_SYSTEM_PERFORMANCE_INFORMATION>(),
@@ -5095,6 +5157,7 @@ pub struct _SYSTEM_EXCEPTION_INFORMATION {
     pub Reserved1: [BYTE; 16usize],
 }
 #[test]
+#[cfg(target_pointer_width = "64")]
 fn bindgen_test_layout__SYSTEM_EXCEPTION_INFORMATION() {
     assert_eq!(
         ::std::mem::size_of::<_SYSTEM_EXCEPTION_INFORMATION>(),
@@ -5132,6 +5195,7 @@ pub struct _SYSTEM_LOOKASIDE_INFORMATION {
     pub Reserved1: [BYTE; 32usize],
 }
 #[test]
+#[cfg(target_pointer_width = "64")]
 fn bindgen_test_layout__SYSTEM_LOOKASIDE_INFORMATION() {
     assert_eq!(
         ::std::mem::size_of::<_SYSTEM_LOOKASIDE_INFORMATION>(),
@@ -5169,6 +5233,7 @@ pub struct _SYSTEM_INTERRUPT_INFORMATION {
     pub Reserved1: [BYTE; 24usize],
 }
 #[test]
+#[cfg(target_pointer_width = "64")]
 fn bindgen_test_layout__SYSTEM_INTERRUPT_INFORMATION() {
     assert_eq!(
         ::std::mem::size_of::<_SYSTEM_INTERRUPT_INFORMATION>(),
@@ -5207,6 +5272,7 @@ pub struct _SYSTEM_POLICY_INFORMATION {
     pub Reserved2: [ULONG; 3usize],
 }
 #[test]
+#[cfg(target_pointer_width = "64")]
 fn bindgen_test_layout__SYSTEM_POLICY_INFORMATION() {
     assert_eq!(
         ::std::mem::size_of::<_SYSTEM_POLICY_INFORMATION>(),
@@ -5275,6 +5341,7 @@ pub struct _THREAD_NAME_INFORMATION {
     pub ThreadName: UNICODE_STRING,
 }
 #[test]
+#[cfg(target_pointer_width = "64")]
 fn bindgen_test_layout__THREAD_NAME_INFORMATION() {
     assert_eq!(
         ::std::mem::size_of::<_THREAD_NAME_INFORMATION>(),
@@ -5313,6 +5380,7 @@ pub struct _SYSTEM_CODEINTEGRITY_INFORMATION {
     pub CodeIntegrityOptions: ULONG,
 }
 #[test]
+#[cfg(target_pointer_width = "64")]
 fn bindgen_test_layout__SYSTEM_CODEINTEGRITY_INFORMATION() {
     assert_eq!(
         ::std::mem::size_of::<_SYSTEM_CODEINTEGRITY_INFORMATION>(),
@@ -5392,6 +5460,7 @@ pub struct _PUBLIC_OBJECT_BASIC_INFORMATION {
     pub Reserved: [ULONG; 10usize],
 }
 #[test]
+#[cfg(target_pointer_width = "64")]
 fn bindgen_test_layout__PUBLIC_OBJECT_BASIC_INFORMATION() {
     assert_eq!(
         ::std::mem::size_of::<_PUBLIC_OBJECT_BASIC_INFORMATION>(),
@@ -5501,6 +5570,7 @@ pub struct __PUBLIC_OBJECT_TYPE_INFORMATION {
     pub Reserved: [ULONG; 22usize],
 }
 #[test]
+#[cfg(target_pointer_width = "64")]
 fn bindgen_test_layout___PUBLIC_OBJECT_TYPE_INFORMATION() {
     assert_eq!(
         ::std::mem::size_of::<__PUBLIC_OBJECT_TYPE_INFORMATION>(),
@@ -5552,6 +5622,7 @@ fn bindgen_test_layout___PUBLIC_OBJECT_TYPE_INFORMATION() {
 }
 pub type PUBLIC_OBJECT_TYPE_INFORMATION = __PUBLIC_OBJECT_TYPE_INFORMATION;
 pub type PPUBLIC_OBJECT_TYPE_INFORMATION = *mut __PUBLIC_OBJECT_TYPE_INFORMATION;
+#[cfg(windows)]
 extern "C" {
     pub fn NtClose(Handle: HANDLE) -> NTSTATUS;
 }
@@ -5608,6 +5679,7 @@ pub struct _KEY_VALUE_ENTRY {
     pub Type: ULONG,
 }
 #[test]
+#[cfg(target_pointer_width = "64")]
 fn bindgen_test_layout__KEY_VALUE_ENTRY() {
     assert_eq!(
         ::std::mem::size_of::<_KEY_VALUE_ENTRY>(),
@@ -5887,6 +5959,7 @@ pub struct _WINSTATIONINFORMATIONW {
     pub Reserved3: [BYTE; 1140usize],
 }
 #[test]
+#[cfg(target_pointer_width = "64")]
 fn bindgen_test_layout__WINSTATIONINFORMATIONW() {
     assert_eq!(
         ::std::mem::size_of::<_WINSTATIONINFORMATIONW>(),
@@ -5967,12 +6040,14 @@ pub type PWINSTATIONQUERYINFORMATIONW = ::std::option::Option<
 pub struct win_ring {
     pub info: NT_IORING_INFO,
     pub handle: HANDLE,
+    pub sqe_tail: u32,
 }
 #[test]
+#[cfg(target_pointer_width = "64")]
 fn bindgen_test_layout_win_ring() {
     assert_eq!(
         ::std::mem::size_of::<win_ring>(),
-        56usize,
+        64usize,
         concat!("Size of: ", stringify!(win_ring))
     );
     assert_eq!(
@@ -6014,6 +6089,23 @@ fn bindgen_test_layout_win_ring() {
         );
     }
     test_field_handle();
+    fn test_field_sqe_tail() {
+        assert_eq!(
+            unsafe {
+                let uninit = ::std::mem::MaybeUninit::<win_ring>::uninit();
+                let ptr = uninit.as_ptr();
+                ::std::ptr::addr_of!((*ptr).sqe_tail) as usize - ptr as usize
+            },
+            56usize,
+            concat!(
+                "Offset of field: ",
+                stringify!(win_ring),
+                "::",
+                stringify!(sqe_tail)
+            )
+        );
+    }
+    test_field_sqe_tail();
 }
 pub type win_ring_sqe = NT_IORING_SQE;
 pub type win_ring_cqe = NT_IORING_CQE;
@@ -6038,21 +6130,27 @@ pub struct _ACTIVATION_CONTEXT {
 pub struct NET_ADDRESS_INFO_ {
     pub _address: u8,
 }
+#[cfg(windows)]
 extern "C" {
     pub fn win_ring_queue_init(entries: u32, ring: *mut win_ring) -> HRESULT;
 }
+#[cfg(windows)]
 extern "C" {
     pub fn win_ring_queue_init_ref(entries: u32) -> *mut win_ring;
 }
+#[cfg(windows)]
 extern "C" {
     pub fn win_ring_queue_exit(ring: *mut win_ring) -> HRESULT;
 }
+#[cfg(windows)]
 extern "C" {
     pub fn win_ring_query_capabilities(capabilities: *mut win_ring_capabilities) -> HRESULT;
 }
+#[cfg(windows)]
 extern "C" {
     pub fn win_ring_prep_nop(sqe: *mut win_ring_sqe);
 }
+#[cfg(windows)]
 extern "C" {
     pub fn win_ring_prep_read(
         sqe: *mut win_ring_sqe,
@@ -6063,6 +6161,7 @@ extern "C" {
         commonOpFlags: NT_IORING_OP_FLAGS,
     );
 }
+#[cfg(windows)]
 extern "C" {
     pub fn win_ring_prep_register_files(
         sqe: *mut win_ring_sqe,
@@ -6072,6 +6171,7 @@ extern "C" {
         commonOpFlags: NT_IORING_OP_FLAGS,
     );
 }
+#[cfg(windows)]
 extern "C" {
     pub fn win_ring_prep_register_buffers(
         sqe: *mut win_ring_sqe,
@@ -6081,6 +6181,7 @@ extern "C" {
         commonOpFlags: NT_IORING_OP_FLAGS,
     );
 }
+#[cfg(windows)]
 extern "C" {
     pub fn win_ring_prep_cancel(
         sqe: *mut win_ring_sqe,
@@ -6089,6 +6190,7 @@ extern "C" {
         commonOpFlags: NT_IORING_OP_FLAGS,
     );
 }
+#[cfg(windows)]
 extern "C" {
     pub fn win_ring_prep_write(
         sqe: *mut win_ring_sqe,
@@ -6100,6 +6202,7 @@ extern "C" {
         commonOpFlags: NT_IORING_OP_FLAGS,
     );
 }
+#[cfg(windows)]
 extern "C" {
     pub fn win_ring_prep_flush(
         sqe: *mut win_ring_sqe,
@@ -6108,24 +6211,35 @@ extern "C" {
         commonOpFlags: NT_IORING_OP_FLAGS,
     );
 }
+#[cfg(windows)]
 extern "C" {
     pub fn win_ring_sqe_set_flags(sqe: *mut win_ring_sqe, flags: NT_IORING_SQE_FLAGS);
 }
+#[cfg(windows)]
 extern "C" {
     pub fn win_ring_sqe_set_data(sqe: *mut win_ring_sqe, userData: *mut ::std::os::raw::c_void);
 }
+#[cfg(windows)]
 extern "C" {
     pub fn win_ring_sqe_set_data64(sqe: *mut win_ring_sqe, userData: u64);
 }
+#[cfg(windows)]
 extern "C" {
     pub fn win_ring_sq_ready(ring: *const win_ring) -> ::std::os::raw::c_uint;
 }
+#[cfg(windows)]
 extern "C" {
     pub fn win_ring_sq_space_left(ring: *const win_ring) -> ::std::os::raw::c_uint;
 }
+#[cfg(windows)]
 extern "C" {
     pub fn win_ring_get_sqe(ring: *const win_ring) -> *mut win_ring_sqe;
 }
+#[cfg(windows)]
+extern "C" {
+    pub fn win_ring_sq_flush(ring: *mut win_ring) -> ::std::os::raw::c_uint;
+}
+#[cfg(windows)]
 extern "C" {
     pub fn win_ring_submit_and_wait_timeout(
         ring: *mut win_ring,
@@ -6133,42 +6247,55 @@ extern "C" {
         timeout: u64,
     ) -> HRESULT;
 }
+#[cfg(windows)]
 extern "C" {
     pub fn win_ring_submit_and_wait(ring: *mut win_ring, numberOfEntries: u32) -> HRESULT;
 }
+#[cfg(windows)]
 extern "C" {
     pub fn win_ring_cqe_iter(ring: *mut win_ring, head: u32) -> *mut win_ring_cqe;
 }
+#[cfg(windows)]
 extern "C" {
     pub fn win_ring_submit(ring: *mut win_ring) -> HRESULT;
 }
+#[cfg(windows)]
 extern "C" {
     pub fn win_ring_cq_ready(ring: *const win_ring) -> ::std::os::raw::c_uint;
 }
+#[cfg(windows)]
 extern "C" {
     pub fn win_ring_cq_space_left(ring: *const win_ring) -> ::std::os::raw::c_uint;
 }
+#[cfg(windows)]
 extern "C" {
     pub fn win_ring_peek_cqe(ring: *const win_ring) -> *mut win_ring_cqe;
 }
+#[cfg(windows)]
 extern "C" {
     pub fn win_ring_wait_cqe(ring: *mut win_ring) -> *mut win_ring_cqe;
 }
+#[cfg(windows)]
 extern "C" {
     pub fn win_ring_cqe_get_data(cqe: *const win_ring_cqe) -> *mut ::std::os::raw::c_void;
 }
+#[cfg(windows)]
 extern "C" {
     pub fn win_ring_cqe_get_data64(cqe: *const win_ring_cqe) -> u64;
 }
+#[cfg(windows)]
 extern "C" {
     pub fn win_ring_cq_clear(ring: *mut win_ring);
 }
+#[cfg(windows)]
 extern "C" {
     pub fn win_ring_cq_advance(ring: *mut win_ring, count: ::std::os::raw::c_uint);
 }
+#[cfg(windows)]
 extern "C" {
     pub fn win_ring_cqe_seen(ring: *mut win_ring, cqe: *mut win_ring_cqe);
 }
+#[cfg(windows)]
 extern "C" {
     pub fn win_ring_register_event(ring: *mut win_ring, event: HANDLE) -> HRESULT;
 }
@@ -0,0 +1,362 @@
+//! A Rust port of `libwinring.h`, used where the C library and `ntdll` are not available.
+//!
+//! Only the emulated ring ([`IoRing::emulated`](crate::IoRing::emulated)) can actually be used on
+//! such hosts: the ring helpers below work on any ring memory, while the `Nt*` system calls all
+//! fail with `STATUS_NOT_SUPPORTED`.
+//!
+//! The functions are re-exported by [`windows`](crate::windows) in place of its `extern`
+//! declarations, as plain Rust functions: no C symbols are exported under their names.
+
+#![allow(non_snake_case, clippy::missing_safety_doc)]
+
+use std::os::raw::{c_uint, c_void};
+use std::{mem, ptr};
+
+use crate::ring::Indices;
+use crate::windows::{
+    win_ring, win_ring_capabilities, win_ring_cqe, win_ring_sqe, FILE_FLUSH_MODE,
+    FILE_WRITE_FLAGS, HANDLE, HRESULT, IORING_BUFFER_INFO, NTSTATUS, NT_IORING_BUFFERREF,
+    NT_IORING_CREATE_REQUIRED_FLAGS, NT_IORING_HANDLEREF, NT_IORING_INFO_CLASS,
+    NT_IORING_OP_FLAGS, NT_IORING_REG_BUFFERS_FLAGS, NT_IORING_REG_FILES_FLAGS,
+    NT_IORING_SQE_FLAGS, PHANDLE, PNT_IORING_CAPABILITIES, PNT_IORING_INFO, PNT_IORING_STRUCTV1,
+    _IORING_OP_CODE_IORING_OP_CANCEL, _IORING_OP_CODE_IORING_OP_FLUSH,
+    _IORING_OP_CODE_IORING_OP_NOP, _IORING_OP_CODE_IORING_OP_READ,
+    _IORING_OP_CODE_IORING_OP_REGISTER_BUFFERS, _IORING_OP_CODE_IORING_OP_REGISTER_FILES,
+    _IORING_OP_CODE_IORING_OP_WRITE, _IORING_VERSION_IORING_VERSION_3, _NT_IORING_CREATE_FLAGS,
+    _NT_IORING_OP_REGISTER_BUFFERS__bindgen_ty_1, _NT_IORING_OP_REGISTER_FILES__bindgen_ty_1,
+    _NT_IORING_STRUCTV1,
+};
+
+const STATUS_NOT_SUPPORTED: NTSTATUS = 0xC000_00BB_u32 as i32;
+const FACILITY_NT_BIT: i32 = 0x1000_0000;
+const INFINITE: u64 = 0xFFFF_FFFF;
+
+#[allow(non_snake_case)]
+fn HRESULT_FROM_NT(status: NTSTATUS) -> HRESULT {
+    status | FACILITY_NT_BIT
+}
+
+pub unsafe fn NtCreateIoRing(
+    _pIoRingHandle: PHANDLE,
+    _CreateParametersSize: u32,
+    _CreateParameters: PNT_IORING_STRUCTV1,
+    _OutputParametersSize: u32,
+    _pRingInfo: PNT_IORING_INFO,
+) -> NTSTATUS {
+    STATUS_NOT_SUPPORTED
+}
+
+pub unsafe fn NtSubmitIoRing(
+    _Handle: HANDLE,
+    _Flags: NT_IORING_CREATE_REQUIRED_FLAGS,
+    _WaitOperations: u32,
+    _Timeout: *mut u64,
+) -> NTSTATUS {
+    STATUS_NOT_SUPPORTED
+}
+
+pub unsafe fn NtQueryIoRingCapabilities(
+    _CapabilitiesLength: usize,
+    _Capabilities: PNT_IORING_CAPABILITIES,
+) -> NTSTATUS {
+    STATUS_NOT_SUPPORTED
+}
+
+pub unsafe fn NtSetInformationIoRing(
+    _Handle: HANDLE,
+    _InformationClass: NT_IORING_INFO_CLASS,
+    _InformationLength: u32,
+    _IoRingInformation: *mut c_void,
+) -> NTSTATUS {
+    STATUS_NOT_SUPPORTED
+}
+
+pub unsafe fn NtClose(_Handle: HANDLE) -> NTSTATUS {
+    STATUS_NOT_SUPPORTED
+}
+
+fn create_parameters(entries: u32) -> _NT_IORING_STRUCTV1 {
+    _NT_IORING_STRUCTV1 {
+        IoRingVersion: _IORING_VERSION_IORING_VERSION_3,
+        SubmissionQueueSize: entries,
+        CompletionQueueSize: entries * 2,
+        Flags: _NT_IORING_CREATE_FLAGS {
+            Required: 0,
+            Advisory: 0,
+        },
+    }
+}
+
+pub unsafe fn win_ring_queue_init(entries: u32, ring: *mut win_ring) -> HRESULT {
+    let mut parameters = create_parameters(entries);
+    let status = NtCreateIoRing(
+        &mut (*ring).handle,
+        mem::size_of_val(&parameters) as u32,
+        &mut parameters,
+        mem::size_of_val(&(*ring).info) as u32,
+        &mut (*ring).info,
+    );
+    HRESULT_FROM_NT(status)
+}
+
+pub unsafe fn win_ring_queue_init_ref(entries: u32) -> *mut win_ring {
+    let ring = Box::into_raw(Box::new(mem::zeroed::<win_ring>()));
+    win_ring_queue_init(entries, ring);
+    ring
+}
+
+pub unsafe fn win_ring_queue_exit(ring: *mut win_ring) -> HRESULT {
+    HRESULT_FROM_NT(NtClose((*ring).handle))
+}
+
+pub unsafe fn win_ring_query_capabilities(capabilities: *mut win_ring_capabilities) -> HRESULT {
+    HRESULT_FROM_NT(NtQueryIoRingCapabilities(
+        mem::size_of::<win_ring_capabilities>(),
+        capabilities,
+    ))
+}
+
+/// Zero `sqe` and set its opcode, like every `win_ring_prep_*` does. Unlike the C version, a null
+/// `sqe` (a full submission queue) is left alone.
+unsafe fn prep<'a>(sqe: *mut win_ring_sqe, op_code: i32) -> Option<&'a mut win_ring_sqe> {
+    let sqe = sqe.as_mut()?;
+    ptr::write_bytes(sqe, 0, 1);
+    sqe.OpCode = op_code;
+    Some(sqe)
+}
+
+pub unsafe fn win_ring_prep_nop(sqe: *mut win_ring_sqe) {
+    prep(sqe, _IORING_OP_CODE_IORING_OP_NOP);
+}
+
+pub unsafe fn win_ring_prep_read(
+    sqe: *mut win_ring_sqe,
+    file: NT_IORING_HANDLEREF,
+    buffer: NT_IORING_BUFFERREF,
+    sizeToRead: u32,
+    fileOffset: u64,
+    commonOpFlags: NT_IORING_OP_FLAGS,
+) {
+    if let Some(sqe) = prep(sqe, _IORING_OP_CODE_IORING_OP_READ) {
+        let read = &mut sqe.__bindgen_anon_1.Read;
+        read.CommonOpFlags = commonOpFlags;
+        read.File = file;
+        read.Buffer = buffer;
+        read.Offset = fileOffset;
+        read.Length = sizeToRead;
+    }
+}
+
+pub unsafe fn win_ring_prep_register_files(
+    sqe: *mut win_ring_sqe,
+    handles: *const HANDLE,
+    count: c_uint,
+    flags: NT_IORING_REG_FILES_FLAGS,
+    commonOpFlags: NT_IORING_OP_FLAGS,
+) {
+    if let Some(sqe) = prep(sqe, _IORING_OP_CODE_IORING_OP_REGISTER_FILES) {
+        let register = &mut sqe.__bindgen_anon_1.RegisterFiles;
+        register.CommonOpFlags = commonOpFlags;
+        register.Flags = flags;
+        register.Count = count;
+        register.__bindgen_anon_1 = _NT_IORING_OP_REGISTER_FILES__bindgen_ty_1 { Handles: handles };
+    }
+}
+
+pub unsafe fn win_ring_prep_register_buffers(
+    sqe: *mut win_ring_sqe,
+    buffers: *const IORING_BUFFER_INFO,
+    count: c_uint,
+    flags: NT_IORING_REG_BUFFERS_FLAGS,
+    commonOpFlags: NT_IORING_OP_FLAGS,
+) {
+    if let Some(sqe) = prep(sqe, _IORING_OP_CODE_IORING_OP_REGISTER_BUFFERS) {
+        let register = &mut sqe.__bindgen_anon_1.RegisterBuffers;
+        register.CommonOpFlags = commonOpFlags;
+        register.Flags = flags;
+        register.Count = count;
+        register.__bindgen_anon_1 =
+            _NT_IORING_OP_REGISTER_BUFFERS__bindgen_ty_1 { Buffers: buffers };
+    }
+}
+
+pub unsafe fn win_ring_prep_cancel(
+    sqe: *mut win_ring_sqe,
+    file: NT_IORING_HANDLEREF,
+    cancelId: u64,
+    commonOpFlags: NT_IORING_OP_FLAGS,
+) {
+    if let Some(sqe) = prep(sqe, _IORING_OP_CODE_IORING_OP_CANCEL) {
+        let cancel = &mut sqe.__bindgen_anon_1.Cancel;
+        cancel.CommonOpFlags = commonOpFlags;
+        cancel.File = file;
+        cancel.CancelId = cancelId;
+    }
+}
+
+pub unsafe fn win_ring_prep_write(
+    sqe: *mut win_ring_sqe,
+    file: NT_IORING_HANDLEREF,
+    buffer: NT_IORING_BUFFERREF,
+    sizeToWrite: u32,
+    fileOffset: u64,
+    flags: FILE_WRITE_FLAGS,
+    commonOpFlags: NT_IORING_OP_FLAGS,
+) {
+    if let Some(sqe) = prep(sqe, _IORING_OP_CODE_IORING_OP_WRITE) {
+        let write = &mut sqe.__bindgen_anon_1.Write;
+        write.CommonOpFlags = commonOpFlags;
+        write.Flags = flags;
+        write.File = file;
+        write.Buffer = buffer;
+        write.Offset = fileOffset;
+        write.Length = sizeToWrite;
+    }
+}
+
+pub unsafe fn win_ring_prep_flush(
+    sqe: *mut win_ring_sqe,
+    file: NT_IORING_HANDLEREF,
+    flushMode: FILE_FLUSH_MODE,
+    commonOpFlags: NT_IORING_OP_FLAGS,
+) {
+    if let Some(sqe) = prep(sqe, _IORING_OP_CODE_IORING_OP_FLUSH) {
+        let flush = &mut sqe.__bindgen_anon_1.Flush;
+        flush.CommonOpFlags = commonOpFlags;
+        flush.FlushMode = flushMode;
+        flush.File = file;
+    }
+}
+
+pub unsafe fn win_ring_sqe_set_flags(sqe: *mut win_ring_sqe, flags: NT_IORING_SQE_FLAGS) {
+    (*sqe).Flags = flags;
+}
+
+pub unsafe fn win_ring_sqe_set_data(sqe: *mut win_ring_sqe, userData: *mut c_void) {
+    (*sqe).UserData = userData as u64;
+}
+
+pub unsafe fn win_ring_sqe_set_data64(sqe: *mut win_ring_sqe, userData: u64) {
+    (*sqe).UserData = userData;
+}
+
+pub unsafe fn win_ring_sq_ready(ring: *const win_ring) -> c_uint {
+    Indices::submission(ring).len()
+}
+
+pub unsafe fn win_ring_sq_space_left(ring: *const win_ring) -> c_uint {
+    let reserved = (*ring).sqe_tail;
+    (*ring).info.SubmissionQueueSize - reserved.wrapping_sub(Indices::submission(ring).head())
+}
+
+pub unsafe fn win_ring_get_sqe(ring: *const win_ring) -> *mut win_ring_sqe {
+    let reserved = &mut (*ring.cast_mut()).sqe_tail;
+    match Indices::submission(ring).reserve(reserved) {
+        Some(slot) => {
+            let sq = (*ring).info.__bindgen_anon_1.SubmissionQueue;
+            (*sq).Entries.as_mut_ptr().add(slot)
+        }
+        None => ptr::null_mut(),
+    }
+}
+
+pub unsafe fn win_ring_sq_flush(ring: *mut win_ring) -> c_uint {
+    Indices::submission(ring).publish((*ring).sqe_tail);
+    win_ring_sq_ready(ring)
+}
+
+pub unsafe fn win_ring_submit_and_wait_timeout(
+    ring: *mut win_ring,
+    numberOfEntries: u32,
+    mut timeout: u64,
+) -> HRESULT {
+    win_ring_sq_flush(ring);
+    let status = NtSubmitIoRing(
+        (*ring).handle,
+        0,
+        numberOfEntries,
+        if numberOfEntries == 0 || timeout == INFINITE {
+            ptr::null_mut()
+        } else {
+            &mut timeout
+        },
+    );
+    HRESULT_FROM_NT(status)
+}
+
+pub unsafe fn win_ring_submit_and_wait(ring: *mut win_ring, numberOfEntries: u32) -> HRESULT {
+    win_ring_submit_and_wait_timeout(ring, numberOfEntries, INFINITE)
+}
+
+pub unsafe fn win_ring_submit(ring: *mut win_ring) -> HRESULT {
+    win_ring_submit_and_wait_timeout(ring, 0, 0)
+}
+
+pub unsafe fn win_ring_cq_ready(ring: *const win_ring) -> c_uint {
+    Indices::completion(ring).len()
+}
+
+pub unsafe fn win_ring_cq_space_left(ring: *const win_ring) -> c_uint {
+    (*ring).info.CompletionQueueSize - win_ring_cq_ready(ring)
+}
+
+pub unsafe fn win_ring_cqe_iter(ring: *mut win_ring, head: u32) -> *mut win_ring_cqe {
+    let cq = (*ring).info.__bindgen_anon_2.CompletionQueue;
+    (*cq)
+        .Entries
+        .as_mut_ptr()
+        .add(Indices::completion(ring).slot(head))
+}
+
+pub unsafe fn win_ring_peek_cqe(ring: *const win_ring) -> *mut win_ring_cqe {
+    if win_ring_cq_ready(ring) == 0 {
+        return ptr::null_mut();
+    }
+    let head = Indices::completion(ring).head();
+    win_ring_cqe_iter(ring as *mut _, head)
+}
+
+pub unsafe fn win_ring_wait_cqe(ring: *mut win_ring) -> *mut win_ring_cqe {
+    let cqe = win_ring_peek_cqe(ring);
+    if !cqe.is_null() {
+        return cqe;
+    }
+    if win_ring_submit_and_wait(ring, 1) < 0 {
+        return ptr::null_mut();
+    }
+    win_ring_peek_cqe(ring)
+}
+
+pub unsafe fn win_ring_cqe_get_data(cqe: *const win_ring_cqe) -> *mut c_void {
+    (*cqe).UserData as *mut c_void
+}
+
+pub unsafe fn win_ring_cqe_get_data64(cqe: *const win_ring_cqe) -> u64 {
+    (*cqe).UserData
+}
+
+pub unsafe fn win_ring_cq_clear(ring: *mut win_ring) {
+    let indices = Indices::completion(ring);
+    indices.release(indices.tail());
+}
+
+pub unsafe fn win_ring_cq_advance(ring: *mut win_ring, count: c_uint) {
+    let indices = Indices::completion(ring);
+    indices.release(indices.head().wrapping_add(count));
+}
+
+pub unsafe fn win_ring_cqe_seen(ring: *mut win_ring, cqe: *mut win_ring_cqe) {
+    if !cqe.is_null() {
+        win_ring_cq_advance(ring, 1);
+    }
+}
+
+pub unsafe fn win_ring_register_event(ring: *mut win_ring, mut event: HANDLE) -> HRESULT {
+    let status = NtSetInformationIoRing(
+        (*ring).handle,
+        crate::windows::_NT_IORING_INFO_CLASS_IoRingRegisterUserCompletionEventClass,
+        mem::size_of::<HANDLE>() as u32,
+        &mut event as *mut HANDLE as *mut c_void,
+    );
+    HRESULT_FROM_NT(status)
+}